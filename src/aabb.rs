@@ -0,0 +1,116 @@
+use crate::{interval::Interval, ray::Ray, vec3::Point3};
+
+#[derive(Copy, Clone)]
+pub struct Aabb {
+  pub x: Interval,
+  pub y: Interval,
+  pub z: Interval,
+}
+
+impl Aabb {
+  pub const EMPTY: Aabb = Aabb {
+    x: Interval::EMPTY,
+    y: Interval::EMPTY,
+    z: Interval::EMPTY,
+  };
+
+  pub const UNIVERSE: Aabb = Aabb {
+    x: Interval::UNIVERSE,
+    y: Interval::UNIVERSE,
+    z: Interval::UNIVERSE,
+  };
+
+  pub fn new(x: Interval, y: Interval, z: Interval) -> Aabb {
+    Aabb { x, y, z }.pad_to_minimums()
+  }
+
+  // Treat the two points a and b as extrema for the bounding box
+  pub fn new_from_points(a: &Point3, b: &Point3) -> Aabb {
+    Aabb::new(
+      Interval::new_from_range(a.x.min(b.x), a.x.max(b.x)),
+      Interval::new_from_range(a.y.min(b.y), a.y.max(b.y)),
+      Interval::new_from_range(a.z.min(b.z), a.z.max(b.z)),
+    )
+  }
+
+  // Create the box tightly enclosing the two input boxes
+  pub fn new_from_boxes(box0: &Aabb, box1: &Aabb) -> Aabb {
+    Aabb {
+      x: Interval::new_from_intervals(&box0.x, &box1.x),
+      y: Interval::new_from_intervals(&box0.y, &box1.y),
+      z: Interval::new_from_intervals(&box0.z, &box1.z),
+    }
+  }
+
+  pub fn axis_interval(&self, n: usize) -> &Interval {
+    match n {
+      1 => &self.y,
+      2 => &self.z,
+      _ => &self.x,
+    }
+  }
+
+  pub fn hit(&self, ray: &Ray, ray_t: &Interval) -> bool {
+    let mut t_min = ray_t.min;
+    let mut t_max = ray_t.max;
+
+    for axis in 0..3 {
+      let ax = self.axis_interval(axis);
+      let (origin, direction) = match axis {
+        0 => (ray.origin.x, ray.direction.x),
+        1 => (ray.origin.y, ray.direction.y),
+        _ => (ray.origin.z, ray.direction.z),
+      };
+      let adinv = 1.0 / direction;
+
+      let t0 = (ax.min - origin) * adinv;
+      let t1 = (ax.max - origin) * adinv;
+      let (t0, t1) = if t0 < t1 { (t0, t1) } else { (t1, t0) };
+
+      if t0 > t_min { t_min = t0; }
+      if t1 < t_max { t_max = t1; }
+
+      if t_max <= t_min {
+        return false;
+      }
+    }
+    true
+  }
+
+  // Returns the index of the longest axis of the bounding box
+  pub fn longest_axis(&self) -> usize {
+    if self.x.size() > self.y.size() {
+      if self.x.size() > self.z.size() { 0 } else { 2 }
+    } else if self.y.size() > self.z.size() { 1 } else { 2 }
+  }
+
+  pub fn centroid(&self) -> Point3 {
+    Point3::new(
+      (self.x.min + self.x.max) * 0.5,
+      (self.y.min + self.y.max) * 0.5,
+      (self.z.min + self.z.max) * 0.5,
+    )
+  }
+
+  // Whether every side of the box is finite, which is not the case for infinite planes
+  pub fn is_bounded(&self) -> bool {
+    [self.x, self.y, self.z].iter().all(|axis| axis.min.is_finite() && axis.max.is_finite())
+  }
+
+  pub fn surface_area(&self) -> f64 {
+    let dx = self.x.size().max(0.0);
+    let dy = self.y.size().max(0.0);
+    let dz = self.z.size().max(0.0);
+    2.0 * (dx * dy + dy * dz + dz * dx)
+  }
+
+  // Adjust the box so that no side is narrower than some delta, padding if necessary
+  fn pad_to_minimums(self) -> Aabb {
+    let delta = 0.0001;
+    Aabb {
+      x: if self.x.size() < delta { self.x.expand(delta) } else { self.x },
+      y: if self.y.size() < delta { self.y.expand(delta) } else { self.y },
+      z: if self.z.size() < delta { self.z.expand(delta) } else { self.z },
+    }
+  }
+}
\ No newline at end of file
@@ -0,0 +1,127 @@
+use std::sync::Arc;
+
+use crate::{color::Color, common::Common, material::{HenyeyGreenstein, Isotropic, Material}, ray::Ray};
+
+// Medium filling the whole scene, such as haze or fog, whose density may fall off exponentially with
+// height. Without a falloff it is uniform everywhere, so rays only reach the background by chance
+pub struct Atmosphere {
+  // Extinction coefficient at the base height, per unit of distance
+  extinction: f64,
+  height_falloff: f64,
+  base_height: f64,
+  phase_function: Arc<dyn Material>,
+}
+
+impl Atmosphere {
+  // Medium scattering equally in all directions, with the scattering coefficient being extinction * albedo
+  pub fn new(extinction: f64, albedo: Color) -> Atmosphere {
+    Atmosphere::new_with_phase(extinction, Arc::new(Isotropic::new(albedo)))
+  }
+
+  // Medium scattering mostly forwards (g > 0) or backwards (g < 0)
+  pub fn new_anisotropic(extinction: f64, albedo: Color, g: f64) -> Atmosphere {
+    Atmosphere::new_with_phase(extinction, Arc::new(HenyeyGreenstein::new(albedo, g)))
+  }
+
+  pub fn new_with_phase(extinction: f64, phase_function: Arc<dyn Material>) -> Atmosphere {
+    Atmosphere {
+      extinction,
+      height_falloff: 0.0,
+      base_height: 0.0,
+      phase_function,
+    }
+  }
+
+  // Let the density decay by a factor of e every 1 / falloff units above base_height
+  pub fn set_height_falloff(&mut self, falloff: f64, base_height: f64) {
+    self.height_falloff = falloff.max(0.0);
+    self.base_height = base_height;
+  }
+
+  pub fn phase_function(&self) -> &dyn Material {
+    self.phase_function.as_ref()
+  }
+
+  // Sample the distance along the ray at which it scatters for a number u in [0, 1), returning None if
+  // it passes t_max first. The optical depth has a closed form for exponential height fog, so it is
+  // inverted directly
+  pub fn sample_distance(&self, ray: &Ray, t_max: f64, u: f64) -> Option<f64> {
+    if self.extinction <= 0.0 {
+      return None;
+    }
+
+    let length = ray.direction.length();
+    let k = self.height_falloff * ray.direction.y / length;
+    let density = self.extinction * (-self.height_falloff * (ray.origin.y - self.base_height)).exp();
+    let optical_depth = -(1.0 - u).ln();
+
+    let distance = if k.abs() < 1e-9 {
+      optical_depth / density
+    } else {
+      // A ray going up sees only a finite optical depth and may escape the medium entirely
+      let x = 1.0 - optical_depth * k / density;
+      if x <= 0.0 {
+        return None;
+      }
+      -x.ln() / k
+    };
+
+    let t = distance / length;
+    if t < t_max { Some(t) } else { None }
+  }
+
+  // Fraction of the light passing along the ray up to t_max without being scattered or absorbed
+  pub fn transmittance(&self, ray: &Ray, t_max: f64) -> f64 {
+    if self.extinction <= 0.0 {
+      return 1.0;
+    }
+
+    let length = ray.direction.length();
+    let k = self.height_falloff * ray.direction.y / length;
+    let density = self.extinction * (-self.height_falloff * (ray.origin.y - self.base_height)).exp();
+    let distance = t_max * length;
+
+    let optical_depth = if k.abs() < 1e-9 {
+      density * distance
+    } else if distance.is_infinite() {
+      if k > 0.0 { density / k } else { Common::INFINITY }
+    } else {
+      density * (1.0 - (-k * distance).exp()) / k
+    };
+    (-optical_depth).exp()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::vec3::{Point3, Vec3};
+
+  #[test]
+  fn rays_escape_as_often_as_the_transmittance() {
+    let mut fog = Atmosphere::new(0.4, Color::new(1.0, 1.0, 1.0));
+    fog.set_height_falloff(0.5, 1.0);
+    let uniform = Atmosphere::new(0.4, Color::new(1.0, 1.0, 1.0));
+
+    let origin = Point3::new(0.0, 2.0, 0.0);
+    let rays = [
+      (Ray::new(origin, Vec3::new(0.3, 1.0, 0.0)), 3.0),
+      (Ray::new(origin, Vec3::new(0.0, 2.0, 1.0)), Common::INFINITY),
+      (Ray::new(origin, Vec3::new(1.0, -1.0, 0.5)), 2.5),
+      (Ray::new(origin, Vec3::new(0.0, -0.5, 0.0)), 10.0),
+      (Ray::new(origin, Vec3::new(1.0, 0.0, 0.0)), 4.0),
+    ];
+
+    // Numbers evenly spread over [0, 1), so the fraction is exact up to their spacing
+    let n = 100_000;
+    for atmosphere in [&fog, &uniform] {
+      for (ray, t_max) in &rays {
+        let passed = (0..n)
+          .filter(|i| atmosphere.sample_distance(ray, *t_max, (*i as f64 + 0.5) / n as f64).is_none())
+          .count();
+        let (fraction, expected) = (passed as f64 / n as f64, atmosphere.transmittance(ray, *t_max));
+        assert!((fraction - expected).abs() < 1e-4, "{} passed, expected {}", fraction, expected);
+      }
+    }
+  }
+}
\ No newline at end of file
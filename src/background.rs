@@ -0,0 +1,45 @@
+use std::sync::Arc;
+
+use crate::{color::Color, environment::Environment, ray::Ray};
+
+// Radiance seen by rays that escape the scene without hitting anything
+pub enum Background {
+  // No light comes from the environment, so the scene is lit only by its emitters
+  None,
+  Solid(Color),
+  // Linear blend between two colors based on the height of the ray direction
+  Gradient { bottom: Color, top: Color },
+  // Image or analytic environment, which is also sampled as a light
+  Environment(Arc<dyn Environment>),
+}
+
+impl Background {
+  pub fn value(&self, ray: &Ray) -> Color {
+    match self {
+      Background::None => Color::new(0.0, 0.0, 0.0),
+      Background::Solid(color) => *color,
+      Background::Gradient { bottom, top } => {
+        let unit_direction = ray.direction.unit_vector();
+        let a = 0.5 * (unit_direction.y + 1.0);
+        *bottom * (1.0 - a) + *top * a
+      }
+      Background::Environment(environment) => environment.value(&ray.direction),
+    }
+  }
+
+  pub fn environment(&self) -> Option<&dyn Environment> {
+    match self {
+      Background::Environment(environment) => Some(environment.as_ref()),
+      _ => None,
+    }
+  }
+}
+
+impl Default for Background {
+  fn default() -> Self {
+    Background::Gradient {
+      bottom: Color::new(1.0, 1.0, 1.0),
+      top: Color::new(0.5, 0.7, 1.0),
+    }
+  }
+}
\ No newline at end of file
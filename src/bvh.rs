@@ -0,0 +1,268 @@
+use crate::{aabb::Aabb, hittable::{HitRecord, Hittable}, hittable_list::HittableList, interval::Interval, ray::Ray};
+
+// Number of buckets the centroids are sorted into when evaluating SAH splits
+const SAH_BUCKETS: usize = 16;
+// Nodes with at most this many objects may become a leaf if splitting does not pay off
+const MAX_LEAF_OBJECTS: usize = 4;
+// Cost of traversing a node relative to intersecting a single object
+const TRAVERSAL_COST: f64 = 0.125;
+
+pub struct BvhNode {
+  left: Box<dyn Hittable>,
+  right: Option<Box<dyn Hittable>>,
+  bbox: Aabb,
+}
+
+impl BvhNode {
+  pub fn new(list: HittableList) -> BvhNode {
+    // Unbounded objects such as infinite planes would swallow every split, so they are kept out
+    // of the hierarchy and tested next to it
+    let (bounded, unbounded): (Vec<_>, Vec<_>) = list.objects.into_iter()
+      .partition(|object| object.bounding_box().is_bounded());
+    if unbounded.is_empty() {
+      return BvhNode::from_objects(bounded);
+    }
+
+    BvhNode {
+      left: Box::new(BvhNode::from_objects(bounded)),
+      right: Some(Box::new(HittableList { objects: unbounded })),
+      bbox: Aabb::UNIVERSE,
+    }
+  }
+
+  fn from_objects(mut objects: Vec<Box<dyn Hittable>>) -> BvhNode {
+    let bbox = objects.iter()
+      .fold(Aabb::EMPTY, |bbox, object| Aabb::new_from_boxes(&bbox, &object.bounding_box()));
+
+    if objects.len() <= 1 {
+      let left = objects.pop().unwrap_or_else(|| Box::new(HittableList { objects: vec![] }));
+      return BvhNode { left, right: None, bbox };
+    }
+    if objects.len() == 2 {
+      let right = objects.pop();
+      let left = objects.pop().unwrap();
+      return BvhNode { left, right, bbox };
+    }
+
+    let (left_objects, right_objects) = match BvhNode::find_split(&objects, &bbox) {
+      Split::Leaf => {
+        let left = Box::new(HittableList { objects });
+        return BvhNode { left, right: None, bbox };
+      }
+      Split::Buckets(axis, centroid_bounds, bucket) => {
+        objects.into_iter().partition(|object| {
+          BvhNode::bucket_index(object.as_ref(), axis, &centroid_bounds) < bucket
+        })
+      }
+      Split::Median(axis) => {
+        objects.sort_by(|a, b| {
+          let ca = BvhNode::axis_centroid(a.as_ref(), axis);
+          let cb = BvhNode::axis_centroid(b.as_ref(), axis);
+          ca.total_cmp(&cb)
+        });
+        let right_objects = objects.split_off(objects.len() / 2);
+        (objects, right_objects)
+      }
+    };
+
+    BvhNode {
+      left: Box::new(BvhNode::from_objects(left_objects)),
+      right: Some(Box::new(BvhNode::from_objects(right_objects))),
+      bbox,
+    }
+  }
+
+  // Pick the bucketed split with the lowest surface area heuristic cost
+  fn find_split(objects: &[Box<dyn Hittable>], bbox: &Aabb) -> Split {
+    let centroid_bounds = objects.iter().fold(Aabb::EMPTY, |bounds, object| {
+      let c = object.bounding_box().centroid();
+      Aabb::new_from_boxes(&bounds, &Aabb { x: Interval::new_from_range(c.x, c.x),
+        y: Interval::new_from_range(c.y, c.y), z: Interval::new_from_range(c.z, c.z) })
+    });
+
+    let axis = centroid_bounds.longest_axis();
+    if centroid_bounds.axis_interval(axis).size() <= 0.0 {
+      // All centroids coincide, so the buckets can't separate them
+      return if objects.len() <= MAX_LEAF_OBJECTS { Split::Leaf } else { Split::Median(axis) };
+    }
+
+    let mut best: Option<(usize, usize, f64)> = None;
+    for axis in 0..3 {
+      if centroid_bounds.axis_interval(axis).size() <= 0.0 {
+        continue;
+      }
+
+      let mut counts = [0usize; SAH_BUCKETS];
+      let mut bounds = [Aabb::EMPTY; SAH_BUCKETS];
+      for object in objects {
+        let b = BvhNode::bucket_index(object.as_ref(), axis, &centroid_bounds);
+        counts[b] += 1;
+        bounds[b] = Aabb::new_from_boxes(&bounds[b], &object.bounding_box());
+      }
+
+      // Sweep from the right so that every split's right-hand side is known up front
+      let mut right_areas = [0.0; SAH_BUCKETS];
+      let mut right_counts = [0usize; SAH_BUCKETS];
+      let mut right_box = Aabb::EMPTY;
+      let mut right_count = 0;
+      for b in (1..SAH_BUCKETS).rev() {
+        right_box = Aabb::new_from_boxes(&right_box, &bounds[b]);
+        right_count += counts[b];
+        right_areas[b] = right_box.surface_area();
+        right_counts[b] = right_count;
+      }
+
+      let mut left_box = Aabb::EMPTY;
+      let mut left_count = 0;
+      for b in 1..SAH_BUCKETS {
+        left_box = Aabb::new_from_boxes(&left_box, &bounds[b - 1]);
+        left_count += counts[b - 1];
+        if left_count == 0 || right_counts[b] == 0 {
+          continue;
+        }
+        let cost = left_box.surface_area() * left_count as f64 + right_areas[b] * right_counts[b] as f64;
+        if best.is_none_or(|(_, _, best_cost)| cost < best_cost) {
+          best = Some((axis, b, cost));
+        }
+      }
+    }
+
+    let parent_area = bbox.surface_area();
+    match best {
+      Some((axis, bucket, cost)) => {
+        let split_cost = TRAVERSAL_COST + cost / parent_area;
+        if objects.len() <= MAX_LEAF_OBJECTS && objects.len() as f64 <= split_cost {
+          Split::Leaf
+        } else {
+          Split::Buckets(axis, centroid_bounds, bucket)
+        }
+      }
+      None => Split::Median(axis),
+    }
+  }
+
+  fn axis_centroid(object: &dyn Hittable, axis: usize) -> f64 {
+    let interval = *object.bounding_box().axis_interval(axis);
+    (interval.min + interval.max) * 0.5
+  }
+
+  fn bucket_index(object: &dyn Hittable, axis: usize, centroid_bounds: &Aabb) -> usize {
+    let bounds = centroid_bounds.axis_interval(axis);
+    let offset = (BvhNode::axis_centroid(object, axis) - bounds.min) / bounds.size();
+    ((offset * SAH_BUCKETS as f64) as usize).min(SAH_BUCKETS - 1)
+  }
+}
+
+enum Split {
+  Leaf,
+  Buckets(usize, Aabb, usize),
+  Median(usize),
+}
+
+impl Hittable for BvhNode {
+  fn hit(&self, ray: &Ray, ray_t: &Interval) -> (bool, Option<HitRecord<'_>>) {
+    if !self.bbox.hit(ray, ray_t) {
+      return (false, None);
+    }
+
+    let (hit_left, rec_left) = self.left.hit(ray, ray_t);
+    let right = match &self.right {
+      Some(right) => right,
+      None => return (hit_left, rec_left),
+    };
+
+    let max = if hit_left { rec_left.as_ref().unwrap().t } else { ray_t.max };
+    let (hit_right, rec_right) = right.hit(ray, &Interval::new_from_range(ray_t.min, max));
+
+    if hit_right {
+      return (true, rec_right);
+    }
+    (hit_left, rec_left)
+  }
+
+  fn bounding_box(&self) -> Aabb {
+    self.bbox
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::sync::Arc;
+
+  use rand::{rngs::SmallRng, Rng, SeedableRng};
+
+  use super::*;
+  use crate::{material::Lambertian, plane::Plane, sphere::Sphere, vec3::{Point3, Vec3}};
+
+  enum Shape {
+    Sphere(Point3, f64),
+    Plane(Point3, Vec3),
+  }
+
+  fn build(shapes: &[Shape]) -> HittableList {
+    let material = Arc::new(Lambertian::new(Vec3::new(0.5, 0.5, 0.5)));
+    let objects = shapes.iter().map(|shape| -> Box<dyn Hittable> {
+      match *shape {
+        Shape::Sphere(center, radius) => Box::new(Sphere::new(center, radius, material.clone())),
+        Shape::Plane(point, normal) => Box::new(Plane::new(point, normal, material.clone())),
+      }
+    }).collect();
+    HittableList { objects }
+  }
+
+  fn random_point(rng: &mut SmallRng, extent: f64) -> Point3 {
+    Point3::new(rng.gen_range(-extent..extent), rng.gen_range(-extent..extent), rng.gen_range(-extent..extent))
+  }
+
+  // Check that the hierarchy finds the same nearest hit as testing every object in turn
+  fn assert_matches_list(shapes: &[Shape]) {
+    let list = build(shapes);
+    let bvh = BvhNode::new(build(shapes));
+    let mut rng = SmallRng::seed_from_u64(11);
+    let interval = Interval::new_from_range(0.001, f64::INFINITY);
+    for _ in 0..2000 {
+      let ray = Ray::new(random_point(&mut rng, 12.0), random_point(&mut rng, 1.0));
+      let (hit_list, rec_list) = list.hit(&ray, &interval);
+      let (hit_bvh, rec_bvh) = bvh.hit(&ray, &interval);
+      assert_eq!(hit_list, hit_bvh);
+      if hit_list {
+        let (a, b) = (rec_list.unwrap(), rec_bvh.unwrap());
+        assert_eq!(a.t, b.t);
+        assert!((a.p - b.p).length() < 1e-12);
+      }
+    }
+  }
+
+  #[test]
+  fn nearest_hit_matches_a_list() {
+    let mut rng = SmallRng::seed_from_u64(5);
+    let mut random_spheres = |count: usize| -> Vec<Shape> {
+      (0..count).map(|_| Shape::Sphere(random_point(&mut rng, 8.0), rng.gen_range(0.1..1.5))).collect()
+    };
+    for count in [0, 1, 2, 3, 5, 200] {
+      assert_matches_list(&random_spheres(count));
+    }
+  }
+
+  #[test]
+  fn coinciding_centroids_match_a_list() {
+    let shapes: Vec<Shape> = (1..=20).map(|i| Shape::Sphere(Point3::new(1.0, -2.0, 0.5), i as f64 * 0.3)).collect();
+    assert_matches_list(&shapes);
+  }
+
+  #[test]
+  fn unbounded_planes_are_kept_out_of_the_hierarchy() {
+    let mut rng = SmallRng::seed_from_u64(9);
+    let mut shapes: Vec<Shape> = (0..50).map(|_| Shape::Sphere(random_point(&mut rng, 8.0), rng.gen_range(0.1..1.5))).collect();
+    shapes.push(Shape::Plane(Point3::new(0.0, -3.0, 0.0), Vec3::new(0.0, 1.0, 0.0)));
+    shapes.push(Shape::Plane(Point3::new(4.0, 0.0, 0.0), Vec3::new(-1.0, 0.2, 0.0)));
+    assert_matches_list(&shapes);
+
+    let bvh = BvhNode::new(build(&shapes));
+    assert!(bvh.left.bounding_box().is_bounded());
+    assert_eq!(bvh.left.bounding_box().x.max, BvhNode::new(build(&shapes[..50])).bounding_box().x.max);
+
+    // Planes alone leave an empty hierarchy next to them
+    assert_matches_list(&shapes[50..]);
+  }
+}
\ No newline at end of file
@@ -1,8 +1,8 @@
-use std::fs;
+use std::{io::{self, Write}, sync::{atomic::{AtomicUsize, Ordering}, mpsc}, thread};
 
-use crate::{color::Color, common::Common, hittable::Hittable, interval::Interval, ray::Ray, vec3::{Point3, Vec3}};
+use crate::{atmosphere::Atmosphere, background::Background, color::Color, common::Common, film::Film, filter::{BoxFilter, Filter}, hittable::{HitRecord, Hittable}, image::Image, interval::Interval, light::Lights, material::ScatterRecord, pdf::{self, EnvironmentPdf, HittablePdf, MixturePdf, Pdf}, ray::Ray, sampler::{IndependentSampler, PixelSample, PixelSampler, Sampler}, spectrum::{self, Wavelengths}, vec3::{Point3, Vec3}};
 
-pub struct Camera<'a> {
+pub struct Camera {
   aspect_ratio: f64,
   image_width: i64,
   samples_per_pixel: i64,
@@ -13,11 +13,20 @@ pub struct Camera<'a> {
   vup: Vec3,
   defocus_angle: f64,
   focus_dist: f64,
-  filename: &'a str,
+  threads: usize,
+  tile_size: i64,
+  seed: Option<u64>,
+  background: Background,
+  shutter: Interval,
+  atmosphere: Option<Atmosphere>,
+  spectral: bool,
+  sampler: Box<dyn Sampler>,
+  filter: Box<dyn Filter>,
 }
 
 struct CameraComputedSettings {
   image_height: i64,
+  seed: u64,
   center: Point3,
   pixel00_loc: Point3,
   pixel_delta_u: Vec3,
@@ -26,10 +35,21 @@ struct CameraComputedSettings {
   defocus_dist_v: Vec3,
 }
 
-impl Camera<'_> {
-  pub fn new<'a>(aspect_ratio: f64, image_width: i64, samples_per_pixel: i64,
+// Names of the beauty pass and the arbitrary output variables rendered alongside it
+const PASS_NAMES: [&str; 4] = ["", "albedo", "normal", "depth"];
+
+struct Tile {
+  x0: i64,
+  y0: i64,
+  x1: i64,
+  y1: i64,
+}
+
+impl Camera {
+  #[allow(clippy::too_many_arguments)]
+  pub fn new(aspect_ratio: f64, image_width: i64, samples_per_pixel: i64,
     max_depth: i64, vfov: f64, look_from: Point3,
-    look_at: Point3, vup: Vec3, defocus_angle: f64, focus_dist: f64,  filename: &'a str) -> Camera {
+    look_at: Point3, vup: Vec3, defocus_angle: f64, focus_dist: f64) -> Camera {
     Camera {
       aspect_ratio,
       image_width,
@@ -41,32 +61,199 @@ impl Camera<'_> {
       defocus_angle,
       focus_dist,
       vup,
-      filename,
+      threads: thread::available_parallelism().map_or(1, |n| n.get()),
+      tile_size: 32,
+      seed: None,
+      background: Background::default(),
+      shutter: Interval::new_from_range(0.0, 1.0),
+      atmosphere: None,
+      spectral: false,
+      sampler: Box::new(IndependentSampler),
+      filter: Box::new(BoxFilter::new(0.5)),
     }
   }
 
-  pub fn render<'a>(&self, world: Box<dyn Hittable + 'a>) {
+  // Number of worker threads used by render, defaults to the available parallelism
+  pub fn set_threads(&mut self, threads: usize) {
+    self.threads = threads.max(1);
+  }
+
+  // Width and height in pixels of the square tiles handed to the workers
+  pub fn set_tile_size(&mut self, tile_size: i64) {
+    self.tile_size = tile_size.max(1);
+  }
+
+  // Seed the sampler and the generators of the tiles so that renders are reproducible
+  pub fn set_seed(&mut self, seed: u64) {
+    self.seed = Some(seed);
+  }
+
+  // Radiance returned for rays that leave the scene, a white to blue gradient by default
+  pub fn set_background(&mut self, background: Background) {
+    self.background = background;
+  }
+
+  // Interval of time during which the shutter is open, ray times are spread uniformly across it
+  pub fn set_shutter(&mut self, open: f64, close: f64) {
+    self.shutter = Interval::new_from_range(open, close);
+  }
+
+  // Medium that every camera and bounce ray travels through, scattering light from the scene and background
+  pub fn set_atmosphere(&mut self, atmosphere: Atmosphere) {
+    self.atmosphere = Some(atmosphere);
+  }
+
+  // Trace every path at a few wavelengths instead of in RGB, so that dispersion splits light without
+  // extra color noise and emitters with spectra, such as black bodies, are rendered from their spectrum
+  pub fn set_spectral(&mut self, spectral: bool) {
+    self.spectral = spectral;
+  }
+
+  // Sampler drawing the random numbers of every pixel sample, independent ones by default
+  pub fn set_sampler(&mut self, sampler: Box<dyn Sampler>) {
+    self.sampler = sampler;
+  }
+
+  // Filter weighting the samples around each pixel, by default a box over the pixel that averages the
+  // samples within it
+  pub fn set_filter(&mut self, filter: Box<dyn Filter>) {
+    self.filter = filter;
+  }
+
+  // Render the world into an image of linear colors, averaged over the samples of each pixel.
+  // Lights are the emissive objects sampled directly at every diffuse bounce, and may be empty
+  pub fn render(&self, world: &dyn Hittable, lights: &Lights) -> Image {
+    self.render_passes(world, lights, false).swap_remove(0).1
+  }
+
+  // Render the color image, named "", followed by the albedo, normal and depth of the first hit
+  // as seen through each pixel, which compositing and denoising tools expect as separate layers
+  pub fn render_layers(&self, world: &dyn Hittable, lights: &Lights) -> Vec<(String, Image)> {
+    self.render_passes(world, lights, true)
+  }
+
+  fn render_passes(&self, world: &dyn Hittable, lights: &Lights, aovs: bool) -> Vec<(String, Image)> {
     let settings = self.init_settings();
+    let tiles = self.tiles(settings.image_height);
 
-    let mut output = format!("P3\n{} {}\n255\n", self.image_width, settings.image_height);
+    let names = if aovs { &PASS_NAMES[..] } else { &PASS_NAMES[..1] };
+    let mut film = Film::new(0, 0, self.image_width, settings.image_height, names.len());
+    let next_tile = AtomicUsize::new(0);
 
-    for j in 0..settings.image_height {
-        print!("\rScanlines remaining: {}  ", settings.image_height-j);
-        for i in 0..self.image_width {
-            let mut pixel_color = Color::new(0.0, 0.0, 0.0);
-            for _ in 0..self.samples_per_pixel {
-              let r = Camera::get_ray(i, j, &settings, self.defocus_angle);
-              pixel_color = pixel_color + Camera::ray_color(&r, self.max_depth,&world);
+    thread::scope(|scope| {
+      let (sender, receiver) = mpsc::channel();
+
+      for _ in 0..self.threads.min(tiles.len()) {
+        let sender = sender.clone();
+        let (tiles, next_tile, settings) = (&tiles, &next_tile, &settings);
+        scope.spawn(move || {
+          loop {
+            let index = next_tile.fetch_add(1, Ordering::Relaxed);
+            if index >= tiles.len() {
+              break;
             }
+            let tile = &tiles[index];
+            // Media and anything else outside the sampler draw from the thread's generator, which is
+            // reseeded per tile so that renders with a seed don't depend on how tiles land on threads
+            Common::seed(Camera::tile_seed(settings.seed, index));
+            _ = sender.send((index, self.render_tile(tile, settings, world, lights, aovs)));
+          }
+        });
+      }
+      drop(sender);
+
+      // The margins of neighbouring tiles overlap, so tiles are merged in order as they become
+      // available to keep the floating-point sums, and the image, independent of the thread timing
+      let mut pending: Vec<Option<Film>> = tiles.iter().map(|_| None).collect();
+      let mut next_merge = 0;
+      let mut tiles_remaining = tiles.len();
+      for (index, tile_film) in receiver {
+        pending[index] = Some(tile_film);
+        while let Some(tile_film) = pending.get_mut(next_merge).and_then(Option::take) {
+          film.merge(&tile_film);
+          next_merge += 1;
+        }
 
-            let row = format!("{}\n", pixel_color.to_color_string(self.samples_per_pixel));
+        tiles_remaining -= 1;
+        print!("\rTiles remaining: {}  ", tiles_remaining);
+        _ = io::stdout().flush();
+      }
+    });
 
-            output.push_str(&row);
+    print!("\rDone!.                    \n");
+    names.iter().map(|name| name.to_string()).zip(film.images()).collect()
+  }
+
+  // Render the pixels of a tile row by row, splatting the color followed by the AOVs, if requested,
+  // into a film reaching past the tile as far as the filter spreads its samples
+  fn render_tile(&self, tile: &Tile, settings: &CameraComputedSettings, world: &dyn Hittable, lights: &Lights, aovs: bool) -> Film {
+    let passes = if aovs { PASS_NAMES.len() } else { 1 };
+    let margin = (self.filter.radius() - 0.5).max(0.0).ceil() as i64;
+    let mut film = Film::new(tile.x0 - margin, tile.y0 - margin, tile.x1 - tile.x0 + 2 * margin, tile.y1 - tile.y0 + 2 * margin, passes);
+    for j in tile.y0..tile.y1 {
+      for i in tile.x0..tile.x1 {
+        for index in 0..self.samples_per_pixel {
+          let mut sampler = PixelSampler::new(self.sampler.as_ref(), PixelSample {
+            x: i,
+            y: j,
+            index: index as u64,
+            count: self.samples_per_pixel as u64,
+            seed: settings.seed,
+          });
+          let (px, py) = Camera::pixel_sample_square(&mut sampler);
+          let (x, y) = (i as f64 + 0.5 + px, j as f64 + 0.5 + py);
+          let mut r = Camera::get_ray(x, y, settings, self.defocus_angle, &self.shutter, &mut sampler);
+          // Drawn in RGB mode too, so that the camera always uses the same dimensions
+          let wavelengths = Wavelengths::sample(sampler.get_1d());
+          if self.spectral {
+            r.wavelengths = Some(wavelengths);
+          }
+          let color = self.ray_color(&r, self.max_depth, world, lights, &mut sampler, 1.0);
+          let mut values = vec![r.wavelengths.map_or(color, |wavelengths| wavelengths.to_rgb(color))];
+          if aovs {
+            values.extend(Camera::first_hit_aovs(&r, world));
+          }
+          film.add_sample(x, y, &values, self.filter.as_ref());
         }
+      }
     }
+    film
+  }
 
-    print!("\rDone!.                    \n");
-    _ = fs::write(self.filename, output);
+  fn first_hit_aovs(ray: &Ray, world: &dyn Hittable) -> [Color; 3] {
+    let black = Color::new(0.0, 0.0, 0.0);
+    let (hit, rec) = world.hit(ray, &Interval::new_from_range(0.001, Common::INFINITY));
+    if !hit {
+      return [black, black, black];
+    }
+
+    let rec = rec.unwrap();
+    let rec = rec.set_shading_normal(rec.material.shading_normal(&rec));
+    let depth = rec.t * ray.direction.length();
+    [rec.material.albedo(&rec), rec.normal, Color::new(depth, depth, depth)]
+  }
+
+  fn tiles(&self, image_height: i64) -> Vec<Tile> {
+    let mut tiles = vec![];
+    for y0 in (0..image_height).step_by(self.tile_size as usize) {
+      for x0 in (0..self.image_width).step_by(self.tile_size as usize) {
+        tiles.push(Tile {
+          x0,
+          y0,
+          x1: (x0 + self.tile_size).min(self.image_width),
+          y1: (y0 + self.tile_size).min(image_height),
+        });
+      }
+    }
+    tiles
+  }
+
+  // Derive an independent seed for every tile (splitmix64 finalizer)
+  fn tile_seed(seed: u64, index: usize) -> u64 {
+    let mut z = seed.wrapping_add((index as u64 + 1).wrapping_mul(0x9e3779b97f4a7c15));
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+    z ^ (z >> 31)
   }
 
   fn init_settings(&self) -> CameraComputedSettings {
@@ -90,15 +277,15 @@ impl Camera<'_> {
     let viewport_v = -v * viewport_height;
 
     // Calculate the horizontal and vertical delta vectors from pixel to pixel
-    let pixel_delta_u = &viewport_u / self.image_width as f64;
-    let pixel_delta_v = &viewport_v / image_height as f64;
+    let pixel_delta_u = viewport_u / self.image_width as f64;
+    let pixel_delta_v = viewport_v / image_height as f64;
 
     // Calculate location of upper-left pixel
-    let viewport_upper_left = &center
-        - &(w * self.focus_dist)
-        - &viewport_u / 2.0
-        - &viewport_v / 2.0;
-    let pixel00_loc = viewport_upper_left + (&pixel_delta_u + &pixel_delta_v) * 0.5;
+    let viewport_upper_left = center
+        - w * self.focus_dist
+        - viewport_u / 2.0
+        - viewport_v / 2.0;
+    let pixel00_loc = viewport_upper_left + (pixel_delta_u + pixel_delta_v) * 0.5;
 
     let defocus_radius = self.focus_dist * Common::degrees_to_radians(self.defocus_angle / 2.0).tan();
     let defocus_dist_u = u * defocus_radius;
@@ -106,7 +293,8 @@ impl Camera<'_> {
 
     CameraComputedSettings {
       image_height,
-      center: center,
+      seed: self.seed.unwrap_or_else(Common::random_seed),
+      center,
       pixel00_loc,
       pixel_delta_u,
       pixel_delta_v,
@@ -115,47 +303,277 @@ impl Camera<'_> {
     }
   }
 
-  fn get_ray(i: i64, j: i64, settings: &CameraComputedSettings, defocus_angle: f64) -> Ray {
-    let pixel_center = &settings.pixel00_loc + &(&settings.pixel_delta_u * i as f64) + (&settings.pixel_delta_v * j as f64);
-    let pixel_sample = pixel_center + Camera::pixel_sample_square(&settings);
-    
-    let ray_origin = if defocus_angle < 0.0 { settings.center } else { Camera::defocus_disk_sample(&settings) };
+  // Ray through the point (x, y) of the image, where pixel (i, j) covers [i, i + 1) x [j, j + 1). The
+  // point on the lens and the time are drawn even when there is no defocus blur or motion, so that the
+  // camera always uses the same dimensions
+  fn get_ray(x: f64, y: f64, settings: &CameraComputedSettings, defocus_angle: f64, shutter: &Interval, sampler: &mut PixelSampler) -> Ray {
+    let pixel_sample = settings.pixel00_loc + (settings.pixel_delta_u * (x - 0.5)) + (settings.pixel_delta_v * (y - 0.5));
+
+    let lens = sampler.get_2d();
+    let ray_origin = if defocus_angle < 0.0 { settings.center } else { Camera::defocus_disk_sample(settings, lens) };
     let ray_direction = pixel_sample - ray_origin;
+    let ray_time = shutter.min + sampler.get_1d() * (shutter.max - shutter.min);
 
-    return Ray::new(ray_origin, ray_direction);
+    Ray::new_with_time(ray_origin, ray_direction, ray_time)
   }
 
-  fn pixel_sample_square(settings: &CameraComputedSettings) -> Vec3 {
-    let px = -0.5 + Common::random_float();
-    let py = -0.5 + Common::random_float();
-    return (settings.pixel_delta_u * px) + (settings.pixel_delta_v * py);
+  // Offset of a sample from the center of its pixel, uniform over the pixel's square
+  fn pixel_sample_square(sampler: &mut PixelSampler) -> (f64, f64) {
+    let (px, py) = sampler.get_2d();
+    (px - 0.5, py - 0.5)
   }
 
-  fn defocus_disk_sample(settings: &CameraComputedSettings) -> Point3 {
-    let p = Vec3::random_in_unit_disk();
-    return settings.center + (settings.defocus_dist_u * p.x) + (settings.defocus_dist_v * p.y);
+  fn defocus_disk_sample(settings: &CameraComputedSettings, lens: (f64, f64)) -> Point3 {
+    let p = Vec3::sample_in_unit_disk(lens);
+    settings.center + (settings.defocus_dist_u * p.x) + (settings.defocus_dist_v * p.y)
   }
 
-  fn ray_color<'a>(ray: &Ray, depth: i64, world: &Box<dyn Hittable + 'a>) -> Color {
+  // Radiance arriving along the ray. The light it finds at its end, emitted by a surface or coming from
+  // the background, is scaled by emission_weight, the MIS weight of the material sample that cast the
+  // ray against sampling the same direction from the lights
+  fn ray_color(&self, ray: &Ray, depth: i64, world: &dyn Hittable, lights: &Lights, sampler: &mut PixelSampler, emission_weight: f64) -> Color {
     if depth <= 0 {
       return Color::new(0.0, 0.0, 0.0);
     }
+    sampler.start_bounce((self.max_depth - depth) as u32);
 
     let interval = Interval::new_from_range(0.001, Common::INFINITY);
     let (hit, rec) = world.hit(ray, &interval);
-    if hit {
-      let uw_rec = rec.unwrap();
-      let (scatter, attenuation, ray_scattered) = uw_rec.material.scatter(&ray, &uw_rec);
-      if scatter {
-        let color = Camera::ray_color(&ray_scattered, depth-1, world);
-        return attenuation * color;
-      } 
 
-      return Color::new(0.0, 0.0, 0.0);
+    // Scatter in the atmosphere if the ray collides with it before reaching the surface. The
+    // scattering albedo is part of the phase function, so no other weight is needed
+    let t_max = if hit { rec.as_ref().unwrap().t } else { Common::INFINITY };
+    let u_medium = sampler.get_1d();
+    let medium_t = self.atmosphere.as_ref().and_then(|atmosphere| atmosphere.sample_distance(ray, t_max, u_medium));
+    let rec = match (medium_t, &self.atmosphere) {
+      (Some(t), Some(atmosphere)) => Some(HitRecord {
+        p: ray.at(t),
+        t,
+        u: 0.0,
+        v: 0.0,
+        material: atmosphere.phase_function(),
+        normal: Vec3::new(1.0, 0.0, 0.0),
+        geometric_normal: Vec3::new(1.0, 0.0, 0.0),
+        dpdu: Vec3::new(0.0, 1.0, 0.0),
+        dpdv: Vec3::new(0.0, 0.0, 1.0),
+        front_face: true,
+      }),
+      _ if hit => rec,
+      _ => return Camera::spectral(ray, self.background.value(ray)) * emission_weight,
+    };
+
+    let uw_rec = rec.unwrap();
+    let uw_rec = uw_rec.set_shading_normal(uw_rec.material.shading_normal(&uw_rec));
+    let color_from_emission = Camera::emitted(ray, &uw_rec) * emission_weight;
+    let (scatter, srec) = uw_rec.material.scatter(ray, &uw_rec, sampler);
+    if !scatter {
+      return color_from_emission;
     }
 
-    let unit_direction = ray.direction.unit_vector();
-    let a = 0.5 * (unit_direction.y + 1.0);
-    Color::new(1.0, 1.0, 1.0) * (1.0 - a) + Color::new(0.5, 0.7, 1.0) * a
+    let (material_pdf, specular) = match srec.unwrap() {
+      ScatterRecord::Specular { attenuation, ray: ray_scattered } => {
+        let mut attenuation = Camera::spectral(ray, attenuation);
+        // A spectral path that dispersion has narrowed to its hero wavelength drops the others
+        if ray.wavelengths.is_some() && ray.wavelength.is_none() && ray_scattered.wavelength.is_some() {
+          attenuation = Wavelengths::terminate_secondary(attenuation);
+        }
+        return color_from_emission + attenuation * self.ray_color(&ray_scattered, depth-1, world, lights, sampler, 1.0);
+      }
+      ScatterRecord::Sampled { pdf } => (pdf, None),
+      ScatterRecord::Mixed { pdf, specular } => (pdf, specular),
+    };
+
+    // Punctual lights can't be hit by scattered rays, so they are added with shadow rays instead
+    let color_from_punctual = self.punctual_lighting(ray, &uw_rec, world, lights, sampler);
+
+    // Lights with an area, including the environment, are reached both by a sample towards them and by
+    // the material's sample, with the two weighted by the power heuristic. The last bounce leaves the
+    // lights out, as the material's sample can't reach them from there either
+    let hittable_pdf = HittablePdf::new(&lights.objects, uw_rec.p, ray.time);
+    let environment_pdf = self.background.environment().map(EnvironmentPdf::new);
+    let lights_pdf;
+    let light_pdf: Option<&dyn Pdf> = match &environment_pdf {
+      _ if depth <= 1 => None,
+      Some(environment_pdf) if !lights.objects.objects.is_empty() => {
+        lights_pdf = MixturePdf::new(&hittable_pdf, environment_pdf);
+        Some(&lights_pdf)
+      }
+      Some(environment_pdf) => Some(environment_pdf),
+      None if !lights.objects.objects.is_empty() => Some(&hittable_pdf),
+      None => None,
+    };
+    let color_from_lights = match light_pdf {
+      Some(light_pdf) => self.sample_lights(ray, &uw_rec, light_pdf, material_pdf.as_ref(), world, sampler),
+      None => Color::new(0.0, 0.0, 0.0),
+    };
+    let color_direct = color_from_emission + color_from_punctual + color_from_lights;
+
+    if let Some((attenuation, ray_scattered)) = specular {
+      return color_direct + Camera::spectral(ray, attenuation) * self.ray_color(&ray_scattered, depth-1, world, lights, sampler, 1.0);
+    }
+
+    let direction = material_pdf.generate(sampler);
+    if direction.near_zero() {
+      return color_direct;
+    }
+    let ray_scattered = ray.redirected(uw_rec.p, direction);
+    if !uw_rec.sides_agree(&ray.direction, &ray_scattered.direction) {
+      return color_direct;
+    }
+    let pdf_value = material_pdf.value(&ray_scattered.direction);
+    let scattering = Camera::spectral(ray, uw_rec.material.eval(ray, &uw_rec, &ray_scattered));
+    if pdf_value <= 0.0 || scattering.near_zero() {
+      return color_direct;
+    }
+
+    let weight = light_pdf.map_or(1.0, |light_pdf| pdf::power_heuristic(pdf_value, light_pdf.value(&ray_scattered.direction)));
+    let color_from_scatter = scattering * self.ray_color(&ray_scattered, depth-1, world, lights, sampler, weight) / pdf_value;
+    color_direct + color_from_scatter
+  }
+
+  // Light from whatever a direction sampled towards the lights reaches, be it an emitter, the
+  // background or a blocker that emits nothing, weighted against the material sampling it
+  fn sample_lights(&self, ray: &Ray, rec: &HitRecord, light_pdf: &dyn Pdf, material_pdf: &dyn Pdf, world: &dyn Hittable, sampler: &mut PixelSampler) -> Color {
+    let black = Color::new(0.0, 0.0, 0.0);
+    let direction = light_pdf.generate(sampler);
+    if direction.near_zero() {
+      return black;
+    }
+    let shadow_ray = ray.redirected(rec.p, direction);
+    if !rec.sides_agree(&ray.direction, &shadow_ray.direction) {
+      return black;
+    }
+    let pdf_value = light_pdf.value(&shadow_ray.direction);
+    let scattering = Camera::spectral(ray, rec.material.eval(ray, rec, &shadow_ray));
+    if pdf_value <= 0.0 || scattering.near_zero() {
+      return black;
+    }
+
+    let (hit, light_rec) = world.hit(&shadow_ray, &Interval::new_from_range(0.001, Common::INFINITY));
+    let (emitted, t) = match light_rec {
+      Some(light_rec) if hit => (Camera::emitted(&shadow_ray, &light_rec), light_rec.t),
+      _ => (Camera::spectral(&shadow_ray, self.background.value(&shadow_ray)), Common::INFINITY),
+    };
+    if emitted.near_zero() {
+      return black;
+    }
+
+    let transmittance = self.atmosphere.as_ref().map_or(1.0, |atmosphere| atmosphere.transmittance(&shadow_ray, t));
+    let weight = pdf::power_heuristic(pdf_value, material_pdf.value(&shadow_ray.direction));
+    scattering * emitted * (transmittance * weight / pdf_value)
+  }
+
+  // Light reaching the point directly from every punctual light that isn't blocked by the world
+  fn punctual_lighting(&self, ray: &Ray, rec: &HitRecord, world: &dyn Hittable, lights: &Lights, sampler: &mut PixelSampler) -> Color {
+    let mut color = Color::new(0.0, 0.0, 0.0);
+    for light in &lights.punctual {
+      let sample = match &ray.wavelengths {
+        Some(wavelengths) => light.sample_spectral(&rec.p, wavelengths, sampler),
+        None => light.sample(&rec.p, sampler),
+      };
+      let sample = match sample {
+        Some(sample) => sample,
+        None => continue,
+      };
+
+      let shadow_ray = ray.redirected(rec.p, sample.direction);
+      if !rec.sides_agree(&ray.direction, &shadow_ray.direction) {
+        continue;
+      }
+      let scattering = Camera::spectral(ray, rec.material.eval(ray, rec, &shadow_ray));
+      if scattering.near_zero() {
+        continue;
+      }
+
+      // Stop just short of the light so that nothing at its position casts a shadow
+      let interval = Interval::new_from_range(0.001, sample.distance * (1.0 - 1e-6));
+      let (blocked, _) = world.hit(&shadow_ray, &interval);
+      if blocked {
+        continue;
+      }
+
+      let transmittance = self.atmosphere.as_ref().map_or(1.0, |atmosphere| atmosphere.transmittance(&shadow_ray, sample.distance));
+      color = color + scattering * sample.irradiance * transmittance;
+    }
+    color
+  }
+
+  // Light emitted by the surface of a hit, at the wavelengths of a spectral path
+  fn emitted(ray: &Ray, rec: &HitRecord) -> Color {
+    match &ray.wavelengths {
+      Some(wavelengths) => rec.material.emitted_spectral(rec.u, rec.v, &rec.p, wavelengths),
+      None => rec.material.emitted(rec.u, rec.v, &rec.p),
+    }
+  }
+
+  // Values of an RGB color at the wavelengths of a spectral path, or the color itself in RGB
+  fn spectral(ray: &Ray, color: Color) -> Color {
+    match &ray.wavelengths {
+      Some(wavelengths) => spectrum::upsample(color, wavelengths),
+      None => color,
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::sync::Arc;
+
+  use super::*;
+  use crate::{filter::GaussianFilter, material::Lambertian, sphere::Sphere};
+
+  // Mean of the pixels of a white Lambertian sphere filling the view, lit by a uniform background
+  fn furnace(lights: &Lights) -> Color {
+    let mut camera = Camera::new(1.0, 8, 64, 8, 20.0, Point3::new(0.0, 0.0, 5.0), Point3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 1.0, 0.0), 0.0, 5.0);
+    camera.set_threads(1);
+    camera.set_seed(3);
+    camera.set_background(Background::Solid(Color::new(0.5, 0.5, 0.5)));
+    let world = Sphere::new(Point3::new(0.0, 0.0, 0.0), 1.0, Arc::new(Lambertian::new(Color::new(1.0, 1.0, 1.0))));
+    let image = camera.render(&world, lights);
+
+    let mut sum = Color::new(0.0, 0.0, 0.0);
+    for y in 0..8 {
+      for x in 0..8 {
+        sum = sum + image.pixel(x, y);
+      }
+    }
+    sum / 64.0
+  }
+
+  #[test]
+  fn white_furnace_matches_the_background() {
+    let mean = furnace(&Lights::new());
+    assert!((mean - Color::new(0.5, 0.5, 0.5)).length() < 1e-9, "mean {} {} {}", mean.x, mean.y, mean.z);
+
+    // The light is only sampled, not in the world, so the samples towards it see the background and
+    // multiple importance sampling has to weight them against the material's to stay unbiased
+    let mut lights = Lights::new();
+    lights.objects.objects.push(Box::new(Sphere::new(Point3::new(2.0, 2.0, 3.0), 1.0, Arc::new(Lambertian::new(Color::new(1.0, 1.0, 1.0))))));
+    let mean = furnace(&lights);
+    for value in [mean.x, mean.y, mean.z] {
+      assert!((value - 0.5).abs() < 0.01, "mean {}", value);
+    }
+  }
+  #[test]
+  fn seeded_renders_do_not_depend_on_the_threads() {
+    // Small tiles and a wide filter so that every pixel gathers samples from several tiles
+    let render = |threads| {
+      let mut camera = Camera::new(1.0, 12, 4, 8, 20.0, Point3::new(0.0, 0.0, 5.0), Point3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 1.0, 0.0), 0.0, 5.0);
+      camera.set_threads(threads);
+      camera.set_tile_size(3);
+      camera.set_seed(11);
+      camera.set_filter(Box::new(GaussianFilter::new(2.0, 0.5)));
+      let world = Sphere::new(Point3::new(0.0, 0.0, 0.0), 1.0, Arc::new(Lambertian::new(Color::new(0.8, 0.6, 0.4))));
+      camera.render(&world, &Lights::new())
+    };
+
+    let (single, multi) = (render(1), render(4));
+    for y in 0..12 {
+      for x in 0..12 {
+        let (a, b) = (single.pixel(x, y), multi.pixel(x, y));
+        assert!(a.x.to_bits() == b.x.to_bits() && a.y.to_bits() == b.y.to_bits() && a.z.to_bits() == b.z.to_bits(),
+          "pixel ({}, {}): {} {} {} vs {} {} {}", x, y, a.x, a.y, a.z, b.x, b.y, b.z);
+      }
+    }
   }
 }
\ No newline at end of file
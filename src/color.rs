@@ -3,23 +3,50 @@ use crate::{interval::Interval, vec3::Vec3};
 pub type Color = Vec3;
 
 impl Color {
-  pub fn to_color_string(&self, samples_per_pixel: i64) -> String {
-    let scale = 1.0 / (samples_per_pixel as f64);
+  // Quantize each channel of a display encoded color to an integer in [0, max]
+  pub fn quantize(&self, max: u32) -> [u32; 3] {
+    let scale = (max + 1) as f64;
+    let intensity = Interval::new_from_range(0.0, max as f64 / scale);
 
-    let r = Color::linear_to_gamma(self.x * scale);
-    let g = Color::linear_to_gamma(self.y * scale);
-    let b = Color::linear_to_gamma(self.z * scale);
-
-    let intensity = Interval::new_from_range(0.000, 0.999);
+    [self.x, self.y, self.z].map(|c| (scale * intensity.clamp(c)) as u32)
+  }
 
-    format!("{} {} {}",
-      256.0 * intensity.clamp(r),
-      256.0 * intensity.clamp(g),
-      256.0 * intensity.clamp(b)
+  // Convert CIE XYZ tristimulus values to linear sRGB (Rec. 709 primaries, D65 white)
+  pub fn new_from_xyz(x: f64, y: f64, z: f64) -> Color {
+    Color::new(
+      3.2404542 * x - 1.5371385 * y - 0.4985314 * z,
+      -0.9692660 * x + 1.8760108 * y + 0.0415560 * z,
+      0.0556434 * x - 0.2040259 * y + 1.0572252 * z,
     )
   }
 
-  fn linear_to_gamma(x: f64) -> f64 {
-    x.sqrt()
+  pub fn luminance(&self) -> f64 {
+    0.2126 * self.x + 0.7152 * self.y + 0.0722 * self.z
+  }
+
+  // Encode a linear color with the piecewise sRGB transfer function
+  pub fn linear_to_srgb(&self) -> Color {
+    Color::new(Color::srgb_oetf(self.x), Color::srgb_oetf(self.y), Color::srgb_oetf(self.z))
+  }
+
+  // Decode an sRGB encoded color, as stored in image files, back into linear space
+  pub fn srgb_to_linear(&self) -> Color {
+    Color::new(Color::srgb_eotf(self.x), Color::srgb_eotf(self.y), Color::srgb_eotf(self.z))
+  }
+
+  fn srgb_oetf(x: f64) -> f64 {
+    if x <= 0.0031308 {
+      12.92 * x
+    } else {
+      1.055 * x.powf(1.0 / 2.4) - 0.055
+    }
+  }
+
+  fn srgb_eotf(x: f64) -> f64 {
+    if x <= 0.04045 {
+      x / 12.92
+    } else {
+      ((x + 0.055) / 1.055).powf(2.4)
+    }
   }
 }
\ No newline at end of file
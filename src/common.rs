@@ -1,19 +1,36 @@
-use std::f64::{consts::PI, INFINITY};
+use std::{cell::RefCell, f64::consts::PI};
+
+use rand::{rngs::SmallRng, Rng, SeedableRng};
+
+thread_local! {
+  // Every thread owns its generator so workers never contend on a shared RNG
+  static RNG: RefCell<SmallRng> = RefCell::new(SmallRng::from_entropy());
+}
 
 pub struct Common {
 }
 
 impl Common {
   pub const PI: f64 = PI;
-  pub const INFINITY: f64 = INFINITY;
+  pub const INFINITY: f64 = f64::INFINITY;
 
   pub fn degrees_to_radians(degrees: f64) -> f64 {
-    return degrees * Common::PI / 180.0;
+    degrees * Common::PI / 180.0
+  }
+
+  // Reseed the current thread's generator, making the following random numbers reproducible
+  pub fn seed(seed: u64) {
+    RNG.with(|rng| *rng.borrow_mut() = SmallRng::seed_from_u64(seed));
   }
 
   // Return a random float in range [0,1)
   pub fn random_float() -> f64 {
-    rand::random::<f64>()
+    RNG.with(|rng| rng.borrow_mut().gen::<f64>())
+  }
+
+  // Random seed for a render that wasn't given one
+  pub fn random_seed() -> u64 {
+    RNG.with(|rng| rng.borrow_mut().gen())
   }
 
   pub fn random_float_in_range(min: f64, max: f64) -> f64 {
@@ -0,0 +1,121 @@
+use std::sync::Arc;
+
+use crate::{aabb::Aabb, color::Color, common::Common, hittable::{HitRecord, Hittable}, interval::Interval, material::{Isotropic, Material}, ray::Ray, vec3::Vec3};
+
+// Volume of uniform density filling a closed boundary, such as smoke or fog. Rays passing through
+// scatter at a random distance distributed exponentially by the density
+pub struct ConstantMedium {
+  boundary: Box<dyn Hittable>,
+  neg_inv_density: f64,
+  phase_function: Arc<dyn Material>,
+}
+
+impl ConstantMedium {
+  pub fn new(boundary: Box<dyn Hittable>, density: f64, albedo: Color) -> ConstantMedium {
+    ConstantMedium::new_with_phase(boundary, density, Arc::new(Isotropic::new(albedo)))
+  }
+
+  // Medium scattering with the given phase function material, such as Isotropic or HenyeyGreenstein
+  pub fn new_with_phase(boundary: Box<dyn Hittable>, density: f64, phase_function: Arc<dyn Material>) -> ConstantMedium {
+    ConstantMedium {
+      boundary,
+      neg_inv_density: -1.0 / density,
+      phase_function,
+    }
+  }
+}
+
+impl Hittable for ConstantMedium {
+  fn hit(&self, ray: &Ray, ray_t: &Interval) -> (bool, Option<HitRecord<'_>>) {
+    // Find where the ray enters and leaves the boundary, even if it starts inside
+    let (hit1, rec1) = self.boundary.hit(ray, &Interval::UNIVERSE);
+    if !hit1 {
+      return (false, None);
+    }
+    let t_enter = rec1.unwrap().t;
+    let (hit2, rec2) = self.boundary.hit(ray, &Interval::new_from_range(t_enter + 0.0001, Common::INFINITY));
+    if !hit2 {
+      return (false, None);
+    }
+    let t_exit = rec2.unwrap().t;
+
+    let t_enter = t_enter.max(ray_t.min).max(0.0);
+    let t_exit = t_exit.min(ray_t.max);
+    if t_enter >= t_exit {
+      return (false, None);
+    }
+
+    let ray_length = ray.direction.length();
+    let distance_inside_boundary = (t_exit - t_enter) * ray_length;
+    // Drawn from the thread's generator rather than the pixel sampler: hit is called for however many
+    // candidates the traversal visits, so it can't be given fixed dimensions of the sampler, and the
+    // camera reseeds the generator per tile to keep seeded renders reproducible
+    let hit_distance = self.neg_inv_density * Common::random_float().ln();
+    if hit_distance > distance_inside_boundary {
+      return (false, None);
+    }
+
+    let t = t_enter + hit_distance / ray_length;
+    let rec = HitRecord {
+      p: ray.at(t),
+      t,
+      u: 0.0,
+      v: 0.0,
+      material: self.phase_function.as_ref(),
+      // The normal, tangents and face are arbitrary inside a volume
+      normal: Vec3::new(1.0, 0.0, 0.0),
+      geometric_normal: Vec3::new(1.0, 0.0, 0.0),
+      dpdu: Vec3::new(0.0, 1.0, 0.0),
+      dpdv: Vec3::new(0.0, 0.0, 1.0),
+      front_face: true,
+    };
+    (true, Some(rec))
+  }
+
+  fn bounding_box(&self) -> Aabb {
+    self.boundary.bounding_box()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{sphere::Sphere, vec3::Point3};
+
+  fn medium(radius: f64, density: f64) -> ConstantMedium {
+    let boundary = Sphere::new(Point3::new(0.0, 0.0, 0.0), radius, Arc::new(Isotropic::new(Color::new(1.0, 1.0, 1.0))));
+    ConstantMedium::new(Box::new(boundary), density, Color::new(1.0, 1.0, 1.0))
+  }
+
+  #[test]
+  fn mean_free_path_is_the_inverse_density() {
+    Common::seed(5);
+    let n = 20000;
+    let density = 2.0;
+    let medium = medium(1000.0, density);
+    // A direction longer than a unit makes t and the distance differ
+    let ray = Ray::new(Point3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 3.0, 4.0));
+    let mut total = 0.0;
+    for _ in 0..n {
+      let (hit, rec) = medium.hit(&ray, &Interval::new_from_range(0.0, Common::INFINITY));
+      assert!(hit);
+      total += rec.unwrap().t * 5.0;
+    }
+    let mean = total / n as f64;
+    assert!((mean - 1.0 / density).abs() < 0.02, "mean free path {}", mean);
+  }
+
+  #[test]
+  fn rays_pass_through_as_often_as_the_transmittance() {
+    Common::seed(9);
+    let n = 20000;
+    let medium = medium(1.0, 0.7);
+    let from_inside = Ray::new(Point3::new(0.0, 0.0, 0.0), Vec3::new(1.0, 0.0, 0.0));
+    let through = Ray::new(Point3::new(-5.0, 0.0, 0.0), Vec3::new(1.0, 0.0, 0.0));
+    for (ray, distance) in [(from_inside, 1.0_f64), (through, 2.0)] {
+      let hits = (0..n).filter(|_| medium.hit(&ray, &Interval::new_from_range(0.001, Common::INFINITY)).0).count();
+      let expected = 1.0 - (-0.7 * distance).exp();
+      assert!((hits as f64 / n as f64 - expected).abs() < 0.015, "{} hits, expected {}", hits, expected * n as f64);
+    }
+  }
+}
\ No newline at end of file
@@ -0,0 +1,157 @@
+use std::sync::Arc;
+
+use crate::{aabb::Aabb, common::Common, hittable::{HitRecord, Hittable}, interval::Interval, material::Material, onb::Onb, ray::Ray, sampler::PixelSampler, vec3::{Point3, Vec3}};
+
+// Flat circle around center, facing along normal
+#[derive(Clone)]
+pub struct Disk {
+  center: Point3,
+  radius: f64,
+  material: Arc<dyn Material>,
+  // Tangent basis of the disk, with w along the normal
+  uvw: Onb,
+}
+
+impl Disk {
+  pub fn new(center: Point3, normal: Vec3, radius: f64, material: Arc<dyn Material>) -> Disk {
+    Disk {
+      center,
+      radius,
+      material,
+      uvw: Onb::new(&normal),
+    }
+  }
+}
+
+impl Hittable for Disk {
+  fn hit(&self, ray: &Ray, ray_t: &Interval) -> (bool, Option<HitRecord<'_>>) {
+    let normal = self.uvw.w;
+    let denom = normal.dot(&ray.direction);
+
+    // No hit if the ray is parallel to the plane
+    if denom.abs() < 1e-8 {
+      return (false, None);
+    }
+
+    let t = normal.dot(&(self.center - ray.origin)) / denom;
+    if !ray_t.surrounds(t) {
+      return (false, None);
+    }
+
+    let p = ray.at(t);
+    let offset = p - self.center;
+    let distance_squared = offset.length_squared();
+    if distance_squared > self.radius * self.radius {
+      return (false, None);
+    }
+
+    // Polar texture coordinates, u the angle around the normal and v the distance from the center
+    let phi = offset.dot(&self.uvw.v).atan2(offset.dot(&self.uvw.u));
+    let u = (phi + Common::PI) / (2.0 * Common::PI);
+    let v = distance_squared.sqrt() / self.radius;
+    let radial = self.uvw.u * phi.cos() + self.uvw.v * phi.sin();
+    let dpdu = (self.uvw.v * phi.cos() - self.uvw.u * phi.sin()) * (2.0 * Common::PI * v * self.radius);
+    let dpdv = radial * self.radius;
+
+    let rec = HitRecord {
+      p,
+      t,
+      u,
+      v,
+      material: self.material.as_ref(),
+      normal,
+      geometric_normal: normal,
+      dpdu,
+      dpdv,
+      front_face: false,
+    };
+    let rec = rec.set_face_normal(ray, normal);
+
+    (true, Some(rec))
+  }
+
+  fn bounding_box(&self) -> Aabb {
+    // The disk extends radius * sin(angle between the normal and the axis) along each axis
+    let n = self.uvw.w;
+    let extent = Vec3::new(
+      self.radius * (1.0 - n.x * n.x).max(0.0).sqrt(),
+      self.radius * (1.0 - n.y * n.y).max(0.0).sqrt(),
+      self.radius * (1.0 - n.z * n.z).max(0.0).sqrt(),
+    );
+    Aabb::new_from_points(&(self.center - extent), &(self.center + extent))
+  }
+
+  fn pdf_value(&self, origin: &Point3, direction: &Vec3, _time: f64) -> f64 {
+    let (hit, rec) = self.hit(&Ray::new(*origin, *direction), &Interval::new_from_range(0.001, Common::INFINITY));
+    if !hit {
+      return 0.0;
+    }
+
+    let t = rec.unwrap().t;
+    let distance_squared = t * t * direction.length_squared();
+    let cosine = (direction.dot(&self.uvw.w) / direction.length()).abs();
+    if cosine < 1e-8 {
+      return 0.0;
+    }
+    let area = Common::PI * self.radius * self.radius;
+    distance_squared / (cosine * area)
+  }
+
+  fn random(&self, origin: &Point3, _time: f64, sampler: &mut PixelSampler) -> Vec3 {
+    // Taking the square root of the radius spreads the points uniformly over the area
+    let (u1, u2) = sampler.get_2d();
+    let r = self.radius * u1.sqrt();
+    let phi = 2.0 * Common::PI * u2;
+    let p = self.center + self.uvw.local(&Vec3::new(r * phi.cos(), r * phi.sin(), 0.0));
+    p - origin
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{color::Color, material::Lambertian, pdf::{tests::{assert_generates_value, integrate}, HittablePdf}};
+
+  fn disk() -> Disk {
+    Disk::new(Point3::new(1.0, 0.0, 0.0), Vec3::new(1.0, 1.0, 0.0), 2.0, Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5))))
+  }
+
+  fn hit(disk: &Disk, origin: Point3, direction: Vec3) -> Option<HitRecord<'_>> {
+    disk.hit(&Ray::new(origin, direction), &Interval::new_from_range(0.001, Common::INFINITY)).1
+  }
+
+  #[test]
+  fn hits_inside_the_radius_with_polar_uvs() {
+    let disk = disk();
+    let (n, u, v) = (disk.uvw.w, disk.uvw.u, disk.uvw.v);
+    for (offset, expected_u, expected_v) in [(u, 0.5, 0.5), (v * 1.5, 0.75, 0.75), (-v * 0.2, 0.25, 0.1), (u * 1.999, 0.5, 0.9995)] {
+      let target = disk.center + offset;
+      let rec = hit(&disk, target + n * 3.0, -n).unwrap_or_else(|| panic!("missed {} {} {}", offset.x, offset.y, offset.z));
+      assert!((rec.t - 3.0).abs() < 1e-12 && rec.front_face);
+      assert!((rec.p - target).length() < 1e-12);
+      assert!((rec.u - expected_u).abs() < 1e-9 && (rec.v - expected_v).abs() < 1e-9, "uv {} {}", rec.u, rec.v);
+    }
+
+    for offset in [u * 2.001, (u + v) * 1.5, -v * 2.5] {
+      assert!(hit(&disk, disk.center + offset + n * 3.0, -n).is_none());
+    }
+
+    // The bounding box holds the rim
+    let bbox = disk.bounding_box();
+    for i in 0..64 {
+      let phi = 2.0 * Common::PI * i as f64 / 64.0;
+      let p = disk.center + (u * phi.cos() + v * phi.sin()) * 2.0;
+      assert!(bbox.x.contains(p.x) && bbox.y.contains(p.y) && bbox.z.contains(p.z), "rim point {} {} {}", p.x, p.y, p.z);
+    }
+  }
+
+  #[test]
+  fn sampled_directions_follow_the_density() {
+    let disk = disk();
+    for origin in [Point3::new(1.6, 0.6, 1.0), Point3::new(0.5, -0.2, -1.0)] {
+      let integral = integrate(&|direction| disk.pdf_value(&origin, direction, 0.0));
+      assert!((integral - 1.0).abs() < 2e-3, "integral {}", integral);
+      assert_generates_value(&HittablePdf::new(&disk, origin, 0.0), 1.0);
+    }
+  }
+}
\ No newline at end of file
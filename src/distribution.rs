@@ -0,0 +1,144 @@
+// Piecewise constant distribution over [0,1) built from non-negative function values
+pub struct Distribution1D {
+  func: Vec<f64>,
+  cdf: Vec<f64>,
+  integral: f64,
+}
+
+impl Distribution1D {
+  pub fn new(func: Vec<f64>) -> Distribution1D {
+    let n = func.len();
+    let mut cdf = vec![0.0; n + 1];
+    for i in 0..n {
+      cdf[i + 1] = cdf[i] + func[i] / n as f64;
+    }
+    let integral = cdf[n];
+
+    // Fall back to a uniform distribution when every value is zero
+    if integral <= 0.0 {
+      for (i, c) in cdf.iter_mut().enumerate() {
+        *c = i as f64 / n as f64;
+      }
+    } else {
+      for c in cdf.iter_mut() {
+        *c /= integral;
+      }
+    }
+
+    Distribution1D {
+      func,
+      cdf,
+      integral,
+    }
+  }
+
+  pub fn len(&self) -> usize {
+    self.func.len()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.func.is_empty()
+  }
+
+  pub fn integral(&self) -> f64 {
+    self.integral
+  }
+
+  // Density at the given segment
+  pub fn pdf(&self, index: usize) -> f64 {
+    if self.integral <= 0.0 { 1.0 } else { self.func[index] / self.integral }
+  }
+
+  // Map u in [0,1) to a point distributed by the function, returning it with its segment
+  pub fn sample(&self, u: f64) -> (f64, usize) {
+    let index = (self.cdf.partition_point(|&c| c <= u).max(1) - 1).min(self.len() - 1);
+    let width = self.cdf[index + 1] - self.cdf[index];
+    let offset = if width > 0.0 { (u - self.cdf[index]) / width } else { 0.0 };
+    ((index as f64 + offset.clamp(0.0, 1.0)) / self.len() as f64, index)
+  }
+}
+
+// Piecewise constant distribution over [0,1)^2 built from a grid of values stored row by row,
+// sampled by picking a row from the marginal distribution and then a column within it
+pub struct Distribution2D {
+  conditional: Vec<Distribution1D>,
+  marginal: Distribution1D,
+}
+
+impl Distribution2D {
+  pub fn new(func: &[f64], width: usize, height: usize) -> Distribution2D {
+    let conditional: Vec<Distribution1D> = func.chunks(width).take(height)
+      .map(|row| Distribution1D::new(row.to_vec()))
+      .collect();
+    let marginal = Distribution1D::new(conditional.iter().map(|row| row.integral()).collect());
+    Distribution2D {
+      conditional,
+      marginal,
+    }
+  }
+
+  // Returns the sampled point as (u, v), with v selecting the row
+  pub fn sample(&self, u1: f64, u2: f64) -> (f64, f64) {
+    let (v, row) = self.marginal.sample(u2);
+    let (u, _) = self.conditional[row].sample(u1);
+    (u, v)
+  }
+
+  // Density of the cell at column i and row j, with respect to area in [0,1)^2
+  pub fn pdf(&self, i: usize, j: usize) -> f64 {
+    self.marginal.pdf(j) * self.conditional[j].pdf(i)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  // Share of n stratified samples landing in each cell of a grid of width by height cells
+  fn histogram<F: Fn(f64) -> (f64, f64)>(sample: F, n: usize, width: usize, height: usize) -> Vec<f64> {
+    let mut counts = vec![0.0; width * height];
+    for i in 0..n {
+      let (u, v) = sample((i as f64 + 0.5) / n as f64);
+      assert!((0.0..1.0).contains(&u) && (0.0..1.0).contains(&v));
+      counts[(v * height as f64) as usize * width + (u * width as f64) as usize] += 1.0 / n as f64;
+    }
+    counts
+  }
+
+  #[test]
+  fn samples_follow_the_density() {
+    let distribution = Distribution1D::new(vec![1.0, 0.0, 3.0, 4.0]);
+    assert_eq!(distribution.integral(), 2.0);
+    let counts = histogram(|u| (distribution.sample(u).0, 0.0), 10000, 4, 1);
+    for (index, count) in counts.iter().enumerate() {
+      assert!((count - distribution.pdf(index) / 4.0).abs() < 1e-3, "segment {} has {}", index, count);
+    }
+    assert_eq!(distribution.sample(0.3).1, 2);
+    // Points are spread evenly within a segment
+    assert!((distribution.sample(0.125 + 0.5 * 0.375).0 - 0.625).abs() < 1e-12);
+  }
+
+  #[test]
+  fn zero_functions_are_sampled_uniformly() {
+    let distribution = Distribution1D::new(vec![0.0; 5]);
+    assert_eq!(distribution.pdf(3), 1.0);
+    assert!((distribution.sample(0.7).0 - 0.7).abs() < 1e-12);
+  }
+
+  #[test]
+  fn samples_follow_the_density_in_two_dimensions() {
+    let func = [1.0, 2.0, 0.0, 5.0, 0.0, 0.0, 3.0, 1.0, 4.0];
+    let distribution = Distribution2D::new(&func, 3, 3);
+    let n = 300;
+    let mut counts = [0.0; 9];
+    for j in 0..n {
+      let cell = histogram(|u| distribution.sample(u, (j as f64 + 0.5) / n as f64), n, 3, 3);
+      counts.iter_mut().zip(cell).for_each(|(count, share)| *count += share / n as f64);
+    }
+    for (index, count) in counts.iter().enumerate() {
+      let expected = distribution.pdf(index % 3, index / 3) / 9.0;
+      assert!((expected - func[index] / 16.0).abs() < 1e-12);
+      assert!((count - expected).abs() < 1e-3, "cell {} has {}", index, count);
+    }
+  }
+}
\ No newline at end of file
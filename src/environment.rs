@@ -0,0 +1,120 @@
+use std::path::Path;
+
+use crate::{color::Color, common::Common, distribution::Distribution2D, image::{Image, ImageError}, sampler::PixelSampler, vec3::Vec3};
+
+// Light arriving from infinitely far away, such as an HDR photograph of the surroundings or a sky.
+// Environments can be sampled for next-event estimation, uniformly over all directions unless they
+// provide something better
+pub trait Environment: Send + Sync {
+  fn value(&self, direction: &Vec3) -> Color;
+
+  // Density, with respect to solid angle, of random returning the given direction
+  fn pdf_value(&self, _direction: &Vec3) -> f64 {
+    1.0 / (4.0 * Common::PI)
+  }
+
+  fn random(&self, sampler: &mut PixelSampler) -> Vec3 {
+    Vec3::sample_unit_vector(sampler.get_2d())
+  }
+}
+
+// Equirectangular (latitude-longitude) image around the scene, with +Y at the top row and u
+// measured around the Y axis from -X as for spheres. Directions are importance sampled by the
+// luminance of the pixels, weighted by the solid angle they cover
+pub struct ImageEnvironment {
+  image: Image,
+  intensity: f64,
+  // Rotation around the Y axis in radians
+  rotation: f64,
+  distribution: Distribution2D,
+}
+
+impl ImageEnvironment {
+  pub fn new(image: Image) -> ImageEnvironment {
+    let (width, height) = (image.width, image.height);
+    let func: Vec<f64> = (0..height).flat_map(|j| {
+      // Rows near the poles are squeezed into a smaller solid angle
+      let sin_theta = (Common::PI * (j as f64 + 0.5) / height as f64).sin();
+      let image = &image;
+      (0..width).map(move |i| image.pixel(i, j).luminance().max(0.0) * sin_theta)
+    }).collect();
+
+    ImageEnvironment {
+      distribution: Distribution2D::new(&func, width, height),
+      image,
+      intensity: 1.0,
+      rotation: 0.0,
+    }
+  }
+
+  // Load an equirectangular .hdr or .exr image, or any other format Image::load supports
+  pub fn load<P: AsRef<Path>>(path: P) -> Result<ImageEnvironment, ImageError> {
+    let image = Image::load(path)?;
+    if image.width == 0 || image.height == 0 {
+      return Err(ImageError::Format(String::from("the environment image is empty")));
+    }
+    Ok(ImageEnvironment::new(image))
+  }
+
+  // Scale factor applied to the radiance of every pixel
+  pub fn set_intensity(&mut self, intensity: f64) {
+    self.intensity = intensity;
+  }
+
+  // Turn the environment counterclockwise around the Y axis, as seen from above
+  pub fn set_rotation(&mut self, degrees: f64) {
+    self.rotation = Common::degrees_to_radians(degrees);
+  }
+
+  fn rotate(&self, direction: &Vec3, angle: f64) -> Vec3 {
+    let (sin, cos) = angle.sin_cos();
+    Vec3::new(cos * direction.x + sin * direction.z, direction.y, -sin * direction.x + cos * direction.z)
+  }
+
+  // Texture coordinates of a world direction, with v = 0 at the top row
+  fn direction_to_uv(&self, direction: &Vec3) -> (f64, f64) {
+    let d = self.rotate(&direction.unit_vector(), -self.rotation);
+    let theta = d.y.clamp(-1.0, 1.0).acos();
+    let phi = (-d.z).atan2(d.x) + Common::PI;
+    (phi / (2.0 * Common::PI), theta / Common::PI)
+  }
+
+  fn uv_to_direction(&self, u: f64, v: f64) -> Vec3 {
+    let theta = v * Common::PI;
+    let phi = u * 2.0 * Common::PI - Common::PI;
+    let d = Vec3::new(theta.sin() * phi.cos(), theta.cos(), -theta.sin() * phi.sin());
+    self.rotate(&d, self.rotation)
+  }
+
+  fn pixel_index(&self, u: f64, v: f64) -> (usize, usize) {
+    let i = ((u * self.image.width as f64) as usize).min(self.image.width - 1);
+    let j = ((v * self.image.height as f64) as usize).min(self.image.height - 1);
+    (i, j)
+  }
+}
+
+impl Environment for ImageEnvironment {
+  fn value(&self, direction: &Vec3) -> Color {
+    let (u, v) = self.direction_to_uv(direction);
+    let (i, j) = self.pixel_index(u, v);
+    self.image.pixel(i, j) * self.intensity
+  }
+
+  fn pdf_value(&self, direction: &Vec3) -> f64 {
+    let (u, v) = self.direction_to_uv(direction);
+    let sin_theta = (v * Common::PI).sin();
+    if sin_theta <= 0.0 {
+      return 0.0;
+    }
+
+    // Convert from density over the image to density over solid angle
+    let (i, j) = self.pixel_index(u, v);
+    self.distribution.pdf(i, j) / (2.0 * Common::PI * Common::PI * sin_theta)
+  }
+
+  fn random(&self, sampler: &mut PixelSampler) -> Vec3 {
+    let (u1, u2) = sampler.get_2d();
+    let (u, v) = self.distribution.sample(u1, u2);
+    self.uv_to_direction(u, v)
+  }
+}
\ No newline at end of file
@@ -0,0 +1,126 @@
+use crate::{color::Color, filter::Filter, image::Image};
+
+// Region of the image being rendered, holding for every pixel the filter weighted sum of the samples
+// around it, for each pass, along with the sum of their weights and of their absolute weights
+pub struct Film {
+  x0: i64,
+  y0: i64,
+  width: i64,
+  height: i64,
+  sums: Vec<Vec<Color>>,
+  weights: Vec<f64>,
+  abs_weights: Vec<f64>,
+}
+
+// Weight sums below this fraction of the absolute weights come from the negative lobes of a filter
+// cancelling the positive ones, and dividing by them would blow the pixel up
+const MIN_RELATIVE_WEIGHT: f64 = 1e-2;
+
+impl Film {
+  // Film of width by height pixels with its top-left pixel at (x0, y0) in the image
+  pub fn new(x0: i64, y0: i64, width: i64, height: i64, passes: usize) -> Film {
+    let pixels = (width.max(0) * height.max(0)) as usize;
+    Film {
+      x0,
+      y0,
+      width,
+      height,
+      sums: vec![vec![Color::new(0.0, 0.0, 0.0); pixels]; passes],
+      weights: vec![0.0; pixels],
+      abs_weights: vec![0.0; pixels],
+    }
+  }
+
+  // Splat a sample at (x, y) in image coordinates, where pixel (i, j) covers [i, i + 1) x [j, j + 1),
+  // into every pixel of the film whose center is within the filter's radius
+  pub fn add_sample(&mut self, x: f64, y: f64, values: &[Color], filter: &dyn Filter) {
+    let radius = filter.radius();
+    let i0 = ((x - 0.5 - radius).ceil() as i64).max(self.x0);
+    let i1 = ((x - 0.5 + radius).floor() as i64).min(self.x0 + self.width - 1);
+    let j0 = ((y - 0.5 - radius).ceil() as i64).max(self.y0);
+    let j1 = ((y - 0.5 + radius).floor() as i64).min(self.y0 + self.height - 1);
+
+    for j in j0..=j1 {
+      for i in i0..=i1 {
+        let weight = filter.evaluate(i as f64 + 0.5 - x, j as f64 + 0.5 - y);
+        if weight == 0.0 {
+          continue;
+        }
+        let index = self.index(i, j);
+        self.weights[index] += weight;
+        self.abs_weights[index] += weight.abs();
+        for (sums, value) in self.sums.iter_mut().zip(values) {
+          sums[index] = sums[index] + *value * weight;
+        }
+      }
+    }
+  }
+
+  // Add the samples of another film, such as that of a tile, where the two overlap
+  pub fn merge(&mut self, other: &Film) {
+    for j in self.y0.max(other.y0)..(self.y0 + self.height).min(other.y0 + other.height) {
+      for i in self.x0.max(other.x0)..(self.x0 + self.width).min(other.x0 + other.width) {
+        let (index, other_index) = (self.index(i, j), other.index(i, j));
+        self.weights[index] += other.weights[other_index];
+        self.abs_weights[index] += other.abs_weights[other_index];
+        for (sums, other_sums) in self.sums.iter_mut().zip(&other.sums) {
+          sums[index] = sums[index] + other_sums[other_index];
+        }
+      }
+    }
+  }
+
+  // Image of each pass, every pixel being the weighted average of its samples, or black without any.
+  // Where the weights nearly cancel out the samples are averaged by their absolute weights instead
+  pub fn images(&self) -> Vec<Image> {
+    self.sums.iter()
+      .map(|sums| Image {
+        width: self.width as usize,
+        height: self.height as usize,
+        pixels: sums.iter().zip(self.weights.iter().zip(&self.abs_weights))
+          .map(|(sum, (&weight, &abs_weight))| {
+            let weight = if weight.abs() > MIN_RELATIVE_WEIGHT * abs_weight { weight } else { abs_weight };
+            if weight != 0.0 { *sum / weight } else { Color::new(0.0, 0.0, 0.0) }
+          })
+          .collect(),
+      })
+      .collect()
+  }
+
+  fn index(&self, i: i64, j: i64) -> usize {
+    ((j - self.y0) * self.width + (i - self.x0)) as usize
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::filter::{BoxFilter, LanczosFilter};
+
+  #[test]
+  fn averages_samples_in_a_pixel() {
+    let mut film = Film::new(0, 0, 2, 1, 1);
+    let filter = BoxFilter::new(0.5);
+    film.add_sample(0.25, 0.5, &[Color::new(1.0, 2.0, 3.0)], &filter);
+    film.add_sample(0.75, 0.5, &[Color::new(3.0, 2.0, 1.0)], &filter);
+    let image = film.images().swap_remove(0);
+    assert_eq!((image.pixels[0].x, image.pixels[0].y, image.pixels[0].z), (2.0, 2.0, 2.0));
+    assert_eq!((image.pixels[1].x, image.pixels[1].y, image.pixels[1].z), (0.0, 0.0, 0.0));
+  }
+
+  #[test]
+  fn cancelling_weights_stay_bounded() {
+    let mut film = Film::new(0, 0, 1, 1, 1);
+    let filter = LanczosFilter::new(3.0);
+    // A sample in the positive lobe and one in the negative lobe whose weights almost cancel out
+    let positive = filter.evaluate(0.9, 0.0);
+    let mut x = 1.0;
+    while filter.evaluate(x, 0.0) > -positive {
+      x += 1e-4;
+    }
+    film.add_sample(0.5 + 0.9, 0.5, &[Color::new(1.0, 1.0, 1.0)], &filter);
+    film.add_sample(0.5 + x, 0.5, &[Color::new(0.0, 0.0, 0.0)], &filter);
+    let pixel = film.images().swap_remove(0).pixels[0];
+    assert!(pixel.x.is_finite() && pixel.x.abs() <= 1.0, "pixel is {}", pixel.x);
+  }
+}
\ No newline at end of file
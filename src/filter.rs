@@ -0,0 +1,152 @@
+use crate::common::Common;
+
+// Weight of a sample by its offset in pixels from the center of a pixel, which every sample within
+// the radius contributes to. Filters wider than a pixel smooth the image, and those with negative
+// lobes, Mitchell and Lanczos, sharpen it again
+pub trait Filter: Send + Sync {
+  fn radius(&self) -> f64;
+  fn evaluate(&self, x: f64, y: f64) -> f64;
+}
+
+// Equal weight over a square, the plain average of the samples within a pixel at a radius of 0.5
+pub struct BoxFilter {
+  radius: f64,
+}
+
+impl BoxFilter {
+  pub fn new(radius: f64) -> BoxFilter {
+    BoxFilter { radius }
+  }
+}
+
+impl Filter for BoxFilter {
+  fn radius(&self) -> f64 {
+    self.radius
+  }
+
+  fn evaluate(&self, x: f64, y: f64) -> f64 {
+    if x.abs() <= self.radius && y.abs() <= self.radius { 1.0 } else { 0.0 }
+  }
+}
+
+// Weight falling off linearly to zero at the radius
+pub struct TentFilter {
+  radius: f64,
+}
+
+impl TentFilter {
+  pub fn new(radius: f64) -> TentFilter {
+    TentFilter { radius }
+  }
+}
+
+impl Filter for TentFilter {
+  fn radius(&self) -> f64 {
+    self.radius
+  }
+
+  fn evaluate(&self, x: f64, y: f64) -> f64 {
+    (self.radius - x.abs()).max(0.0) * (self.radius - y.abs()).max(0.0)
+  }
+}
+
+// Gaussian of standard deviation sigma, lowered by its value at the radius so that it reaches zero there
+pub struct GaussianFilter {
+  radius: f64,
+  sigma: f64,
+}
+
+impl GaussianFilter {
+  pub fn new(radius: f64, sigma: f64) -> GaussianFilter {
+    GaussianFilter { radius, sigma }
+  }
+
+  fn gaussian(&self, x: f64) -> f64 {
+    let g = |x: f64| (-x * x / (2.0 * self.sigma * self.sigma)).exp();
+    (g(x) - g(self.radius)).max(0.0)
+  }
+}
+
+impl Filter for GaussianFilter {
+  fn radius(&self) -> f64 {
+    self.radius
+  }
+
+  fn evaluate(&self, x: f64, y: f64) -> f64 {
+    self.gaussian(x) * self.gaussian(y)
+  }
+}
+
+// Cubic filter of Mitchell and Netravali, "Reconstruction Filters in Computer Graphics", 1988. B and C
+// trade blurring against ringing, and B + 2C = 1 gives the best compromise, such as the default 1/3, 1/3
+pub struct MitchellFilter {
+  radius: f64,
+  b: f64,
+  c: f64,
+}
+
+impl MitchellFilter {
+  pub fn new(radius: f64, b: f64, c: f64) -> MitchellFilter {
+    MitchellFilter { radius, b, c }
+  }
+
+  // The cubic spans [-2, 2], which is stretched over the radius
+  fn mitchell(&self, x: f64) -> f64 {
+    let x = (2.0 * x / self.radius).abs();
+    let (b, c) = (self.b, self.c);
+    let value = if x <= 1.0 {
+      (12.0 - 9.0 * b - 6.0 * c) * x * x * x + (-18.0 + 12.0 * b + 6.0 * c) * x * x + (6.0 - 2.0 * b)
+    } else if x <= 2.0 {
+      (-b - 6.0 * c) * x * x * x + (6.0 * b + 30.0 * c) * x * x + (-12.0 * b - 48.0 * c) * x + (8.0 * b + 24.0 * c)
+    } else {
+      0.0
+    };
+    value / 6.0
+  }
+}
+
+impl Filter for MitchellFilter {
+  fn radius(&self) -> f64 {
+    self.radius
+  }
+
+  fn evaluate(&self, x: f64, y: f64) -> f64 {
+    self.mitchell(x) * self.mitchell(y)
+  }
+}
+
+// Sinc windowed by a sinc stretched over the radius, the closest of these to an ideal low-pass filter
+pub struct LanczosFilter {
+  radius: f64,
+}
+
+impl LanczosFilter {
+  pub fn new(radius: f64) -> LanczosFilter {
+    LanczosFilter { radius }
+  }
+
+  fn lanczos(&self, x: f64) -> f64 {
+    if x.abs() >= self.radius {
+      return 0.0;
+    }
+    sinc(x) * sinc(x / self.radius)
+  }
+}
+
+impl Filter for LanczosFilter {
+  fn radius(&self) -> f64 {
+    self.radius
+  }
+
+  fn evaluate(&self, x: f64, y: f64) -> f64 {
+    self.lanczos(x) * self.lanczos(y)
+  }
+}
+
+fn sinc(x: f64) -> f64 {
+  if x.abs() < 1e-5 {
+    return 1.0;
+  }
+  let x = Common::PI * x;
+  x.sin() / x
+}
\ No newline at end of file
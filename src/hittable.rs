@@ -1,27 +1,73 @@
-use crate::{interval::Interval, material::Material, ray::Ray, vec3::{Point3, Vec3}};
+use crate::{aabb::Aabb, interval::Interval, material::Material, onb::Onb, ray::Ray, sampler::PixelSampler, vec3::{Point3, Vec3}};
 
+#[derive(Copy, Clone)]
 pub struct HitRecord<'a> {
   pub p: Point3,
+  // Normal used for shading, which interpolated vertex normals and normal or bump maps bend away from
+  // the true normal of the surface in geometric_normal. Both face against the ray
   pub normal: Vec3,
-  pub material: &'a Box<dyn Material>,
+  pub geometric_normal: Vec3,
+  // Derivatives of p with respect to the texture coordinates u and v, which orient tangent space
+  pub dpdu: Vec3,
+  pub dpdv: Vec3,
+  pub material: &'a dyn Material,
   pub t: f64,
+  pub u: f64,
+  pub v: f64,
   pub front_face: bool,
 }
 
 impl<'a> HitRecord<'a> {
+  // Set both normals from the geometric normal pointing out of the surface
   pub fn set_face_normal(&self, ray: &Ray, outward_normal: Vec3) -> HitRecord<'a> {
     let front_face = ray.direction.dot(&outward_normal) < 0.0;
     let normal = if front_face { outward_normal } else { -outward_normal };
     HitRecord {
-      p: self.p,
-      t: self.t,
-      material: self.material,
       normal,
+      geometric_normal: normal,
       front_face,
+      ..*self
     }
   }
+
+  // Replace the shading normal, turning it to the same side as the geometric normal
+  pub fn set_shading_normal(&self, normal: Vec3) -> HitRecord<'a> {
+    let normal = normal.unit_vector();
+    HitRecord {
+      normal: if normal.dot(&self.geometric_normal) < 0.0 { -normal } else { normal },
+      ..*self
+    }
+  }
+
+  // Orthonormal frame around the shading normal, with its first axis along dpdu
+  pub fn shading_frame(&self) -> Onb {
+    Onb::new_with_tangent(&self.normal, &self.dpdu)
+  }
+
+  // Whether light scattering from incoming into scattered stays on the same side of the surface, or
+  // passes through it, by both the shading and the geometric normal. Where they disagree a bent shading
+  // normal would let light leak through the surface
+  pub fn sides_agree(&self, incoming: &Vec3, scattered: &Vec3) -> bool {
+    let shading = incoming.dot(&self.normal) * scattered.dot(&self.normal);
+    let geometric = incoming.dot(&self.geometric_normal) * scattered.dot(&self.geometric_normal);
+    (shading < 0.0) == (geometric < 0.0)
+  }
 }
 
-pub trait Hittable {
-  fn hit(&self, ray: &Ray, ray_t: &Interval) -> (bool, Option<HitRecord>);
+pub trait Hittable: Send + Sync {
+  fn hit(&self, ray: &Ray, ray_t: &Interval) -> (bool, Option<HitRecord<'_>>);
+
+  fn bounding_box(&self) -> Aabb;
+
+  // Density, with respect to solid angle, of sampling the given direction from origin with random,
+  // zero for objects that can't be sampled as lights. Moving objects are sampled where they are at the
+  // time of the ray leaving origin
+  fn pdf_value(&self, _origin: &Point3, _direction: &Vec3, _time: f64) -> f64 {
+    0.0
+  }
+
+  // Random direction from origin towards a point on the object, drawn from the sampler
+  fn random(&self, _origin: &Point3, _time: f64, _sampler: &mut PixelSampler) -> Vec3 {
+    Vec3::new(1.0, 0.0, 0.0)
+  }
 }
\ No newline at end of file
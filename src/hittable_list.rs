@@ -1,4 +1,4 @@
-use crate::{hittable::{HitRecord, Hittable}, interval::Interval, ray::Ray};
+use crate::{aabb::Aabb, hittable::{HitRecord, Hittable}, interval::Interval, ray::Ray, sampler::PixelSampler, vec3::{Point3, Vec3}};
 
 pub struct HittableList {
   pub objects: Vec<Box<dyn Hittable>>
@@ -12,8 +12,8 @@ impl HittableList {
   }
 }
 
-impl Hittable for &HittableList {
-  fn hit(&self, ray: &Ray, ray_t: &Interval) -> (bool, Option<HitRecord>) {
+impl Hittable for HittableList {
+  fn hit(&self, ray: &Ray, ray_t: &Interval) -> (bool, Option<HitRecord<'_>>) {
     let mut hit_anything = false;
     let mut closest_so_far = ray_t.max;
     let mut rec: Option<HitRecord> = None;
@@ -29,6 +29,28 @@ impl Hittable for &HittableList {
       }
     }
 
-    return (hit_anything, rec);
+    (hit_anything, rec)
+  }
+
+  fn bounding_box(&self) -> Aabb {
+    self.objects.iter()
+      .fold(Aabb::EMPTY, |bbox, object| Aabb::new_from_boxes(&bbox, &object.bounding_box()))
+  }
+
+  // Every object is picked with equal probability, so the density is the average of theirs
+  fn pdf_value(&self, origin: &Point3, direction: &Vec3, time: f64) -> f64 {
+    if self.objects.is_empty() {
+      return 0.0;
+    }
+    let sum: f64 = self.objects.iter().map(|object| object.pdf_value(origin, direction, time)).sum();
+    sum / self.objects.len() as f64
+  }
+
+  fn random(&self, origin: &Point3, time: f64, sampler: &mut PixelSampler) -> Vec3 {
+    if self.objects.is_empty() {
+      return Vec3::new(1.0, 0.0, 0.0);
+    }
+    let index = ((sampler.get_1d() * self.objects.len() as f64) as usize).min(self.objects.len() - 1);
+    self.objects[index].random(origin, time, sampler)
   }
 }
\ No newline at end of file
@@ -0,0 +1,311 @@
+use std::{fmt, fs::{self, File}, io::{self, BufReader, BufWriter, Write}, path::Path};
+
+use crate::{color::Color, openexr::{self, ExrPrecision}, radiance, tone_mapping::DisplayTransform};
+
+#[derive(Debug)]
+pub enum ImageError {
+  Io(io::Error),
+  Format(String),
+}
+
+impl fmt::Display for ImageError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      ImageError::Io(err) => write!(f, "{}", err),
+      ImageError::Format(message) => write!(f, "{}", message),
+    }
+  }
+}
+
+impl std::error::Error for ImageError {}
+
+impl From<io::Error> for ImageError {
+  fn from(err: io::Error) -> Self {
+    ImageError::Io(err)
+  }
+}
+
+#[derive(Copy, Clone)]
+pub enum PngBitDepth {
+  Eight,
+  Sixteen,
+}
+
+#[derive(Copy, Clone)]
+pub enum ImageFormat {
+  Png(PngBitDepth),
+  // Binary PPM (P6) with 8 bits per channel
+  Ppm,
+  // Portable float map holding the unclamped linear colors
+  Pfm,
+  Exr(ExrPrecision),
+  // Radiance RGBE
+  Hdr,
+}
+
+impl ImageFormat {
+  // Pick the format matching the extension of the path, using 8-bit PNG for .png files and
+  // half precision for .exr files
+  pub fn from_path<P: AsRef<Path>>(path: P) -> Result<ImageFormat, ImageError> {
+    match extension(path.as_ref()).as_str() {
+      "png" => Ok(ImageFormat::Png(PngBitDepth::Eight)),
+      "ppm" => Ok(ImageFormat::Ppm),
+      "pfm" => Ok(ImageFormat::Pfm),
+      "exr" => Ok(ImageFormat::Exr(ExrPrecision::Half)),
+      "hdr" => Ok(ImageFormat::Hdr),
+      other => Err(ImageError::Format(format!("unsupported image format '{}'", other))),
+    }
+  }
+}
+
+// A buffer of linear RGB colors, stored row by row from the top-left corner
+pub struct Image {
+  pub width: usize,
+  pub height: usize,
+  pub pixels: Vec<Color>,
+}
+
+impl Image {
+  pub fn new(width: usize, height: usize) -> Image {
+    Image {
+      width,
+      height,
+      pixels: vec![Color::new(0.0, 0.0, 0.0); width * height],
+    }
+  }
+
+  pub fn pixel(&self, x: usize, y: usize) -> Color {
+    self.pixels[y * self.width + x]
+  }
+
+  pub fn set_pixel(&mut self, x: usize, y: usize, color: Color) {
+    self.pixels[y * self.width + x] = color;
+  }
+
+  // Write the image in the format given by the extension of the path, with the default display
+  // transform for low dynamic range formats
+  pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), ImageError> {
+    let format = ImageFormat::from_path(&path)?;
+    self.save_as(path, format, &DisplayTransform::default())
+  }
+
+  // Write the image in the given format. The display transform is only applied to the low dynamic
+  // range formats, PNG and PPM, while the other formats store the linear colors as they are.
+  pub fn save_as<P: AsRef<Path>>(&self, path: P, format: ImageFormat, display: &DisplayTransform) -> Result<(), ImageError> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    match format {
+      ImageFormat::Png(bit_depth) => self.write_png(&mut writer, bit_depth, display)?,
+      ImageFormat::Ppm => self.write_ppm(&mut writer, display)?,
+      ImageFormat::Pfm => self.write_pfm(&mut writer)?,
+      ImageFormat::Exr(precision) => openexr::write_exr(&mut writer, &[("", self)], precision)?,
+      ImageFormat::Hdr => radiance::write_hdr(self, &mut writer)?,
+    }
+    writer.flush()?;
+    Ok(())
+  }
+
+  pub fn write_png<W: Write>(&self, writer: W, bit_depth: PngBitDepth, display: &DisplayTransform) -> Result<(), ImageError> {
+    let mut encoder = png::Encoder::new(writer, self.width as u32, self.height as u32);
+    encoder.set_color(png::ColorType::Rgb);
+
+    let data: Vec<u8> = match bit_depth {
+      PngBitDepth::Eight => {
+        encoder.set_depth(png::BitDepth::Eight);
+        self.pixels.iter().flat_map(|pixel| display.apply(*pixel).quantize(255).map(|c| c as u8)).collect()
+      }
+      PngBitDepth::Sixteen => {
+        encoder.set_depth(png::BitDepth::Sixteen);
+        self.pixels.iter().flat_map(|pixel| display.apply(*pixel).quantize(65535)).flat_map(|c| (c as u16).to_be_bytes()).collect()
+      }
+    };
+
+    let mut writer = encoder.write_header().map_err(png_error)?;
+    writer.write_image_data(&data).map_err(png_error)?;
+    writer.finish().map_err(png_error)
+  }
+
+  pub fn write_ppm<W: Write>(&self, mut writer: W, display: &DisplayTransform) -> Result<(), ImageError> {
+    write!(writer, "P6\n{} {}\n255\n", self.width, self.height)?;
+    let data: Vec<u8> = self.pixels.iter().flat_map(|pixel| display.apply(*pixel).quantize(255).map(|c| c as u8)).collect();
+    writer.write_all(&data)?;
+    Ok(())
+  }
+
+  pub fn write_pfm<W: Write>(&self, mut writer: W) -> Result<(), ImageError> {
+    // A negative scale marks little-endian data, and rows are stored from the bottom up
+    write!(writer, "PF\n{} {}\n-1.0\n", self.width, self.height)?;
+    let mut data = Vec::with_capacity(self.width * self.height * 12);
+    for row in self.pixels.chunks(self.width.max(1)).rev() {
+      for pixel in row {
+        for c in [pixel.x, pixel.y, pixel.z] {
+          data.extend_from_slice(&(c as f32).to_le_bytes());
+        }
+      }
+    }
+    writer.write_all(&data)?;
+    Ok(())
+  }
+
+  // Load a PNG, PPM (P3/P6), Radiance HDR or OpenEXR file, chosen by extension. PNG and PPM values
+  // are converted from sRGB to linear color, the HDR formats already store linear values
+  pub fn load<P: AsRef<Path>>(path: P) -> Result<Image, ImageError> {
+    let path = path.as_ref();
+    let image = Image::load_raw(path)?;
+    match extension(path).as_str() {
+      "png" | "ppm" => Ok(Image {
+        pixels: image.pixels.iter().map(|pixel| pixel.srgb_to_linear()).collect(),
+        ..image
+      }),
+      _ => Ok(image),
+    }
+  }
+
+  // Load an image as stored, without decoding sRGB, for data such as normal and height maps
+  pub fn load_raw<P: AsRef<Path>>(path: P) -> Result<Image, ImageError> {
+    let path = path.as_ref();
+    match extension(path).as_str() {
+      "png" => Image::load_png(path),
+      "ppm" => Image::load_ppm(path),
+      "hdr" => radiance::read_hdr(BufReader::new(File::open(path)?)),
+      "exr" => openexr::load_exr(path),
+      other => Err(ImageError::Format(format!("unsupported image format '{}'", other))),
+    }
+  }
+
+  fn load_png(path: &Path) -> Result<Image, ImageError> {
+    let mut decoder = png::Decoder::new(File::open(path)?);
+    decoder.set_transformations(png::Transformations::EXPAND);
+    let mut reader = decoder.read_info().map_err(png_error)?;
+    let mut buffer = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut buffer).map_err(png_error)?;
+    let bytes = &buffer[..info.buffer_size()];
+
+    let channels = info.color_type.samples();
+    let samples: Vec<f64> = match info.bit_depth {
+      png::BitDepth::Sixteen => bytes.chunks_exact(2)
+        .map(|b| u16::from_be_bytes([b[0], b[1]]) as f64 / 65535.0)
+        .collect(),
+      _ => bytes.iter().map(|&b| b as f64 / 255.0).collect(),
+    };
+
+    let pixels = samples.chunks_exact(channels)
+      .map(|s| match channels {
+        1 | 2 => Color::new(s[0], s[0], s[0]),
+        _ => Color::new(s[0], s[1], s[2]),
+      })
+      .collect();
+
+    Ok(Image {
+      width: info.width as usize,
+      height: info.height as usize,
+      pixels,
+    })
+  }
+
+  fn load_ppm(path: &Path) -> Result<Image, ImageError> {
+    let bytes = fs::read(path)?;
+    let mut pos = 0;
+    let magic = next_token(&bytes, &mut pos)?;
+    let width = parse_header_value(next_token(&bytes, &mut pos)?)?;
+    let height = parse_header_value(next_token(&bytes, &mut pos)?)?;
+    let max_value = parse_header_value(next_token(&bytes, &mut pos)?)? as f64;
+
+    let samples: Vec<f64> = match magic.as_str() {
+      "P3" => (0..width * height * 3)
+        .map(|_| Ok(parse_header_value(next_token(&bytes, &mut pos)?)? as f64 / max_value))
+        .collect::<Result<_, ImageError>>()?,
+      "P6" => {
+        // A single whitespace byte separates the header from the binary data
+        let data = bytes.get(pos + 1..).unwrap_or(&[]);
+        let sample_size = if max_value < 256.0 { 1 } else { 2 };
+        if data.len() < width * height * 3 * sample_size {
+          return Err(ImageError::Format(String::from("PPM pixel data is truncated")));
+        }
+        data.chunks_exact(sample_size)
+          .take(width * height * 3)
+          .map(|b| if sample_size == 1 { b[0] as f64 } else { u16::from_be_bytes([b[0], b[1]]) as f64 } / max_value)
+          .collect()
+      }
+      _ => return Err(ImageError::Format(format!("unsupported PPM variant '{}'", magic))),
+    };
+
+    let pixels = samples.chunks_exact(3)
+      .map(|s| Color::new(s[0], s[1], s[2]))
+      .collect();
+
+    Ok(Image {
+      width,
+      height,
+      pixels,
+    })
+  }
+}
+
+fn png_error<E: fmt::Display>(err: E) -> ImageError {
+  ImageError::Format(err.to_string())
+}
+
+pub(crate) fn extension(path: &Path) -> String {
+  path.extension()
+    .and_then(|ext| ext.to_str())
+    .unwrap_or("")
+    .to_ascii_lowercase()
+}
+
+// Read the next whitespace separated token of a PPM header, skipping comments
+fn next_token(bytes: &[u8], pos: &mut usize) -> Result<String, ImageError> {
+  loop {
+    while *pos < bytes.len() && bytes[*pos].is_ascii_whitespace() {
+      *pos += 1;
+    }
+    if *pos < bytes.len() && bytes[*pos] == b'#' {
+      while *pos < bytes.len() && bytes[*pos] != b'\n' {
+        *pos += 1;
+      }
+      continue;
+    }
+    break;
+  }
+
+  let start = *pos;
+  while *pos < bytes.len() && !bytes[*pos].is_ascii_whitespace() {
+    *pos += 1;
+  }
+  if start == *pos {
+    return Err(ImageError::Format(String::from("unexpected end of PPM file")));
+  }
+  Ok(String::from_utf8_lossy(&bytes[start..*pos]).into_owned())
+}
+
+fn parse_header_value(token: String) -> Result<usize, ImageError> {
+  token.parse().map_err(|_| ImageError::Format(format!("invalid PPM value '{}'", token)))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn pfm_round_trips_linear_colors() {
+    let mut image = Image::new(3, 2);
+    for j in 0..2 {
+      for i in 0..3 {
+        image.set_pixel(i, j, Color::new(i as f64 * 1.5, -(j as f64), 1000.25 + (i + 3 * j) as f64));
+      }
+    }
+    let mut data = vec![];
+    image.write_pfm(&mut data).unwrap();
+
+    let header = b"PF\n3 2\n-1.0\n";
+    assert_eq!(&data[..header.len()], header);
+    let values: Vec<f64> = data[header.len()..].chunks(4)
+      .map(|bytes| f32::from_le_bytes(bytes.try_into().unwrap()) as f64)
+      .collect();
+    assert_eq!(values.len(), 3 * 2 * 3);
+    // Rows are stored from the bottom up
+    for (index, rgb) in values.chunks(3).enumerate() {
+      let pixel = image.pixel(index % 3, 1 - index / 3);
+      assert_eq!(rgb, [pixel.x, pixel.y, pixel.z]);
+    }
+  }
+}
\ No newline at end of file
@@ -0,0 +1,80 @@
+use std::sync::Arc;
+
+use crate::{aabb::Aabb, hittable::{HitRecord, Hittable}, interval::Interval, material::Material, ray::Ray, sampler::PixelSampler, transform::Transform, vec3::{Point3, Vec3}};
+
+// A shared object placed in the world by a transform, optionally with a material of its own
+pub struct Instance {
+  object: Arc<dyn Hittable>,
+  transform: Transform,
+  material: Option<Arc<dyn Material>>,
+  bbox: Aabb,
+}
+
+impl Instance {
+  pub fn new(object: Arc<dyn Hittable>, transform: Transform) -> Instance {
+    let bbox = object.bounding_box();
+    // Keep unbounded objects unbounded, transforming infinite corners would produce NaNs
+    let bbox = if bbox.is_bounded() { transform.bounding_box(&bbox) } else { Aabb::UNIVERSE };
+    Instance {
+      object,
+      transform,
+      material: None,
+      bbox,
+    }
+  }
+
+  // Place the object with a material replacing the ones it was built with
+  pub fn new_with_material(object: Arc<dyn Hittable>, transform: Transform, material: Arc<dyn Material>) -> Instance {
+    Instance {
+      material: Some(material),
+      ..Instance::new(object, transform)
+    }
+  }
+}
+
+impl Hittable for Instance {
+  fn hit(&self, ray: &Ray, ray_t: &Interval) -> (bool, Option<HitRecord<'_>>) {
+    // Move the ray into object space, leaving the direction unnormalized so that t stays the same
+    let inverse = self.transform.inverse();
+    let object_ray = ray.redirected(inverse.point(&ray.origin), inverse.vector(&ray.direction));
+
+    let (hit, rec) = self.object.hit(&object_ray, ray_t);
+    if !hit {
+      return (false, None);
+    }
+
+    // Move the hit back into world space, which keeps the normal facing against the ray
+    let rec = rec.unwrap();
+    let rec = HitRecord {
+      p: self.transform.point(&rec.p),
+      normal: self.transform.normal(&rec.normal).unit_vector(),
+      geometric_normal: self.transform.normal(&rec.geometric_normal).unit_vector(),
+      dpdu: self.transform.vector(&rec.dpdu),
+      dpdv: self.transform.vector(&rec.dpdv),
+      material: self.material.as_deref().unwrap_or(rec.material),
+      ..rec
+    };
+    (true, Some(rec))
+  }
+
+  fn bounding_box(&self) -> Aabb {
+    self.bbox
+  }
+
+  // Light sampling densities carry over as long as the transform preserves solid angles
+  fn pdf_value(&self, origin: &Point3, direction: &Vec3, time: f64) -> f64 {
+    if !self.transform.is_similarity() {
+      return 0.0;
+    }
+    let inverse = self.transform.inverse();
+    self.object.pdf_value(&inverse.point(origin), &inverse.vector(direction), time)
+  }
+
+  fn random(&self, origin: &Point3, time: f64, sampler: &mut PixelSampler) -> Vec3 {
+    if !self.transform.is_similarity() {
+      return Vec3::new(1.0, 0.0, 0.0);
+    }
+    let inverse = self.transform.inverse();
+    self.transform.vector(&self.object.random(&inverse.point(origin), time, sampler))
+  }
+}
\ No newline at end of file
@@ -1,5 +1,6 @@
 use crate::common::Common;
 
+#[derive(Copy, Clone)]
 pub struct Interval {
   pub min: f64,
   pub max: f64,
@@ -30,6 +31,23 @@ impl Interval {
     }
   }
 
+  // Create the interval tightly enclosing the two input intervals
+  pub fn new_from_intervals(a: &Interval, b: &Interval) -> Interval {
+    Interval {
+      min: a.min.min(b.min),
+      max: a.max.max(b.max),
+    }
+  }
+
+  pub fn size(&self) -> f64 {
+    self.max - self.min
+  }
+
+  pub fn expand(&self, delta: f64) -> Interval {
+    let padding = delta / 2.0;
+    Interval::new_from_range(self.min - padding, self.max + padding)
+  }
+
   pub fn contains(&self, x: f64) -> bool {
     self.min <= x && x <= self.max
   }
@@ -45,6 +63,12 @@ impl Interval {
     if x > self.max {
       return self.max;
     }
-    return x;
+    x
+  }
+}
+
+impl Default for Interval {
+  fn default() -> Self {
+    Interval::new()
   }
 }
\ No newline at end of file
@@ -7,4 +7,38 @@ pub mod hittable_list;
 pub mod common;
 pub mod interval;
 pub mod camera;
-pub mod material;
\ No newline at end of file
+pub mod material;
+pub mod aabb;
+pub mod bvh;
+pub mod triangle;
+pub mod mesh;
+pub mod obj;
+pub mod texture;
+pub mod perlin;
+pub mod image;
+pub mod openexr;
+pub mod radiance;
+pub mod tone_mapping;
+pub mod background;
+pub mod scene;
+pub mod onb;
+pub mod pdf;
+pub mod quad;
+pub mod disk;
+pub mod plane;
+pub mod transform;
+pub mod instance;
+pub mod motion;
+pub mod constant_medium;
+pub mod atmosphere;
+pub mod distribution;
+pub mod environment;
+pub mod sky;
+pub mod light;
+pub mod microfacet;
+pub mod principled;
+pub mod normal_map;
+pub mod spectrum;
+pub mod sampler;
+pub mod filter;
+pub mod film;
\ No newline at end of file
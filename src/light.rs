@@ -0,0 +1,283 @@
+use crate::{color::Color, common::Common, hittable_list::HittableList, onb::Onb, sampler::PixelSampler, spectrum::{self, Spectrum, Wavelengths}, vec3::{Point3, Vec3}};
+
+// Light arriving at a point from a punctual light
+pub struct LightSample {
+  // Unit vector from the point towards the light
+  pub direction: Vec3,
+  // Distance to the light, infinite for directional lights
+  pub distance: f64,
+  // Irradiance on a surface facing the light, before any shadowing
+  pub irradiance: Color,
+}
+
+// Light from a single point or direction, which rays can never hit, so it is only reached by shadow
+// rays cast towards it
+pub trait Light: Send + Sync {
+  // Sample the light as seen from p, or None if no light reaches p. Lights with an extent draw the
+  // point on them from the sampler
+  fn sample(&self, p: &Point3, sampler: &mut PixelSampler) -> Option<LightSample>;
+
+  // Sample the light with its irradiance at the wavelengths of a spectral path, upsampled from RGB
+  // unless the light has a spectrum of its own
+  fn sample_spectral(&self, p: &Point3, wavelengths: &Wavelengths, sampler: &mut PixelSampler) -> Option<LightSample> {
+    self.sample(p, sampler).map(|sample| LightSample {
+      irradiance: spectrum::upsample(sample.irradiance, wavelengths),
+      ..sample
+    })
+  }
+}
+
+// Everything that emits light in a scene: objects with an emissive material, sampled by solid angle,
+// and punctual lights
+pub struct Lights {
+  pub objects: HittableList,
+  pub punctual: Vec<Box<dyn Light>>,
+}
+
+impl Lights {
+  pub fn new() -> Lights {
+    Lights {
+      objects: HittableList { objects: vec![] },
+      punctual: vec![],
+    }
+  }
+}
+
+impl Default for Lights {
+  fn default() -> Self {
+    Lights::new()
+  }
+}
+
+// Light radiating equally in all directions from a point, falling off with the square of the distance
+pub struct PointLight {
+  position: Point3,
+  // Radiant intensity, the power per unit solid angle
+  intensity: Color,
+}
+
+impl PointLight {
+  pub fn new(position: Point3, intensity: Color) -> PointLight {
+    PointLight { position, intensity }
+  }
+}
+
+impl Light for PointLight {
+  fn sample(&self, p: &Point3, _sampler: &mut PixelSampler) -> Option<LightSample> {
+    let to_light = self.position - *p;
+    let distance_squared = to_light.length_squared();
+    if distance_squared == 0.0 {
+      return None;
+    }
+
+    let distance = distance_squared.sqrt();
+    Some(LightSample {
+      direction: to_light / distance,
+      distance,
+      irradiance: self.intensity / distance_squared,
+    })
+  }
+}
+
+// Point light shining only into a cone. It has full intensity within the inner angle and fades out
+// smoothly towards the outer angle, with the falloff exponent shaping the transition
+pub struct SpotLight {
+  position: Point3,
+  direction: Vec3,
+  intensity: Color,
+  cos_inner: f64,
+  cos_outer: f64,
+  falloff: f64,
+}
+
+impl SpotLight {
+  // The angles are measured from the spot's axis in degrees
+  pub fn new(position: Point3, direction: Vec3, intensity: Color, inner_angle: f64, outer_angle: f64) -> SpotLight {
+    let outer_angle = outer_angle.clamp(0.0, 180.0);
+    SpotLight {
+      position,
+      direction: direction.unit_vector(),
+      intensity,
+      cos_inner: Common::degrees_to_radians(inner_angle.clamp(0.0, outer_angle)).cos(),
+      cos_outer: Common::degrees_to_radians(outer_angle).cos(),
+      falloff: 1.0,
+    }
+  }
+
+  pub fn set_falloff(&mut self, falloff: f64) {
+    self.falloff = falloff.max(0.0);
+  }
+
+  fn attenuation(&self, cos_theta: f64) -> f64 {
+    if cos_theta >= self.cos_inner {
+      return 1.0;
+    }
+    if cos_theta <= self.cos_outer {
+      return 0.0;
+    }
+
+    let x = (cos_theta - self.cos_outer) / (self.cos_inner - self.cos_outer);
+    (x * x * (3.0 - 2.0 * x)).powf(self.falloff)
+  }
+}
+
+impl Light for SpotLight {
+  fn sample(&self, p: &Point3, _sampler: &mut PixelSampler) -> Option<LightSample> {
+    let to_light = self.position - *p;
+    let distance_squared = to_light.length_squared();
+    if distance_squared == 0.0 {
+      return None;
+    }
+
+    let distance = distance_squared.sqrt();
+    let direction = to_light / distance;
+    let attenuation = self.attenuation(-direction.dot(&self.direction));
+    if attenuation <= 0.0 {
+      return None;
+    }
+
+    Some(LightSample {
+      direction,
+      distance,
+      irradiance: self.intensity * (attenuation / distance_squared),
+    })
+  }
+}
+
+// Light from infinitely far away, such as the sun. With a nonzero angular diameter the direction is
+// sampled within the light's disk, which gives soft shadows
+pub struct DirectionalLight {
+  // Direction the light travels in
+  direction: Vec3,
+  irradiance: Color,
+  cos_radius: f64,
+}
+
+impl DirectionalLight {
+  pub fn new(direction: Vec3, irradiance: Color) -> DirectionalLight {
+    DirectionalLight {
+      direction: direction.unit_vector(),
+      irradiance,
+      cos_radius: 1.0,
+    }
+  }
+
+  // Angular diameter of the light's disk in degrees, 0.53 for the sun
+  pub fn set_angular_diameter(&mut self, degrees: f64) {
+    self.cos_radius = Common::degrees_to_radians(degrees.clamp(0.0, 180.0) / 2.0).cos();
+  }
+}
+
+impl Light for DirectionalLight {
+  fn sample(&self, _p: &Point3, sampler: &mut PixelSampler) -> Option<LightSample> {
+    let to_light = -self.direction;
+    let direction = if self.cos_radius < 1.0 {
+      let (u1, u2) = sampler.get_2d();
+      let z = 1.0 + u1 * (self.cos_radius - 1.0);
+      let phi = 2.0 * Common::PI * u2;
+      let r = (1.0 - z * z).max(0.0).sqrt();
+      Onb::new(&to_light).local(&Vec3::new(r * phi.cos(), r * phi.sin(), z))
+    } else {
+      to_light
+    };
+
+    Some(LightSample {
+      direction,
+      distance: Common::INFINITY,
+      irradiance: self.irradiance,
+    })
+  }
+}
+
+// Punctual light emitting the given spectrum, such as a black body, with the color of the wrapped
+// light tinting and scaling it
+pub struct SpectralLight {
+  light: Box<dyn Light>,
+  spectrum: Spectrum,
+}
+
+impl SpectralLight {
+  pub fn new(light: Box<dyn Light>, spectrum: Spectrum) -> SpectralLight {
+    SpectralLight { light, spectrum }
+  }
+}
+
+impl Light for SpectralLight {
+  fn sample(&self, p: &Point3, sampler: &mut PixelSampler) -> Option<LightSample> {
+    self.light.sample(p, sampler).map(|sample| LightSample {
+      irradiance: sample.irradiance * self.spectrum.rgb(),
+      ..sample
+    })
+  }
+
+  fn sample_spectral(&self, p: &Point3, wavelengths: &Wavelengths, sampler: &mut PixelSampler) -> Option<LightSample> {
+    self.light.sample_spectral(p, wavelengths, sampler).map(|sample| LightSample {
+      irradiance: sample.irradiance * self.spectrum.values(wavelengths),
+      ..sample
+    })
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::pdf::tests::sampler;
+
+  // Point at an angle in degrees from the downward axis of a spot at the origin
+  fn below(angle: f64, distance: f64) -> Point3 {
+    let angle = Common::degrees_to_radians(angle);
+    Point3::new(angle.sin(), -angle.cos(), 0.0) * distance
+  }
+
+  fn irradiance(light: &dyn Light, p: &Point3) -> f64 {
+    light.sample(p, &mut sampler(0)).map_or(0.0, |sample| sample.irradiance.y)
+  }
+
+  #[test]
+  fn point_lights_fall_off_with_the_square_of_the_distance() {
+    let light = PointLight::new(Point3::new(1.0, 2.0, 3.0), Color::new(8.0, 4.0, 2.0));
+    for (offset, distance) in [(Vec3::new(0.0, -1.0, 0.0), 1.0), (Vec3::new(2.0, 0.0, 0.0), 2.0), (Vec3::new(0.0, 2.4, -3.2), 4.0)] {
+      let sample = light.sample(&(Point3::new(1.0, 2.0, 3.0) + offset), &mut sampler(0)).unwrap();
+      assert!((sample.distance - distance).abs() < 1e-12);
+      assert!((sample.direction + offset / distance).length() < 1e-12);
+      let expected = Color::new(8.0, 4.0, 2.0) / (distance * distance);
+      assert!((sample.irradiance - expected).length() < 1e-12, "irradiance {} at {}", sample.irradiance.x, distance);
+    }
+    assert!(light.sample(&Point3::new(1.0, 2.0, 3.0), &mut sampler(0)).is_none());
+  }
+
+  #[test]
+  fn spot_lights_fade_between_the_inner_and_outer_angles() {
+    let spot = SpotLight::new(Point3::new(0.0, 0.0, 0.0), Vec3::new(0.0, -2.0, 0.0), Color::new(2.0, 2.0, 2.0), 20.0, 40.0);
+
+    // Full intensity inside the inner cone, falling off with the square of the distance
+    for angle in [0.0, 10.0, 19.9] {
+      for distance in [1.0, 3.0] {
+        let value = irradiance(&spot, &below(angle, distance));
+        assert!((value - 2.0 / (distance * distance)).abs() < 1e-12, "{} at {} degrees", value, angle);
+      }
+    }
+
+    // Nothing outside the outer cone or behind the spot
+    for angle in [40.1, 90.0, 180.0] {
+      assert!(spot.sample(&below(angle, 1.0), &mut sampler(0)).is_none(), "lit at {} degrees", angle);
+    }
+
+    // In between it fades smoothly and steadily from one to zero
+    let mut previous = 2.0;
+    for i in 1..100 {
+      let value = irradiance(&spot, &below(20.0 + 0.2 * i as f64, 1.0));
+      assert!(value > 0.0 && value < previous, "{} at {} degrees", value, 20.0 + 0.2 * i as f64);
+      previous = value;
+    }
+    assert!((irradiance(&spot, &below(20.01, 1.0)) - 2.0).abs() < 1e-3);
+    assert!(irradiance(&spot, &below(39.99, 1.0)) < 1e-5);
+
+    // Halfway between the cosines of the angles the smoothstep is a half, raised to the falloff
+    let mut spot = spot;
+    let halfway = ((spot.cos_inner + spot.cos_outer) / 2.0).acos().to_degrees();
+    assert!((irradiance(&spot, &below(halfway, 1.0)) - 1.0).abs() < 1e-12);
+    spot.set_falloff(2.0);
+    assert!((irradiance(&spot, &below(halfway, 1.0)) - 0.5).abs() < 1e-12);
+  }
+}
\ No newline at end of file
@@ -1,16 +1,42 @@
-use rust_ray_tracer::{camera::Camera, color::Color, common::Common, hittable::Hittable, hittable_list::HittableList, material::{Dielectric, Lambertian, Metal}, sphere::Sphere, vec3::{Point3, Vec3}};
+use std::{env, process, sync::Arc};
+
+use rust_ray_tracer::{atmosphere::Atmosphere, bvh::BvhNode, camera::Camera, color::Color, common::Common, hittable::Hittable, hittable_list::HittableList, light::Lights, material::{Dielectric, Lambertian, Metal}, plane::Plane, scene::Scene, sphere::Sphere, vec3::{Point3, Vec3}};
 
 
 fn main() {
+	let args: Vec<String> = env::args().collect();
+	match args.get(1) {
+		Some(path) => render_scene_file(path),
+		None => render_random_spheres(),
+	}
+}
+
+fn render_scene_file(path: &str) {
+	let scene = match Scene::load(path) {
+		Ok(scene) => scene,
+		Err(err) => {
+			eprintln!("{}: {}", path, err);
+			process::exit(1);
+		}
+	};
+
+	let world = BvhNode::new(scene.world);
+	if let Err(err) = scene.output.render(&scene.camera, &world, &scene.lights) {
+		eprintln!("could not write '{}': {}", scene.output.path, err);
+		process::exit(1);
+	}
+}
+
+fn render_random_spheres() {
 	// World
 
 	let material_ground = Lambertian::new(Color::new(0.5, 0.5, 0.5));
 
-	let sphere_ground = Box::new(Sphere::new(
-		Point3::new(0.0,-1000.0,0.0),
-		1000.0, Box::new(material_ground)));
+	let plane_ground = Box::new(Plane::new(
+		Point3::new(0.0, 0.0, 0.0),
+		Vec3::new(0.0, 1.0, 0.0), Arc::new(material_ground)));
 
-	let mut objects: Vec<Box<(dyn Hittable + 'static)>> = vec![sphere_ground];
+	let mut objects: Vec<Box<dyn Hittable>> = vec![plane_ground];
 
 	for i in -11..10 {
 		for j in -11..10 {
@@ -22,7 +48,7 @@ fn main() {
 					// diffuse
 					let albedo = Color::random() * Color::random();
 					let sphere_material = Lambertian::new(albedo);
-					let sphere = Sphere::new(center, 0.2, Box::new(sphere_material));
+					let sphere = Sphere::new(center, 0.2, Arc::new(sphere_material));
 					objects.push(Box::new(sphere));
 				}
 				else if choose_mat < 0.95 {
@@ -30,12 +56,12 @@ fn main() {
 					let albedo = Color::random_in_range(0.5, 1.0);
 					let fuzz = Common::random_float_in_range(0.0, 0.5);
 					let sphere_material = Metal::new(albedo, fuzz);
-					let sphere = Sphere::new(center, 0.2, Box::new(sphere_material));
+					let sphere = Sphere::new(center, 0.2, Arc::new(sphere_material));
 					objects.push(Box::new(sphere));
 				} else {
 					// glass
 					let sphere_material = Dielectric::new(1.5);
-					let sphere = Sphere::new(center, 0.2, Box::new(sphere_material));
+					let sphere = Sphere::new(center, 0.2, Arc::new(sphere_material));
 					objects.push(Box::new(sphere));
 				}
 			}
@@ -43,20 +69,20 @@ fn main() {
 	}
 
 	let material1 = Dielectric::new(1.5);
-	let sphere1 = Sphere::new(Point3::new(0.0, 1.0, 0.0), 1.0, Box::new(material1));
+	let sphere1 = Sphere::new(Point3::new(0.0, 1.0, 0.0), 1.0, Arc::new(material1));
 	objects.push(Box::new(sphere1));
 
 	let material2 = Lambertian::new(Point3::new(0.4, 0.2, 0.1));
-	let sphere2 = Sphere::new(Point3::new(-4.0, 1.0, 0.0), 1.0, Box::new(material2));
+	let sphere2 = Sphere::new(Point3::new(-4.0, 1.0, 0.0), 1.0, Arc::new(material2));
 	objects.push(Box::new(sphere2));
 
 	let material3 = Metal::new(Color::new(0.7, 0.6, 0.5), 0.0);
-	let sphere3 = Sphere::new(Point3::new(4.0, 1.0, 0.0), 1.0, Box::new(material3));
+	let sphere3 = Sphere::new(Point3::new(4.0, 1.0, 0.0), 1.0, Arc::new(material3));
 	objects.push(Box::new(sphere3));
 
-	let world = HittableList {
-		objects: objects
-	};
+	let world = BvhNode::new(HittableList {
+		objects
+	});
 
 
 	// Camera
@@ -73,8 +99,18 @@ fn main() {
 	let focus_dist = 10.0;
 	let filename = "testimg.ppm";
 	
-	let camera = Camera::new(aspect_ratio, image_width,
+	let mut camera = Camera::new(aspect_ratio, image_width,
 		samples_per_pixel, max_depth, vfov, look_from,
-		look_at, vup, defocus_angle, focus_dist, filename);
-	camera.render(Box::new(&world));
+		look_at, vup, defocus_angle, focus_dist);
+
+	// Light haze hugging the ground, which fades the spheres towards the horizon
+	let mut atmosphere = Atmosphere::new(0.03, Color::new(0.9, 0.9, 0.9));
+	atmosphere.set_height_falloff(0.5, 0.0);
+	camera.set_atmosphere(atmosphere);
+	let lights = Lights::new();
+	let image = camera.render(&world, &lights);
+	if let Err(err) = image.save(filename) {
+		eprintln!("could not write '{}': {}", filename, err);
+		process::exit(1);
+	}
 }
\ No newline at end of file
@@ -1,15 +1,61 @@
-use crate::{color::Color, common::Common, hittable::HitRecord, ray::Ray, vec3::Vec3};
+use std::sync::Arc;
 
-pub trait Material {
-  fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> (bool, Color, Ray);
+use crate::{color::Color, common::Common, hittable::HitRecord, microfacet::{self, TrowbridgeReitz}, pdf::{CosinePdf, HenyeyGreensteinPdf, MicrofacetDielectricPdf, MicrofacetReflectionPdf, Pdf, SpherePdf}, ray::Ray, sampler::PixelSampler, spectrum::{self, Spectrum, Wavelengths}, texture::{SolidColor, Texture}, vec3::{Point3, Vec3}};
+
+// How light leaves a surface after a hit
+pub enum ScatterRecord {
+  // A single direction, such as a mirror reflection, that can't be importance sampled and is followed as is
+  Specular { attenuation: Color, ray: Ray },
+  // Directions drawn from a density, which the integrator mixes with light sampling and weights by Material::eval
+  Sampled { pdf: Box<dyn Pdf> },
+  // Sampled lobes beside smooth ones, such as those of a layered material. Light sampling covers the
+  // sampled lobes, whose density only counts the samples they are chosen for, and the path follows the
+  // specular direction instead of drawing from the density when a smooth lobe was chosen
+  Mixed { pdf: Box<dyn Pdf>, specular: Option<(Color, Ray)> },
+}
+
+pub trait Material: Send + Sync {
+  // Scatter a ray that hit the surface, drawing any random choices from the sampler
+  fn scatter(&self, r_in: &Ray, rec: &HitRecord, sampler: &mut PixelSampler) -> (bool, Option<ScatterRecord>);
+
+  // BSDF times the cosine to the normal for light arriving along scattered, used for sampled scattering
+  fn eval(&self, _r_in: &Ray, _rec: &HitRecord, _scattered: &Ray) -> Color {
+    Color::new(0.0, 0.0, 0.0)
+  }
+
+  // Normal to shade the hit with, which normal and bump maps bend. It is applied to the record before
+  // scatter and eval are called
+  fn shading_normal(&self, rec: &HitRecord) -> Vec3 {
+    rec.normal
+  }
+
+  // Light emitted by the surface itself, black for materials that don't glow
+  fn emitted(&self, _u: f64, _v: f64, _p: &Point3) -> Color {
+    Color::new(0.0, 0.0, 0.0)
+  }
+
+  // Emitted light at the wavelengths of a spectral path, upsampled from the RGB emission unless the
+  // material has a spectrum of its own
+  fn emitted_spectral(&self, u: f64, v: f64, p: &Point3, wavelengths: &Wavelengths) -> Color {
+    spectrum::upsample(self.emitted(u, v, p), wavelengths)
+  }
+
+  // Base color of the surface for the albedo AOV, white for materials without one
+  fn albedo(&self, _rec: &HitRecord) -> Color {
+    Color::new(1.0, 1.0, 1.0)
+  }
 }
 
 pub struct Lambertian {
-  albedo: Color,
+  albedo: Arc<dyn Texture>,
 }
 
 impl Lambertian {
   pub fn new(albedo: Color) -> Lambertian {
+    Lambertian::new_from_texture(Arc::new(SolidColor::new(albedo)))
+  }
+
+  pub fn new_from_texture(albedo: Arc<dyn Texture>) -> Lambertian {
     Lambertian {
       albedo
     }
@@ -17,25 +63,31 @@ impl Lambertian {
 }
 
 impl Material for Lambertian {
-  fn scatter(&self, _: &Ray, rec: &HitRecord) -> (bool, Color, Ray) {
-    let mut scatter_direction = rec.normal + Vec3::random_unit_vector();
+  fn scatter(&self, _: &Ray, rec: &HitRecord, _: &mut PixelSampler) -> (bool, Option<ScatterRecord>) {
+    (true, Some(ScatterRecord::Sampled { pdf: Box::new(CosinePdf::new(&rec.normal)) }))
+  }
 
-    if scatter_direction.near_zero() {
-      scatter_direction = rec.normal;
-    }
+  fn eval(&self, _: &Ray, rec: &HitRecord, scattered: &Ray) -> Color {
+    let cosine = rec.normal.dot(&scattered.direction.unit_vector()).max(0.0);
+    self.albedo.value(rec.u, rec.v, &rec.p) * (cosine / Common::PI)
+  }
 
-    let scattered = Ray::new(rec.p, scatter_direction);
-    return (true, self.albedo, scattered);
+  fn albedo(&self, rec: &HitRecord) -> Color {
+    self.albedo.value(rec.u, rec.v, &rec.p)
   }
 }
 
 pub struct Metal {
-  albedo: Color,
+  albedo: Arc<dyn Texture>,
   fuzz: f64,
 }
 
 impl Metal {
   pub fn new(albedo: Color, fuzz: f64) -> Metal {
+    Metal::new_from_texture(Arc::new(SolidColor::new(albedo)), fuzz)
+  }
+
+  pub fn new_from_texture(albedo: Arc<dyn Texture>, fuzz: f64) -> Metal {
     Metal {
       albedo,
       fuzz,
@@ -44,48 +96,386 @@ impl Metal {
 }
 
 impl Material for Metal {
-  fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> (bool, Color, Ray) {
+  fn scatter(&self, r_in: &Ray, rec: &HitRecord, sampler: &mut PixelSampler) -> (bool, Option<ScatterRecord>) {
     let reflected = r_in.direction.unit_vector().reflect(&rec.normal);
-    let scattered = Ray::new(rec.p, reflected + Vec3::random_unit_vector() * self.fuzz);
-    return (scattered.direction.dot(&rec.normal) > 0.0, self.albedo, scattered);
+    let scattered = r_in.redirected(rec.p, reflected + Vec3::sample_unit_vector(sampler.get_2d()) * self.fuzz);
+    if scattered.direction.dot(&rec.normal) <= 0.0 {
+      return (false, None);
+    }
+    let attenuation = self.albedo.value(rec.u, rec.v, &rec.p);
+    (true, Some(ScatterRecord::Specular { attenuation, ray: scattered }))
+  }
+
+  fn albedo(&self, rec: &HitRecord) -> Color {
+    self.albedo.value(rec.u, rec.v, &rec.p)
+  }
+}
+
+// Index of refraction that varies with wavelength, in micrometers, which splits white light into its colors
+#[derive(Copy, Clone)]
+pub enum Dispersion {
+  // n = a + b / λ², a simple fit for glasses over the visible range
+  Cauchy { a: f64, b: f64 },
+  // n² = 1 + Σ b_i λ² / (λ² - c_i), the form glass catalogs use, for example b = [1.03961212, 0.231792344,
+  // 1.01046945] and c = [0.00600069867, 0.0200179144, 103.560653] for BK7
+  Sellmeier { b: [f64; 3], c: [f64; 3] },
+}
+
+impl Dispersion {
+  // Index of refraction at a wavelength given in nanometers
+  pub fn ior(&self, wavelength: f64) -> f64 {
+    let lambda2 = (wavelength / 1000.0).powi(2);
+    match self {
+      Dispersion::Cauchy { a, b } => a + b / lambda2,
+      Dispersion::Sellmeier { b, c } => {
+        let sum: f64 = (0..3).map(|i| b[i] * lambda2 / (lambda2 - c[i])).sum();
+        (1.0 + sum).sqrt()
+      }
+    }
   }
 }
 
 pub struct Dielectric {
   ir: f64,
+  // Fraction of light absorbed per unit of distance travelled inside, for each channel
+  absorption: Color,
+  dispersion: Option<Dispersion>,
 }
 
 impl Dielectric {
   pub fn new(ir: f64) -> Dielectric {
     Dielectric {
-      ir
+      ir,
+      absorption: Color::new(0.0, 0.0, 0.0),
+      dispersion: None,
     }
   }
 
+  // Tint the glass by absorbing light as it travels through, following the Beer-Lambert law
+  pub fn set_absorption(&mut self, absorption: Color) {
+    self.absorption = absorption;
+  }
+
+  // Let the index of refraction vary with wavelength. Rays reaching the glass then carry a single
+  // wavelength through the rest of their path: in RGB with a color weight that averages to white,
+  // and spectrally the hero wavelength of the path
+  pub fn set_dispersion(&mut self, dispersion: Dispersion) {
+    self.dispersion = Some(dispersion);
+  }
+
   fn reflectance(cosine: f64, ref_idx: f64) -> f64 {
     let r0 = (1.0 - ref_idx) / (1.0 + ref_idx);
     let r0 = r0*r0;
-    return r0 + (1.0 - r0)*(1.0 - cosine).powi(5);
+    r0 + (1.0 - r0)*(1.0 - cosine).powi(5)
   }
 }
 
 impl Material for Dielectric {
-  fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> (bool, Color, Ray) {
-    let attenuation = Color::new(1.0, 1.0, 1.0);
-    let refraction_ratio = if rec.front_face { 1.0/self.ir } else { self.ir };
+  fn scatter(&self, r_in: &Ray, rec: &HitRecord, sampler: &mut PixelSampler) -> (bool, Option<ScatterRecord>) {
+    // A ray reaching the surface from the inside has travelled through the glass for its whole length
+    let mut attenuation = if rec.front_face {
+      Color::new(1.0, 1.0, 1.0)
+    } else {
+      let distance = rec.t * r_in.direction.length();
+      let a = self.absorption;
+      Color::new((-a.x * distance).exp(), (-a.y * distance).exp(), (-a.z * distance).exp())
+    };
+
+    let mut wavelength = r_in.wavelength;
+    let ir = match (&self.dispersion, r_in.wavelength) {
+      (None, _) => self.ir,
+      (Some(dispersion), Some(wavelength)) => dispersion.ior(wavelength),
+      (Some(dispersion), None) if r_in.wavelengths.is_some() => {
+        let hero = r_in.wavelengths.unwrap().hero();
+        wavelength = Some(hero);
+        dispersion.ior(hero)
+      }
+      (Some(dispersion), None) => {
+        let sampled = spectrum::sample_wavelength(sampler.get_1d());
+        attenuation = attenuation * spectrum::wavelength_weight(sampled);
+        wavelength = Some(sampled);
+        dispersion.ior(sampled)
+      }
+    };
+    let refraction_ratio = if rec.front_face { 1.0/ir } else { ir };
 
     let unit_direction = r_in.direction.unit_vector();
     let cos_theta = (-unit_direction).dot(&rec.normal).min(1.0);
     let sin_theta = (1.0 - cos_theta*cos_theta).sqrt();
     
     let cannot_refract = refraction_ratio * sin_theta > 1.0;
-    let direction = if cannot_refract || Dielectric::reflectance(cos_theta, refraction_ratio) > Common::random_float() {
+    let direction = if cannot_refract || Dielectric::reflectance(cos_theta, refraction_ratio) > sampler.get_1d() {
       unit_direction.reflect(&rec.normal)
     } else {
       unit_direction.refract(&rec.normal, refraction_ratio)
     };
-    let scattered = Ray::new(rec.p, direction);
+    let scattered = Ray { wavelength, ..r_in.redirected(rec.p, direction) };
+
+    (true, Some(ScatterRecord::Specular { attenuation, ray: scattered }))
+  }
+}
+
+// Metal with a GGX microfacet surface, whose albedo is its reflectance at normal incidence. Roughness
+// runs from 0 for a mirror to 1 for a very rough surface
+pub struct RoughConductor {
+  albedo: Arc<dyn Texture>,
+  distribution: TrowbridgeReitz,
+}
+
+impl RoughConductor {
+  pub fn new(albedo: Color, roughness: f64) -> RoughConductor {
+    RoughConductor::new_from_texture(Arc::new(SolidColor::new(albedo)), roughness)
+  }
+
+  pub fn new_from_texture(albedo: Arc<dyn Texture>, roughness: f64) -> RoughConductor {
+    RoughConductor {
+      albedo,
+      distribution: TrowbridgeReitz::new(roughness, roughness),
+    }
+  }
+
+  // Stretch the highlights by using different roughness along the two tangent directions
+  pub fn set_anisotropic_roughness(&mut self, roughness_u: f64, roughness_v: f64) {
+    self.distribution = TrowbridgeReitz::new(roughness_u, roughness_v);
+  }
+}
+
+impl Material for RoughConductor {
+  fn scatter(&self, r_in: &Ray, rec: &HitRecord, _: &mut PixelSampler) -> (bool, Option<ScatterRecord>) {
+    if self.distribution.is_smooth() {
+      let unit_direction = r_in.direction.unit_vector();
+      let f0 = self.albedo.value(rec.u, rec.v, &rec.p);
+      let attenuation = microfacet::fresnel_schlick(f0, -unit_direction.dot(&rec.normal));
+      let scattered = r_in.redirected(rec.p, unit_direction.reflect(&rec.normal));
+      return (true, Some(ScatterRecord::Specular { attenuation, ray: scattered }));
+    }
+
+    let pdf = MicrofacetReflectionPdf::new(rec.shading_frame(), &r_in.direction, self.distribution);
+    (true, Some(ScatterRecord::Sampled { pdf: Box::new(pdf) }))
+  }
+
+  fn eval(&self, r_in: &Ray, rec: &HitRecord, scattered: &Ray) -> Color {
+    let uvw = rec.shading_frame();
+    let wo = uvw.to_local(&-r_in.direction.unit_vector());
+    let wi = uvw.to_local(&scattered.direction.unit_vector());
+    let f0 = self.albedo.value(rec.u, rec.v, &rec.p);
+    microfacet::conductor(&self.distribution, f0, &wo, &wi).0
+  }
+
+  fn albedo(&self, rec: &HitRecord) -> Color {
+    self.albedo.value(rec.u, rec.v, &rec.p)
+  }
+}
+
+// Glass with a GGX microfacet surface, which blurs both its reflections and what is seen through it
+pub struct RoughDielectric {
+  ir: f64,
+  distribution: TrowbridgeReitz,
+}
+
+impl RoughDielectric {
+  pub fn new(ir: f64, roughness: f64) -> RoughDielectric {
+    RoughDielectric {
+      ir,
+      distribution: TrowbridgeReitz::new(roughness, roughness),
+    }
+  }
+
+  pub fn set_anisotropic_roughness(&mut self, roughness_u: f64, roughness_v: f64) {
+    self.distribution = TrowbridgeReitz::new(roughness_u, roughness_v);
+  }
+
+  // Index of refraction behind the surface relative to the side the ray arrives from
+  fn eta(&self, rec: &HitRecord) -> f64 {
+    if rec.front_face { self.ir } else { 1.0 / self.ir }
+  }
+}
+
+impl Material for RoughDielectric {
+  fn scatter(&self, r_in: &Ray, rec: &HitRecord, sampler: &mut PixelSampler) -> (bool, Option<ScatterRecord>) {
+    if self.distribution.is_smooth() {
+      let wo = -r_in.direction.unit_vector();
+      let reflectance = microfacet::fresnel_dielectric(wo.dot(&rec.normal), self.eta(rec));
+      let direction = match microfacet::refract(&wo, &rec.normal, self.eta(rec)) {
+        Some(refracted) if sampler.get_1d() >= reflectance => refracted,
+        _ => microfacet::reflect(&wo, &rec.normal),
+      };
+      let attenuation = Color::new(1.0, 1.0, 1.0);
+      let scattered = r_in.redirected(rec.p, direction);
+      return (true, Some(ScatterRecord::Specular { attenuation, ray: scattered }));
+    }
+
+    let pdf = MicrofacetDielectricPdf::new(rec.shading_frame(), &r_in.direction, self.distribution, self.eta(rec));
+    (true, Some(ScatterRecord::Sampled { pdf: Box::new(pdf) }))
+  }
+
+  fn eval(&self, r_in: &Ray, rec: &HitRecord, scattered: &Ray) -> Color {
+    let uvw = rec.shading_frame();
+    let wo = uvw.to_local(&-r_in.direction.unit_vector());
+    let wi = uvw.to_local(&scattered.direction.unit_vector());
+    let value = microfacet::dielectric(&self.distribution, self.eta(rec), &wo, &wi).0;
+    Color::new(value, value, value)
+  }
+}
+
+pub struct DiffuseLight {
+  emit: Arc<dyn Texture>,
+  spectrum: Option<Spectrum>,
+}
+
+impl DiffuseLight {
+  pub fn new(emit: Color) -> DiffuseLight {
+    DiffuseLight::new_from_texture(Arc::new(SolidColor::new(emit)))
+  }
+
+  pub fn new_from_texture(emit: Arc<dyn Texture>) -> DiffuseLight {
+    DiffuseLight {
+      emit,
+      spectrum: None,
+    }
+  }
+
+  // Emit light with the given spectrum, such as a black body, tinted and scaled by the emit texture
+  pub fn set_spectrum(&mut self, spectrum: Spectrum) {
+    self.spectrum = Some(spectrum);
+  }
+}
+
+impl Material for DiffuseLight {
+  fn scatter(&self, _: &Ray, _: &HitRecord, _: &mut PixelSampler) -> (bool, Option<ScatterRecord>) {
+    (false, None)
+  }
+
+  fn emitted(&self, u: f64, v: f64, p: &Point3) -> Color {
+    match &self.spectrum {
+      Some(spectrum) => self.emit.value(u, v, p) * spectrum.rgb(),
+      None => self.emit.value(u, v, p),
+    }
+  }
+
+  fn emitted_spectral(&self, u: f64, v: f64, p: &Point3, wavelengths: &Wavelengths) -> Color {
+    let emit = spectrum::upsample(self.emit.value(u, v, p), wavelengths);
+    match &self.spectrum {
+      Some(spectrum) => emit * spectrum.values(wavelengths),
+      None => emit,
+    }
+  }
+}
+
+// Phase function of a participating medium that scatters light equally in all directions
+pub struct Isotropic {
+  albedo: Arc<dyn Texture>,
+}
+
+impl Isotropic {
+  pub fn new(albedo: Color) -> Isotropic {
+    Isotropic::new_from_texture(Arc::new(SolidColor::new(albedo)))
+  }
+
+  pub fn new_from_texture(albedo: Arc<dyn Texture>) -> Isotropic {
+    Isotropic {
+      albedo
+    }
+  }
+}
+
+impl Material for Isotropic {
+  fn scatter(&self, _: &Ray, _: &HitRecord, _: &mut PixelSampler) -> (bool, Option<ScatterRecord>) {
+    (true, Some(ScatterRecord::Sampled { pdf: Box::new(SpherePdf::new()) }))
+  }
+
+  fn eval(&self, _: &Ray, rec: &HitRecord, _: &Ray) -> Color {
+    self.albedo.value(rec.u, rec.v, &rec.p) / (4.0 * Common::PI)
+  }
+
+  fn albedo(&self, rec: &HitRecord) -> Color {
+    self.albedo.value(rec.u, rec.v, &rec.p)
+  }
+}
+
+// Phase function of a medium that favors scattering forwards (g > 0) or backwards (g < 0)
+pub struct HenyeyGreenstein {
+  albedo: Arc<dyn Texture>,
+  g: f64,
+}
+
+impl HenyeyGreenstein {
+  pub fn new(albedo: Color, g: f64) -> HenyeyGreenstein {
+    HenyeyGreenstein::new_from_texture(Arc::new(SolidColor::new(albedo)), g)
+  }
+
+  pub fn new_from_texture(albedo: Arc<dyn Texture>, g: f64) -> HenyeyGreenstein {
+    HenyeyGreenstein {
+      albedo,
+      g: g.clamp(-0.999, 0.999),
+    }
+  }
+}
+
+impl Material for HenyeyGreenstein {
+  fn scatter(&self, r_in: &Ray, _: &HitRecord, _: &mut PixelSampler) -> (bool, Option<ScatterRecord>) {
+    let pdf = HenyeyGreensteinPdf::new(&r_in.direction, self.g);
+    (true, Some(ScatterRecord::Sampled { pdf: Box::new(pdf) }))
+  }
+
+  fn eval(&self, r_in: &Ray, rec: &HitRecord, scattered: &Ray) -> Color {
+    let cos_theta = r_in.direction.unit_vector().dot(&scattered.direction.unit_vector());
+    self.albedo.value(rec.u, rec.v, &rec.p) * HenyeyGreensteinPdf::phase(cos_theta, self.g)
+  }
+
+  fn albedo(&self, rec: &HitRecord) -> Color {
+    self.albedo.value(rec.u, rec.v, &rec.p)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{hittable::Hittable, interval::Interval, pdf::tests::sampler, sphere::Sphere};
+
+  fn specular_attenuation(material: &dyn Material, ray: &Ray, rec: &HitRecord) -> Color {
+    match material.scatter(ray, rec, &mut sampler(0)) {
+      (true, Some(ScatterRecord::Specular { attenuation, .. })) => attenuation,
+      _ => panic!("expected a specular scatter"),
+    }
+  }
+
+  #[test]
+  fn glass_absorbs_along_the_distance_travelled_inside() {
+    let mut glass = Dielectric::new(1.5);
+    glass.set_absorption(Color::new(0.5, 1.0, 2.0));
+    let sphere = Sphere::new(Point3::new(0.0, 0.0, 0.0), 1.5, Arc::new(Dielectric::new(1.5)));
+    let ray_t = Interval::new_from_range(0.001, f64::INFINITY);
+
+    // Leaving the glass after 1.5 units, with a direction longer than a unit so t isn't the distance
+    let inside = Ray::new(Point3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 2.0, 0.0));
+    let rec = sphere.hit(&inside, &ray_t).1.unwrap();
+    assert!(!rec.front_face);
+    let attenuation = specular_attenuation(&glass, &inside, &rec);
+    let expected = Color::new((-0.75f64).exp(), (-1.5f64).exp(), (-3.0f64).exp());
+    assert!((attenuation - expected).length() < 1e-12, "attenuation {} {} {}", attenuation.x, attenuation.y, attenuation.z);
+
+    // Entering it nothing has been absorbed yet
+    let outside = Ray::new(Point3::new(0.0, 5.0, 0.0), Vec3::new(0.0, -1.0, 0.0));
+    let rec = sphere.hit(&outside, &ray_t).1.unwrap();
+    assert!(rec.front_face);
+    let attenuation = specular_attenuation(&glass, &outside, &rec);
+    assert_eq!((attenuation.x, attenuation.y, attenuation.z), (1.0, 1.0, 1.0));
+  }
+
+  #[test]
+  fn dispersion_matches_reference_indices() {
+    // BK7 from its Sellmeier coefficients at the Fraunhofer F, d and C lines
+    let bk7 = Dispersion::Sellmeier { b: [1.03961212, 0.231792344, 1.01046945], c: [0.00600069867, 0.0200179144, 103.560653] };
+    for (wavelength, expected) in [(486.13, 1.52238), (587.56, 1.51680), (656.27, 1.51432)] {
+      let ior = bk7.ior(wavelength);
+      assert!((ior - expected).abs() < 5e-5, "{} at {} nm", ior, wavelength);
+    }
 
-    return (true, attenuation, scattered);
+    // Cauchy's fit of BK7, with the wavelength in micrometers
+    let cauchy = Dispersion::Cauchy { a: 1.5046, b: 0.0042 };
+    assert!((cauchy.ior(500.0) - (1.5046 + 0.0042 / 0.25)).abs() < 1e-12);
+    assert!((cauchy.ior(587.56) - bk7.ior(587.56)).abs() < 1e-3);
+    assert!(cauchy.ior(400.0) > cauchy.ior(700.0) && bk7.ior(400.0) > bk7.ior(700.0));
   }
 }
\ No newline at end of file
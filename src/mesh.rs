@@ -0,0 +1,86 @@
+use std::{ops::Range, sync::Arc};
+
+use crate::{aabb::Aabb, bvh::BvhNode, hittable::{HitRecord, Hittable}, hittable_list::HittableList, interval::Interval, material::Material, ray::Ray, triangle::Triangle, vec3::{Point3, Vec3}};
+
+// Indices of a triangle's corners into the vertex buffers of a MeshData
+#[derive(Copy, Clone)]
+pub struct Face {
+  pub positions: [usize; 3],
+  pub normals: Option<[usize; 3]>,
+  pub uvs: Option<[usize; 3]>,
+}
+
+// Vertex buffers and faces shared by every triangle of a mesh
+pub struct MeshData {
+  pub positions: Vec<Point3>,
+  pub normals: Vec<Vec3>,
+  pub uvs: Vec<(f64, f64)>,
+  pub faces: Vec<Face>,
+  pub material: Arc<dyn Material>,
+}
+
+// A named, contiguous range of faces, such as an OBJ group
+pub struct MeshGroup {
+  pub name: String,
+  pub faces: Range<usize>,
+}
+
+pub struct Mesh {
+  data: Arc<MeshData>,
+  groups: Vec<MeshGroup>,
+  bvh: BvhNode,
+}
+
+impl Mesh {
+  pub fn new(data: MeshData, groups: Vec<MeshGroup>) -> Mesh {
+    let data = Arc::new(data);
+    let faces = 0..data.faces.len();
+    Mesh {
+      bvh: Mesh::build_bvh(&data, faces),
+      data,
+      groups,
+    }
+  }
+
+  pub fn data(&self) -> &Arc<MeshData> {
+    &self.data
+  }
+
+  pub fn groups(&self) -> &[MeshGroup] {
+    &self.groups
+  }
+
+  // Build a mesh containing only the faces of the named group, sharing this mesh's vertex buffers. A
+  // group that is opened more than once, as OBJ files may do, keeps the faces of every range
+  pub fn group(&self, name: &str) -> Option<Mesh> {
+    let groups: Vec<MeshGroup> = self.groups.iter()
+      .filter(|group| group.name == name)
+      .map(|group| MeshGroup { name: group.name.clone(), faces: group.faces.clone() })
+      .collect();
+    if groups.is_empty() {
+      return None;
+    }
+    Some(Mesh {
+      data: self.data.clone(),
+      bvh: Mesh::build_bvh(&self.data, groups.iter().flat_map(|group| group.faces.clone())),
+      groups,
+    })
+  }
+
+  fn build_bvh(data: &Arc<MeshData>, faces: impl Iterator<Item = usize>) -> BvhNode {
+    let objects: Vec<Box<dyn Hittable>> = faces
+      .map(|face| Box::new(Triangle::new_from_mesh(data.clone(), face)) as Box<dyn Hittable>)
+      .collect();
+    BvhNode::new(HittableList { objects })
+  }
+}
+
+impl Hittable for Mesh {
+  fn hit(&self, ray: &Ray, ray_t: &Interval) -> (bool, Option<HitRecord<'_>>) {
+    self.bvh.hit(ray, ray_t)
+  }
+
+  fn bounding_box(&self) -> Aabb {
+    self.bvh.bounding_box()
+  }
+}
\ No newline at end of file
@@ -0,0 +1,287 @@
+use crate::{color::Color, common::Common, vec3::Vec3};
+
+// Directions are given in a local frame around the surface normal, which is the Z axis
+
+// Trowbridge-Reitz (GGX) distribution of microfacet normals, with separate roughness along the X and Y
+// axes of the frame for anisotropic surfaces
+#[derive(Copy, Clone)]
+pub struct TrowbridgeReitz {
+  alpha_x: f64,
+  alpha_y: f64,
+}
+
+impl TrowbridgeReitz {
+  // Roughness in [0, 1] is squared into the distribution's alpha, which looks more perceptually linear
+  pub fn new(roughness_x: f64, roughness_y: f64) -> TrowbridgeReitz {
+    let alpha = |roughness: f64| roughness.clamp(0.0, 1.0).powi(2).max(1e-4);
+    TrowbridgeReitz {
+      alpha_x: alpha(roughness_x),
+      alpha_y: alpha(roughness_y),
+    }
+  }
+
+  // Surfaces this smooth are treated as perfect mirrors, as the distribution is too peaked to sample well
+  pub fn is_smooth(&self) -> bool {
+    self.alpha_x.max(self.alpha_y) < 1e-3
+  }
+
+  // Density of microfacet normals wm per unit projected area
+  pub fn d(&self, wm: &Vec3) -> f64 {
+    let x = wm.x / self.alpha_x;
+    let y = wm.y / self.alpha_y;
+    let e = x * x + y * y + wm.z * wm.z;
+    1.0 / (Common::PI * self.alpha_x * self.alpha_y * e * e)
+  }
+
+  // Smith's auxiliary function, the projected area of back facing microfacets seen from w
+  fn lambda(&self, w: &Vec3) -> f64 {
+    let cos2_theta = w.z * w.z;
+    if cos2_theta == 0.0 {
+      return Common::INFINITY;
+    }
+    let tan2_theta_alpha2 = (self.alpha_x * self.alpha_x * w.x * w.x + self.alpha_y * self.alpha_y * w.y * w.y) / cos2_theta;
+    ((1.0 + tan2_theta_alpha2).sqrt() - 1.0) / 2.0
+  }
+
+  // Fraction of microfacets visible from w
+  pub fn g1(&self, w: &Vec3) -> f64 {
+    1.0 / (1.0 + self.lambda(w))
+  }
+
+  // Fraction of microfacets visible from both wo and wi, with height correlated masking and shadowing
+  pub fn g(&self, wo: &Vec3, wi: &Vec3) -> f64 {
+    1.0 / (1.0 + self.lambda(wo) + self.lambda(wi))
+  }
+
+  // Density of the normals wm of the microfacets visible from w, which face it from the same side of
+  // the surface as w
+  pub fn d_visible(&self, w: &Vec3, wm: &Vec3) -> f64 {
+    let cos_theta = w.dot(wm);
+    if cos_theta * w.z <= 0.0 {
+      return 0.0;
+    }
+    self.g1(w) / w.z.abs() * self.d(wm) * cos_theta.abs()
+  }
+
+  // Sample a visible microfacet normal from w by stretching the view to a hemisphere of unit
+  // roughness and sampling the projected disk there (Heitz, "Sampling the GGX Distribution of Visible
+  // Normals", 2018), for a pair of numbers in [0, 1)
+  pub fn sample_visible(&self, w: &Vec3, u: (f64, f64)) -> Vec3 {
+    let mut wh = Vec3::new(self.alpha_x * w.x, self.alpha_y * w.y, w.z).unit_vector();
+    if wh.z < 0.0 {
+      wh = -wh;
+    }
+    let t1 = if wh.z < 0.99999 { Vec3::new(0.0, 0.0, 1.0).cross(&wh).unit_vector() } else { Vec3::new(1.0, 0.0, 0.0) };
+    let t2 = wh.cross(&t1);
+
+    // Warp a uniform disk sample onto the part of the disk that is visible from wh
+    let r = u.0.sqrt();
+    let phi = 2.0 * Common::PI * u.1;
+    let (px, py) = (r * phi.cos(), r * phi.sin());
+    let h = (1.0 - px * px).sqrt();
+    let s = (1.0 + wh.z) / 2.0;
+    let py = (1.0 - s) * h + s * py;
+    let pz = (1.0 - px * px - py * py).max(0.0).sqrt();
+
+    let nh = t1 * px + t2 * py + wh * pz;
+    Vec3::new(self.alpha_x * nh.x, self.alpha_y * nh.y, nh.z.max(1e-6)).unit_vector()
+  }
+}
+
+// Schlick's approximation of the reflectance of a conductor with normal incidence reflectance f0
+pub fn fresnel_schlick(f0: Color, cos_theta: f64) -> Color {
+  let weight = (1.0 - cos_theta.clamp(0.0, 1.0)).powi(5);
+  f0 + (Color::new(1.0, 1.0, 1.0) - f0) * weight
+}
+
+// Unpolarized reflectance of a dielectric interface with relative index of refraction eta, the index
+// behind the surface over the index on the side the normal points to
+pub fn fresnel_dielectric(cos_theta_i: f64, eta: f64) -> f64 {
+  let (cos_theta_i, eta) = if cos_theta_i < 0.0 { (-cos_theta_i, 1.0 / eta) } else { (cos_theta_i.min(1.0), eta) };
+
+  let sin2_theta_t = (1.0 - cos_theta_i * cos_theta_i) / (eta * eta);
+  if sin2_theta_t >= 1.0 {
+    return 1.0;
+  }
+
+  let cos_theta_t = (1.0 - sin2_theta_t).sqrt();
+  let r_parallel = (eta * cos_theta_i - cos_theta_t) / (eta * cos_theta_i + cos_theta_t);
+  let r_perpendicular = (cos_theta_i - eta * cos_theta_t) / (cos_theta_i + eta * cos_theta_t);
+  (r_parallel * r_parallel + r_perpendicular * r_perpendicular) / 2.0
+}
+
+// Direction leaving the interface for light from w refracted by the microfacet normal n, pointing
+// away from the surface like w, or None for total internal reflection
+pub fn refract(w: &Vec3, n: &Vec3, eta: f64) -> Option<Vec3> {
+  let cos_theta_i = n.dot(w);
+  let (cos_theta_i, eta, n) = if cos_theta_i < 0.0 { (-cos_theta_i, 1.0 / eta, -*n) } else { (cos_theta_i, eta, *n) };
+
+  let sin2_theta_t = (1.0 - cos_theta_i * cos_theta_i) / (eta * eta);
+  if sin2_theta_t >= 1.0 {
+    return None;
+  }
+
+  let cos_theta_t = (1.0 - sin2_theta_t).sqrt();
+  Some(-*w / eta + n * (cos_theta_i / eta - cos_theta_t))
+}
+
+// Mirror w about the microfacet normal n
+pub fn reflect(w: &Vec3, n: &Vec3) -> Vec3 {
+  -*w + *n * (2.0 * w.dot(n))
+}
+
+// Rough conductor BRDF times the cosine of wi for the given Fresnel reflectance at normal incidence,
+// together with the density of sampling wi from wo with TrowbridgeReitz::sample_visible
+pub fn conductor(distribution: &TrowbridgeReitz, f0: Color, wo: &Vec3, wi: &Vec3) -> (Color, f64) {
+  let black = Color::new(0.0, 0.0, 0.0);
+  if wo.z <= 0.0 || wi.z <= 0.0 {
+    return (black, 0.0);
+  }
+  let wm = *wo + *wi;
+  if wm.length_squared() == 0.0 {
+    return (black, 0.0);
+  }
+
+  let wm = wm.unit_vector();
+  let fresnel = fresnel_schlick(f0, wo.dot(&wm));
+  let value = fresnel * (distribution.d(&wm) * distribution.g(wo, wi) / (4.0 * wo.z));
+  let pdf = distribution.d_visible(wo, &wm) / (4.0 * wo.dot(&wm).abs());
+  (value, pdf)
+}
+
+// Rough dielectric BSDF times the absolute cosine of wi, for reflection and transmission, together
+// with the density of sampling wi from wo by choosing between them with the Fresnel reflectance.
+// Transmitted radiance is scaled by 1 / eta^2 as the beam is compressed or spread by the refraction
+pub fn dielectric(distribution: &TrowbridgeReitz, eta: f64, wo: &Vec3, wi: &Vec3) -> (f64, f64) {
+  if wo.z == 0.0 || wi.z == 0.0 {
+    return (0.0, 0.0);
+  }
+
+  // The generalized half vector lies between wo and wi for reflection, and between wo and the
+  // refracted wi scaled by the relative index for transmission
+  let reflection = wo.z * wi.z > 0.0;
+  let etap = if reflection { 1.0 } else if wo.z > 0.0 { eta } else { 1.0 / eta };
+  let wm = *wi * etap + *wo;
+  if wm.length_squared() == 0.0 {
+    return (0.0, 0.0);
+  }
+  let wm = if wm.z < 0.0 { -wm.unit_vector() } else { wm.unit_vector() };
+
+  // Microfacets seen from their back side can't scatter between the two directions
+  if wm.dot(wi) * wi.z < 0.0 || wm.dot(wo) * wo.z < 0.0 {
+    return (0.0, 0.0);
+  }
+
+  let reflectance = fresnel_dielectric(wo.dot(&wm), eta);
+  let transmittance = 1.0 - reflectance;
+  let d = distribution.d(&wm);
+  let g = distribution.g(wo, wi);
+  let d_visible = distribution.d_visible(wo, &wm);
+
+  if reflection {
+    let value = d * g * reflectance / (4.0 * wo.z.abs());
+    let pdf = d_visible / (4.0 * wo.dot(&wm).abs()) * reflectance;
+    (value, pdf)
+  } else {
+    let denom = wi.dot(&wm) + wo.dot(&wm) / etap;
+    let denom = denom * denom;
+    let value = transmittance * d * g * (wi.dot(&wm) * wo.dot(&wm)).abs() / (wo.z.abs() * denom * etap * etap);
+    let pdf = d_visible * wi.dot(&wm).abs() / denom * transmittance;
+    (value, pdf)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{onb::Onb, pdf::{tests::{assert_generates_value, integrate, sampler}, MicrofacetDielectricPdf, MicrofacetReflectionPdf}};
+
+  fn distributions() -> [TrowbridgeReitz; 3] {
+    [TrowbridgeReitz::new(0.5, 0.5), TrowbridgeReitz::new(0.9, 0.9), TrowbridgeReitz::new(0.4, 0.8)]
+  }
+
+  fn outgoing() -> [Vec3; 3] {
+    [Vec3::new(0.0, 0.0, 1.0), Vec3::new(0.6, 0.2, 0.5).unit_vector(), Vec3::new(-0.9, 0.3, 0.1).unit_vector()]
+  }
+
+  #[test]
+  fn visible_normals_integrate_to_one() {
+    for distribution in distributions() {
+      // Seen from below, the visible microfacets are the ones facing away from the normal
+      for wo in outgoing().into_iter().flat_map(|wo| [wo, -wo]) {
+        let integral = integrate(&|wm| if wm.z > 0.0 { distribution.d_visible(&wo, wm) } else { 0.0 });
+        assert!((integral - 1.0).abs() < 1e-3, "integral {}", integral);
+      }
+    }
+  }
+
+  #[test]
+  fn sampled_directions_follow_the_density() {
+    let uvw = Onb::new(&Vec3::new(0.2, 0.9, -0.3).unit_vector());
+    for distribution in distributions() {
+      for wo in outgoing() {
+        let incoming = -uvw.local(&wo);
+        assert_generates_value(&MicrofacetReflectionPdf::new(uvw, &incoming, distribution), 1.0);
+        assert_generates_value(&MicrofacetDielectricPdf::new(uvw, &incoming, distribution, 1.5), 1.0);
+        // From inside, where rays beyond the critical angle are totally reflected
+        assert_generates_value(&MicrofacetDielectricPdf::new(uvw, &incoming, distribution, 1.0 / 1.5), 1.0);
+      }
+    }
+  }
+
+  // Sampling visible normals makes the weight of a sample the fraction of the microfacets seen from wo
+  // that wi also sees, so a surface reflecting everything loses energy only to the light that single
+  // scattering leaves masked or sends below the surface
+  #[test]
+  fn white_surfaces_lose_energy_only_to_masking() {
+    let white = Color::new(1.0, 1.0, 1.0);
+    for distribution in distributions() {
+      for wo in outgoing() {
+        let mut sum = 0.0;
+        let n = 20_000;
+        for index in 0..n {
+          let mut sampler = sampler(index);
+          let wm = distribution.sample_visible(&wo, sampler.get_2d());
+          let wi = reflect(&wo, &wm);
+          if wi.z <= 0.0 {
+            continue;
+          }
+          let (value, pdf) = conductor(&distribution, white, &wo, &wi);
+          let masking = distribution.g(&wo, &wi) / distribution.g1(&wo);
+          assert!((value.x / pdf - masking).abs() < 1e-9);
+          sum += masking;
+        }
+        let albedo = integrate(&|wi| conductor(&distribution, white, &wo, wi).0.x);
+        assert!(albedo <= 1.0 && (albedo - sum / n as f64).abs() < 1e-2, "albedo {}, estimate {}", albedo, sum / n as f64);
+
+        // Reflection and transmission share the light in proportion to the Fresnel reflectance. Radiance
+        // is scaled by 1 / eta^2 on refraction, so the energy is found by undoing that
+        for eta in [1.5, 1.0 / 1.5] {
+          let mut sum = 0.0;
+          for index in 0..n {
+            let mut sampler = sampler(index);
+            let wm = distribution.sample_visible(&wo, sampler.get_2d());
+            for (wi, reflected) in [(Some(reflect(&wo, &wm)), true), (refract(&wo, &wm, eta), false)] {
+              // Like the density, leave out directions that end up on the wrong side of the surface
+              let wi = match wi {
+                Some(wi) if (wi.z > 0.0) == reflected => wi,
+                _ => continue,
+              };
+              let (value, pdf) = dielectric(&distribution, eta, &wo, &wi);
+              let etap2 = if wi.z < 0.0 { eta * eta } else { 1.0 };
+              let masking = distribution.g(&wo, &wi) / distribution.g1(&wo);
+              assert!((value * etap2 / pdf - masking).abs() < 1e-9);
+              let fresnel = fresnel_dielectric(wo.dot(&wm), eta);
+              sum += masking * if wi.z < 0.0 { 1.0 - fresnel } else { fresnel };
+            }
+          }
+          let albedo = integrate(&|wi| {
+            let etap2 = if wi.z < 0.0 { eta * eta } else { 1.0 };
+            dielectric(&distribution, eta, &wo, wi).0 * etap2
+          });
+          assert!(albedo <= 1.0 && (albedo - sum / n as f64).abs() < 1e-2, "albedo {}, estimate {}", albedo, sum / n as f64);
+        }
+      }
+    }
+  }
+}
\ No newline at end of file
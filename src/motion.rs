@@ -0,0 +1,188 @@
+use std::sync::Arc;
+
+use crate::{aabb::Aabb, hittable::{HitRecord, Hittable}, interval::Interval, ray::Ray, sampler::PixelSampler, transform::Transform, vec3::{Point3, Vec3}};
+
+// Pose of an animated object at a moment in time, with the rotation in degrees around the X, Y
+// and Z axes applied in turn before the translation
+#[derive(Copy, Clone)]
+pub struct Keyframe {
+  pub time: f64,
+  pub translate: Vec3,
+  pub rotate: Vec3,
+}
+
+// A shared object moved by keyframes, placed at the pose interpolated for each ray's time
+pub struct Animated {
+  object: Arc<dyn Hittable>,
+  keyframes: Vec<Keyframe>,
+  bbox: Aabb,
+}
+
+impl Animated {
+  // Keyframes are sorted by time, the object holds the first and last pose outside of their range
+  pub fn new(object: Arc<dyn Hittable>, mut keyframes: Vec<Keyframe>) -> Animated {
+    assert!(!keyframes.is_empty(), "an animated object needs at least one keyframe");
+    keyframes.sort_by(|a, b| a.time.total_cmp(&b.time));
+
+    let object_bbox = object.bounding_box();
+    let rotates = keyframes.iter().any(|key| {
+      let r = key.rotate - keyframes[0].rotate;
+      r.x != 0.0 || r.y != 0.0 || r.z != 0.0
+    });
+    let bbox = if !object_bbox.is_bounded() {
+      Aabb::UNIVERSE
+    } else if !rotates {
+      // A box moving in a straight line stays within the box around both of its ends
+      keyframes.iter().fold(Aabb::EMPTY, |bbox, key| {
+        Aabb::new_from_boxes(&bbox, &Animated::transform(key).bounding_box(&object_bbox))
+      })
+    } else {
+      // Rotations sweep the box along arcs, so bound it by a sphere around the object's origin instead
+      let radius = (0..8).map(|i| Vec3::new(
+        if i & 1 == 0 { object_bbox.x.min } else { object_bbox.x.max },
+        if i & 2 == 0 { object_bbox.y.min } else { object_bbox.y.max },
+        if i & 4 == 0 { object_bbox.z.min } else { object_bbox.z.max },
+      ).length()).fold(0.0, f64::max);
+      let extent = Vec3::new(radius, radius, radius);
+      keyframes.iter().fold(Aabb::EMPTY, |bbox, key| {
+        let sphere_bbox = Aabb::new_from_points(&(key.translate - extent), &(key.translate + extent));
+        Aabb::new_from_boxes(&bbox, &sphere_bbox)
+      })
+    };
+
+    Animated {
+      object,
+      keyframes,
+      bbox,
+    }
+  }
+
+  fn transform(key: &Keyframe) -> Transform {
+    Transform::translation(&key.translate)
+      * Transform::rotation(&Vec3::new(0.0, 0.0, 1.0), key.rotate.z)
+      * Transform::rotation(&Vec3::new(0.0, 1.0, 0.0), key.rotate.y)
+      * Transform::rotation(&Vec3::new(1.0, 0.0, 0.0), key.rotate.x)
+  }
+
+  // Linearly interpolate the pose between the keyframes surrounding time
+  fn transform_at(&self, time: f64) -> Transform {
+    let next = self.keyframes.partition_point(|key| key.time <= time);
+    if next == 0 {
+      return Animated::transform(&self.keyframes[0]);
+    }
+    if next == self.keyframes.len() {
+      return Animated::transform(&self.keyframes[next - 1]);
+    }
+
+    let (a, b) = (&self.keyframes[next - 1], &self.keyframes[next]);
+    let t = (time - a.time) / (b.time - a.time);
+    Animated::transform(&Keyframe {
+      time,
+      translate: a.translate + (b.translate - a.translate) * t,
+      rotate: a.rotate + (b.rotate - a.rotate) * t,
+    })
+  }
+}
+
+impl Hittable for Animated {
+  fn hit(&self, ray: &Ray, ray_t: &Interval) -> (bool, Option<HitRecord<'_>>) {
+    let transform = self.transform_at(ray.time);
+    let inverse = transform.inverse();
+    let object_ray = ray.redirected(inverse.point(&ray.origin), inverse.vector(&ray.direction));
+
+    let (hit, rec) = self.object.hit(&object_ray, ray_t);
+    if !hit {
+      return (false, None);
+    }
+
+    let rec = rec.unwrap();
+    let rec = HitRecord {
+      p: transform.point(&rec.p),
+      normal: transform.normal(&rec.normal).unit_vector(),
+      geometric_normal: transform.normal(&rec.geometric_normal).unit_vector(),
+      dpdu: transform.vector(&rec.dpdu),
+      dpdv: transform.vector(&rec.dpdv),
+      ..rec
+    };
+    (true, Some(rec))
+  }
+
+  fn bounding_box(&self) -> Aabb {
+    self.bbox
+  }
+
+  // Rotations and translations preserve solid angles, so the object is sampled in its pose at the time
+  fn pdf_value(&self, origin: &Point3, direction: &Vec3, time: f64) -> f64 {
+    let inverse = self.transform_at(time).inverse();
+    self.object.pdf_value(&inverse.point(origin), &inverse.vector(direction), time)
+  }
+
+  fn random(&self, origin: &Point3, time: f64, sampler: &mut PixelSampler) -> Vec3 {
+    let transform = self.transform_at(time);
+    let inverse = transform.inverse();
+    transform.vector(&self.object.random(&inverse.point(origin), time, sampler))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{material::Lambertian, pdf::tests::sampler, sphere::Sphere};
+
+  fn close(a: &Vec3, b: &Vec3) -> bool {
+    (*a - *b).length() < 1e-9
+  }
+
+  // Sphere off the object's origin, carried along the X axis while turning half a turn around Y
+  fn animated() -> Animated {
+    let sphere = Sphere::new(Point3::new(2.0, 0.0, 0.0), 0.5, Arc::new(Lambertian::new(Vec3::new(0.5, 0.5, 0.5))));
+    Animated::new(Arc::new(sphere), vec![
+      Keyframe { time: 1.0, translate: Vec3::new(4.0, 0.0, 0.0), rotate: Vec3::new(0.0, 180.0, 0.0) },
+      Keyframe { time: 0.0, translate: Vec3::new(0.0, 0.0, 0.0), rotate: Vec3::new(0.0, 0.0, 0.0) },
+    ])
+  }
+
+  #[test]
+  fn poses_are_interpolated_between_keyframes() {
+    let animated = animated();
+    let p = Point3::new(2.0, 0.0, 0.0);
+    assert!(close(&animated.transform_at(0.0).point(&p), &Point3::new(2.0, 0.0, 0.0)));
+    assert!(close(&animated.transform_at(0.5).point(&p), &Point3::new(2.0, 0.0, -2.0)));
+    assert!(close(&animated.transform_at(1.0).point(&p), &Point3::new(2.0, 0.0, 0.0)));
+    assert!(close(&animated.transform_at(0.25).point(&p), &Point3::new(1.0 + 2.0f64.sqrt(), 0.0, -(2.0f64.sqrt()))));
+  }
+
+  #[test]
+  fn poses_hold_outside_the_keyframes() {
+    let animated = animated();
+    let p = Point3::new(2.0, 0.0, 0.0);
+    assert!(close(&animated.transform_at(-3.0).point(&p), &animated.transform_at(0.0).point(&p)));
+    assert!(close(&animated.transform_at(7.0).point(&p), &animated.transform_at(1.0).point(&p)));
+  }
+
+  #[test]
+  fn rotating_bounding_box_holds_every_pose() {
+    let animated = animated();
+    let bbox = animated.bounding_box();
+    for i in 0..=100 {
+      let center = animated.transform_at(i as f64 / 100.0).point(&Point3::new(2.0, 0.0, 0.0));
+      for axis in 0..3 {
+        let interval = bbox.axis_interval(axis);
+        let c = [center.x, center.y, center.z][axis];
+        assert!(interval.min <= c - 0.5 && c + 0.5 <= interval.max);
+      }
+    }
+  }
+
+  #[test]
+  fn lights_are_sampled_in_their_pose_at_the_time() {
+    let animated = animated();
+    let origin = Point3::new(2.0, 5.0, 0.0);
+    for (index, time) in [0.0, 0.3, 0.5, 0.9].into_iter().enumerate() {
+      let direction = animated.random(&origin, time, &mut sampler(index as u64));
+      let ray = Ray::new_with_time(origin, direction, time);
+      assert!(animated.hit(&ray, &Interval::new_from_range(0.001, f64::INFINITY)).0);
+      assert!(animated.pdf_value(&origin, &direction, time) > 0.0);
+    }
+  }
+}
\ No newline at end of file
@@ -0,0 +1,208 @@
+use std::sync::Arc;
+
+use crate::{color::Color, hittable::HitRecord, material::{Material, ScatterRecord}, ray::Ray, sampler::PixelSampler, spectrum::Wavelengths, texture::Texture, vec3::{Point3, Vec3}};
+
+// Step in texture coordinates over which bump maps take differences of height, about a texel of a
+// 1024 pixel map
+const BUMP_DELTA: f64 = 1.0 / 1024.0;
+
+// Wraps a material to shade it with normals read from a tangent space normal map, where red, green and
+// blue encode the normal along dpdu, dpdv and the surface normal. Strength scales the tilt away from
+// the surface normal. The map should be loaded as raw data rather than as an sRGB color image
+pub struct NormalMap {
+  material: Arc<dyn Material>,
+  map: Arc<dyn Texture>,
+  strength: f64,
+}
+
+impl NormalMap {
+  pub fn new(material: Arc<dyn Material>, map: Arc<dyn Texture>) -> NormalMap {
+    NormalMap {
+      material,
+      map,
+      strength: 1.0,
+    }
+  }
+
+  pub fn set_strength(&mut self, strength: f64) {
+    self.strength = strength;
+  }
+}
+
+impl Material for NormalMap {
+  fn scatter(&self, r_in: &Ray, rec: &HitRecord, sampler: &mut PixelSampler) -> (bool, Option<ScatterRecord>) {
+    self.material.scatter(r_in, rec, sampler)
+  }
+
+  fn eval(&self, r_in: &Ray, rec: &HitRecord, scattered: &Ray) -> Color {
+    self.material.eval(r_in, rec, scattered)
+  }
+
+  fn shading_normal(&self, rec: &HitRecord) -> Vec3 {
+    let rec = rec.set_shading_normal(self.material.shading_normal(rec));
+    let encoded = self.map.value(rec.u, rec.v, &rec.p);
+    let tangent_normal = Vec3::new(
+      (2.0 * encoded.x - 1.0) * self.strength,
+      (2.0 * encoded.y - 1.0) * self.strength,
+      2.0 * encoded.z - 1.0,
+    );
+
+    // Tangent space follows dpdu, with its second axis turned towards dpdv for mirrored texture layouts
+    let frame = rec.shading_frame();
+    let bitangent = if frame.v.dot(&rec.dpdv) < 0.0 { -frame.v } else { frame.v };
+    let normal = frame.u * tangent_normal.x + bitangent * tangent_normal.y + frame.w * tangent_normal.z;
+    if normal.near_zero() { rec.normal } else { normal.unit_vector() }
+  }
+
+  fn emitted(&self, u: f64, v: f64, p: &Point3) -> Color {
+    self.material.emitted(u, v, p)
+  }
+
+  fn emitted_spectral(&self, u: f64, v: f64, p: &Point3, wavelengths: &Wavelengths) -> Color {
+    self.material.emitted_spectral(u, v, p, wavelengths)
+  }
+
+  fn albedo(&self, rec: &HitRecord) -> Color {
+    self.material.albedo(rec)
+  }
+}
+
+// Wraps a material to shade it as if displaced along its normal by the luminance of a height texture
+// times scale, in world units
+pub struct BumpMap {
+  material: Arc<dyn Material>,
+  height: Arc<dyn Texture>,
+  scale: f64,
+}
+
+impl BumpMap {
+  pub fn new(material: Arc<dyn Material>, height: Arc<dyn Texture>, scale: f64) -> BumpMap {
+    BumpMap {
+      material,
+      height,
+      scale,
+    }
+  }
+
+  fn displacement(&self, u: f64, v: f64, p: &Point3) -> f64 {
+    self.height.value(u, v, p).luminance() * self.scale
+  }
+}
+
+impl Material for BumpMap {
+  fn scatter(&self, r_in: &Ray, rec: &HitRecord, sampler: &mut PixelSampler) -> (bool, Option<ScatterRecord>) {
+    self.material.scatter(r_in, rec, sampler)
+  }
+
+  fn eval(&self, r_in: &Ray, rec: &HitRecord, scattered: &Ray) -> Color {
+    self.material.eval(r_in, rec, scattered)
+  }
+
+  // The displaced surface's tangents gain the rate of change of the displacement along the normal,
+  // and their cross product is the bumped normal
+  fn shading_normal(&self, rec: &HitRecord) -> Vec3 {
+    let rec = rec.set_shading_normal(self.material.shading_normal(rec));
+    let displacement = self.displacement(rec.u, rec.v, &rec.p);
+    let du = self.displacement(rec.u + BUMP_DELTA, rec.v, &(rec.p + rec.dpdu * BUMP_DELTA)) - displacement;
+    let dv = self.displacement(rec.u, rec.v + BUMP_DELTA, &(rec.p + rec.dpdv * BUMP_DELTA)) - displacement;
+
+    let dpdu = rec.dpdu + rec.normal * (du / BUMP_DELTA);
+    let dpdv = rec.dpdv + rec.normal * (dv / BUMP_DELTA);
+    let normal = dpdu.cross(&dpdv);
+    if normal.near_zero() {
+      return rec.normal;
+    }
+
+    // The cross product's direction depends on the handedness of the texture layout
+    let unbumped = rec.dpdu.cross(&rec.dpdv);
+    let normal = normal.unit_vector();
+    if unbumped.dot(&rec.normal) < 0.0 { -normal } else { normal }
+  }
+
+  fn emitted(&self, u: f64, v: f64, p: &Point3) -> Color {
+    self.material.emitted(u, v, p)
+  }
+
+  fn emitted_spectral(&self, u: f64, v: f64, p: &Point3, wavelengths: &Wavelengths) -> Color {
+    self.material.emitted_spectral(u, v, p, wavelengths)
+  }
+
+  fn albedo(&self, rec: &HitRecord) -> Color {
+    self.material.albedo(rec)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{hittable::Hittable, interval::Interval, material::Lambertian, quad::Quad, sphere::Sphere, texture::SolidColor};
+
+  // Height rising by one over each unit of the u texture coordinate
+  struct Ramp;
+
+  impl Texture for Ramp {
+    fn value(&self, u: f64, _v: f64, _p: &Point3) -> Color {
+      Color::new(u, u, u)
+    }
+  }
+
+  fn base() -> Arc<dyn Material> {
+    Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)))
+  }
+
+  fn flat_normals() -> Arc<dyn Texture> {
+    Arc::new(SolidColor::new_from_rgb(0.5, 0.5, 1.0))
+  }
+
+  // Hits on a sphere and on quads whose texture layouts are mirrored from each other
+  fn hits(material: Arc<dyn Material>, check: &dyn Fn(&HitRecord)) {
+    let sphere = Sphere::new(Point3::new(0.0, 0.0, 0.0), 1.0, material.clone());
+    let quad = Quad::new(Point3::new(-1.0, -1.0, -2.0), Vec3::new(2.0, 0.0, 0.0), Vec3::new(0.0, 2.0, 0.0), material.clone());
+    let mirrored = Quad::new(Point3::new(-1.0, 1.0, -2.0), Vec3::new(2.0, 0.0, 0.0), Vec3::new(0.0, -2.0, 0.0), material);
+    let rays = [
+      Ray::new(Point3::new(0.2, 0.3, 5.0), Vec3::new(0.0, 0.0, -1.0)),
+      Ray::new(Point3::new(-3.0, 1.0, 0.5), Vec3::new(1.0, -0.2, 0.0)),
+      Ray::new(Point3::new(0.4, -0.3, -5.0), Vec3::new(0.0, 0.0, 1.0)),
+    ];
+    let ray_t = Interval::new_from_range(0.001, f64::INFINITY);
+    for object in [&sphere as &dyn Hittable, &quad, &mirrored] {
+      for ray in &rays {
+        if let (true, Some(rec)) = object.hit(ray, &ray_t) {
+          check(&rec);
+        }
+      }
+    }
+  }
+
+  fn assert_close(a: Vec3, b: Vec3) {
+    assert!((a - b).length() < 1e-9, "{} {} {} is not {} {} {}", a.x, a.y, a.z, b.x, b.y, b.z);
+  }
+
+  #[test]
+  fn flat_maps_leave_the_normal_unchanged() {
+    let mut normal_map = NormalMap::new(base(), flat_normals());
+    normal_map.set_strength(2.0);
+    hits(Arc::new(normal_map), &|rec| assert_close(rec.material.shading_normal(rec), rec.normal));
+
+    let bump_map = BumpMap::new(base(), Arc::new(SolidColor::new_from_rgb(0.3, 0.6, 0.9)), 5.0);
+    hits(Arc::new(bump_map), &|rec| assert_close(rec.material.shading_normal(rec), rec.normal));
+  }
+
+  #[test]
+  fn maps_tilt_the_normal_along_the_tangents() {
+    let ray = Ray::new(Point3::new(0.2, 0.3, 5.0), Vec3::new(0.0, 0.0, -1.0));
+    let ray_t = Interval::new_from_range(0.001, f64::INFINITY);
+
+    // Red and green tilt towards dpdu and dpdv, scaled by the strength
+    let mut normal_map = NormalMap::new(base(), Arc::new(SolidColor::new_from_rgb(1.0, 0.5, 1.0)));
+    normal_map.set_strength(0.5);
+    let quad = Quad::new(Point3::new(-1.0, -1.0, 0.0), Vec3::new(2.0, 0.0, 0.0), Vec3::new(0.0, 2.0, 0.0), Arc::new(normal_map));
+    let rec = quad.hit(&ray, &ray_t).1.unwrap();
+    assert_close(rec.material.shading_normal(&rec), Vec3::new(0.5, 0.0, 1.0).unit_vector());
+
+    // Height rising along u turns the normal back against dpdu, by the slope in world units
+    let quad = Quad::new(Point3::new(-1.0, -1.0, 0.0), Vec3::new(2.0, 0.0, 0.0), Vec3::new(0.0, 2.0, 0.0), Arc::new(BumpMap::new(base(), Arc::new(Ramp), 1.0)));
+    let rec = quad.hit(&ray, &ray_t).1.unwrap();
+    assert_close(rec.material.shading_normal(&rec), Vec3::new(-0.5, 0.0, 1.0).unit_vector());
+  }
+}
\ No newline at end of file
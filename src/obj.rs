@@ -0,0 +1,220 @@
+use std::{fmt, fs, io, path::Path, sync::Arc};
+
+use crate::{material::Material, mesh::{Face, Mesh, MeshData, MeshGroup}, vec3::{Point3, Vec3}};
+
+#[derive(Debug)]
+pub enum ObjError {
+  Io(io::Error),
+  Parse { line: usize, message: String },
+}
+
+impl fmt::Display for ObjError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      ObjError::Io(err) => write!(f, "could not read OBJ file: {}", err),
+      ObjError::Parse { line, message } => write!(f, "line {}: {}", line, message),
+    }
+  }
+}
+
+impl std::error::Error for ObjError {}
+
+impl From<io::Error> for ObjError {
+  fn from(err: io::Error) -> Self {
+    ObjError::Io(err)
+  }
+}
+
+// Load a Wavefront OBJ file into a mesh where every face uses the given material
+pub fn load_obj<P: AsRef<Path>>(path: P, material: Arc<dyn Material>) -> Result<Mesh, ObjError> {
+  let source = fs::read_to_string(path)?;
+  parse_obj(&source, material)
+}
+
+// Parse OBJ source text, triangulating polygons as fans around their first vertex
+pub fn parse_obj(source: &str, material: Arc<dyn Material>) -> Result<Mesh, ObjError> {
+  let mut data = MeshData {
+    positions: vec![],
+    normals: vec![],
+    uvs: vec![],
+    faces: vec![],
+    material,
+  };
+  let mut groups = vec![];
+  let mut group_name = String::from("default");
+  let mut group_start = 0;
+
+  for (index, line) in source.lines().enumerate() {
+    let line_number = index + 1;
+    let line = line.split('#').next().unwrap_or("").trim();
+    let mut tokens = line.split_whitespace();
+    let keyword = match tokens.next() {
+      Some(keyword) => keyword,
+      None => continue,
+    };
+    let args: Vec<&str> = tokens.collect();
+
+    match keyword {
+      "v" => {
+        let [x, y, z] = parse_floats(&args, 3, line_number)?;
+        data.positions.push(Point3::new(x, y, z));
+      }
+      "vn" => {
+        let [x, y, z] = parse_floats(&args, 3, line_number)?;
+        data.normals.push(Vec3::new(x, y, z).unit_vector());
+      }
+      "vt" => {
+        let [u, v, _] = parse_floats(&args, 1, line_number)?;
+        data.uvs.push((u, v));
+      }
+      "f" => {
+        if args.len() < 3 {
+          return Err(parse_error(line_number, "a face needs at least three vertices"));
+        }
+        let vertices = args.iter()
+          .map(|arg| parse_vertex(arg, &data, line_number))
+          .collect::<Result<Vec<_>, _>>()?;
+
+        for i in 1..vertices.len() - 1 {
+          let corners = [vertices[0], vertices[i], vertices[i + 1]];
+          data.faces.push(Face {
+            positions: corners.map(|c| c.0),
+            normals: if corners.iter().all(|c| c.2.is_some()) { Some(corners.map(|c| c.2.unwrap())) } else { None },
+            uvs: if corners.iter().all(|c| c.1.is_some()) { Some(corners.map(|c| c.1.unwrap())) } else { None },
+          });
+        }
+      }
+      "g" | "o" => {
+        if data.faces.len() > group_start {
+          groups.push(MeshGroup { name: group_name, faces: group_start..data.faces.len() });
+        }
+        group_name = if args.is_empty() { String::from("default") } else { args.join(" ") };
+        group_start = data.faces.len();
+      }
+      // Smoothing groups and materials are not supported, the default material is used instead. Lines,
+      // points, free-form geometry and any other statements have nothing to render and are skipped
+      _ => {}
+    }
+  }
+
+  if data.faces.len() > group_start {
+    groups.push(MeshGroup { name: group_name, faces: group_start..data.faces.len() });
+  }
+
+  Ok(Mesh::new(data, groups))
+}
+
+fn parse_error(line: usize, message: &str) -> ObjError {
+  ObjError::Parse { line, message: message.to_string() }
+}
+
+// Parse at least `required` and at most three floats, filling the remainder with zeros
+fn parse_floats(args: &[&str], required: usize, line: usize) -> Result<[f64; 3], ObjError> {
+  if args.len() < required {
+    return Err(parse_error(line, &format!("expected {} numbers, found {}", required, args.len())));
+  }
+  let mut values = [0.0; 3];
+  for (value, arg) in values.iter_mut().zip(args) {
+    *value = arg.parse().map_err(|_| parse_error(line, &format!("invalid number '{}'", arg)))?;
+  }
+  Ok(values)
+}
+
+// Parse a face vertex of the form v, v/vt, v//vn or v/vt/vn into zero-based indices
+fn parse_vertex(arg: &str, data: &MeshData, line: usize) -> Result<(usize, Option<usize>, Option<usize>), ObjError> {
+  let mut parts = arg.split('/');
+  let position = resolve_index(parts.next(), data.positions.len(), line)?
+    .ok_or_else(|| parse_error(line, &format!("face vertex '{}' has no position", arg)))?;
+  let uv = resolve_index(parts.next(), data.uvs.len(), line)?;
+  let normal = resolve_index(parts.next(), data.normals.len(), line)?;
+  Ok((position, uv, normal))
+}
+
+// OBJ indices are one-based, and negative indices count back from the latest element
+fn resolve_index(part: Option<&str>, count: usize, line: usize) -> Result<Option<usize>, ObjError> {
+  let part = match part {
+    Some(part) if !part.is_empty() => part,
+    _ => return Ok(None),
+  };
+  let index: i64 = part.parse().map_err(|_| parse_error(line, &format!("invalid index '{}'", part)))?;
+  let resolved = if index < 0 { count as i64 + index } else { index - 1 };
+  if resolved < 0 || resolved >= count as i64 {
+    return Err(parse_error(line, &format!("index {} is out of range", index)));
+  }
+  Ok(Some(resolved as usize))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{color::Color, hittable::Hittable, interval::Interval, material::Lambertian, ray::Ray};
+
+  fn parse(source: &str) -> Result<Mesh, ObjError> {
+    parse_obj(source, Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5))))
+  }
+
+  fn parse_line_error(source: &str) -> usize {
+    match parse(source) {
+      Err(ObjError::Parse { line, .. }) => line,
+      _ => panic!("expected a parse error"),
+    }
+  }
+
+  #[test]
+  fn triangulates_faces_with_texture_coordinates_and_normals() {
+    let mesh = parse("v 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\nvt 0 0\nvt 1 0\nvt 1 1\nvt 0 1\nvn 0 0 2\nf 1/1/1 2/2/1 3/3/1 4/4/1\n").unwrap();
+    let data = mesh.data();
+    assert_eq!(data.faces.len(), 2);
+    assert_eq!(data.faces[0].positions, [0, 1, 2]);
+    assert_eq!(data.faces[1].positions, [0, 2, 3]);
+    assert_eq!(data.faces[1].uvs, Some([0, 2, 3]));
+    assert_eq!(data.faces[1].normals, Some([0, 0, 0]));
+    assert_eq!(data.normals[0].z, 1.0);
+
+    let mesh = parse("v 0 0 0\nv 1 0 0\nv 0 1 0\nvn 0 0 1\nf 1//1 2//1 3//1\nf 1 2 3\n").unwrap();
+    assert_eq!(mesh.data().faces[0].uvs, None);
+    assert_eq!(mesh.data().faces[0].normals, Some([0, 0, 0]));
+    assert_eq!(mesh.data().faces[1].normals, None);
+  }
+
+  #[test]
+  fn resolves_negative_indices_from_the_latest_vertex() {
+    let mesh = parse("v 0 0 0\nv 1 0 0\nv 0 1 0\nf -3 -2 -1\nv 0 0 1\nf -4 -3 -1\n").unwrap();
+    assert_eq!(mesh.data().faces[0].positions, [0, 1, 2]);
+    assert_eq!(mesh.data().faces[1].positions, [0, 1, 3]);
+  }
+
+  #[test]
+  fn merges_groups_opened_more_than_once() {
+    let source = "v 0 0 0\nv 1 0 0\nv 0 1 0\nv 0 0 5\nv 1 0 5\nv 0 1 5\n\
+      g a\nf 1 2 3\ng b\nf 1 2 3\ng a\nf 4 5 6\n";
+    let mesh = parse(source).unwrap();
+    let names: Vec<&str> = mesh.groups().iter().map(|group| group.name.as_str()).collect();
+    assert_eq!(names, ["a", "b", "a"]);
+
+    let group = mesh.group("a").unwrap();
+    assert_eq!(group.groups().len(), 2);
+    let bbox = group.bounding_box();
+    assert!(bbox.z.min <= 0.0 && bbox.z.max >= 5.0);
+    let ray = Ray::new(Point3::new(0.2, 0.2, 10.0), Vec3::new(0.0, 0.0, -1.0));
+    let (hit, rec) = group.hit(&ray, &Interval::new_from_range(0.001, f64::INFINITY));
+    assert!(hit);
+    assert_eq!(rec.unwrap().t, 5.0);
+    assert!(mesh.group("c").is_none());
+  }
+
+  #[test]
+  fn skips_unsupported_statements() {
+    let source = "mtllib scene.mtl\nv 0 0 0\nv 1 0 0\nv 0 1 0\nvp 0.5\ncstype bspline\nusemtl red\ns 1\nl 1 2\np 3\nf 1 2 3\n";
+    assert_eq!(parse(source).unwrap().data().faces.len(), 1);
+  }
+
+  #[test]
+  fn reports_the_line_of_malformed_statements() {
+    assert_eq!(parse_line_error("v 0 0 0\nv 1 0\n"), 2);
+    assert_eq!(parse_line_error("v 0 0 0\nv 1 0 0\nf 1 2\n"), 3);
+    assert_eq!(parse_line_error("v 0 0 0\nv 1 0 0\nv 0 1 0\nf 1 2 4\n"), 4);
+    assert_eq!(parse_line_error("v 0 0 0\nv 1 0 0\nv 0 1 0\nf 1 2 -4\n"), 4);
+    assert_eq!(parse_line_error("v 0 0 0\nv 1 0 0\nv 0 1 0\nf 1 2 x\n"), 4);
+  }
+}
\ No newline at end of file
@@ -0,0 +1,75 @@
+use crate::vec3::Vec3;
+
+// Right-handed orthonormal basis built around a direction, used to turn samples around the Z axis into
+// world space
+#[derive(Copy, Clone)]
+pub struct Onb {
+  pub u: Vec3,
+  pub v: Vec3,
+  pub w: Vec3,
+}
+
+impl Onb {
+  pub fn new(n: &Vec3) -> Onb {
+    let w = n.unit_vector();
+    let a = if w.x.abs() > 0.9 { Vec3::new(0.0, 1.0, 0.0) } else { Vec3::new(1.0, 0.0, 0.0) };
+    let v = w.cross(&a).unit_vector();
+    let u = v.cross(&w);
+    Onb { u, v, w }
+  }
+
+  // Basis around n whose first axis follows the tangent t as closely as possible, or an arbitrary one
+  // when t is parallel to n
+  pub fn new_with_tangent(n: &Vec3, t: &Vec3) -> Onb {
+    let w = n.unit_vector();
+    let u = *t - w * w.dot(t);
+    if u.length_squared() < 1e-16 {
+      return Onb::new(n);
+    }
+    let u = u.unit_vector();
+    let v = w.cross(&u);
+    Onb { u, v, w }
+  }
+
+  // Transform a vector given in basis coordinates into world space
+  pub fn local(&self, a: &Vec3) -> Vec3 {
+    self.u * a.x + self.v * a.y + self.w * a.z
+  }
+
+  // Transform a world space vector into basis coordinates
+  pub fn to_local(&self, a: &Vec3) -> Vec3 {
+    Vec3::new(a.dot(&self.u), a.dot(&self.v), a.dot(&self.w))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn assert_right_handed(uvw: &Onb) {
+    for (a, b) in [(uvw.u, uvw.v), (uvw.v, uvw.w), (uvw.w, uvw.u)] {
+      assert!((a.length() - 1.0).abs() < 1e-12 && a.dot(&b).abs() < 1e-12);
+    }
+    assert!((uvw.u.cross(&uvw.v) - uvw.w).length() < 1e-12);
+  }
+
+  #[test]
+  fn bases_are_right_handed() {
+    let normals = [
+      Vec3::new(0.0, 0.0, 1.0), Vec3::new(0.0, 0.0, -1.0), Vec3::new(1.0, 0.0, 0.0), Vec3::new(-1.0, 0.0, 0.0),
+      Vec3::new(0.0, 1.0, 0.0), Vec3::new(0.3, -0.5, 0.8), Vec3::new(-2.0, 0.1, 0.05), Vec3::new(0.95, 0.2, 0.1),
+    ];
+    for n in normals {
+      let uvw = Onb::new(&n);
+      assert_right_handed(&uvw);
+      assert!((uvw.w - n.unit_vector()).length() < 1e-12);
+
+      let with_tangent = Onb::new_with_tangent(&n, &Vec3::new(0.2, 0.7, -0.4));
+      assert_right_handed(&with_tangent);
+      assert_right_handed(&Onb::new_with_tangent(&n, &n));
+
+      let a = Vec3::new(0.4, -1.3, 2.2);
+      assert!((uvw.local(&uvw.to_local(&a)) - a).length() < 1e-12);
+    }
+  }
+}
\ No newline at end of file
@@ -0,0 +1,70 @@
+use std::{fs::File, io::{BufWriter, Seek, Write}, path::Path};
+
+use exr::prelude::{AnyChannel, AnyChannels, Encoding, FlatSamples, Layer, LayerAttributes, SmallVec, WritableImage, f16, read_first_rgba_layer_from_file};
+
+use crate::{color::Color, image::{Image, ImageError}};
+
+#[derive(Copy, Clone)]
+pub enum ExrPrecision {
+  Half,
+  Float,
+}
+
+// Read the R, G and B channels of the first layer that has them, ignoring alpha
+pub fn load_exr<P: AsRef<Path>>(path: P) -> Result<Image, ImageError> {
+  let image = read_first_rgba_layer_from_file(
+    path,
+    |resolution, _| Image::new(resolution.width(), resolution.height()),
+    |image: &mut Image, position, (r, g, b, _): (f32, f32, f32, f32)| {
+      image.set_pixel(position.x(), position.y(), Color::new(r as f64, g as f64, b as f64));
+    },
+  ).map_err(|err| ImageError::Format(err.to_string()))?;
+  Ok(image.layer_data.channel_data.pixels)
+}
+
+pub fn save_exr<P: AsRef<Path>>(path: P, layers: &[(&str, &Image)], precision: ExrPrecision) -> Result<(), ImageError> {
+  write_exr(BufWriter::new(File::create(path)?), layers, precision)
+}
+
+// Write named images into a single OpenEXR file, storing the unclamped linear colors. The
+// layer named "" becomes the R, G and B channels, other layers are stored as name.R, name.G
+// and name.B, so all layers must have the same size.
+pub fn write_exr<W: Write + Seek>(writer: W, layers: &[(&str, &Image)], precision: ExrPrecision) -> Result<(), ImageError> {
+  let (width, height) = match layers.first() {
+    Some((_, image)) => (image.width, image.height),
+    None => return Err(ImageError::Format(String::from("an OpenEXR file needs at least one layer"))),
+  };
+  if layers.iter().any(|(_, image)| image.width != width || image.height != height) {
+    return Err(ImageError::Format(String::from("all OpenEXR layers must have the same size")));
+  }
+
+  let mut channels = SmallVec::new();
+  for (name, image) in layers {
+    let prefix = if name.is_empty() { String::new() } else { format!("{}.", name) };
+    let components: [fn(&Image, usize) -> f64; 3] = [
+      |image, i| image.pixels[i].x,
+      |image, i| image.pixels[i].y,
+      |image, i| image.pixels[i].z,
+    ];
+    for (channel, component) in ["R", "G", "B"].iter().zip(components) {
+      let values = (0..image.pixels.len()).map(|i| component(image, i) as f32);
+      let samples = match precision {
+        ExrPrecision::Half => FlatSamples::F16(values.map(f16::from_f32).collect()),
+        ExrPrecision::Float => FlatSamples::F32(values.collect()),
+      };
+      channels.push(AnyChannel::new(format!("{}{}", prefix, channel).as_str(), samples));
+    }
+  }
+
+  let layer = Layer::new(
+    (width, height),
+    LayerAttributes::default(),
+    Encoding::SMALL_LOSSLESS,
+    AnyChannels::sort(channels),
+  );
+
+  exr::prelude::Image::from_layer(layer)
+    .write()
+    .to_buffered(writer)
+    .map_err(|err| ImageError::Format(err.to_string()))
+}
\ No newline at end of file
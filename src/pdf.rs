@@ -0,0 +1,445 @@
+use crate::{color::Color, common::Common, environment::Environment, hittable::Hittable, microfacet::{self, TrowbridgeReitz}, onb::Onb, sampler::PixelSampler, vec3::{Point3, Vec3}};
+
+// A probability density over directions that can also generate directions distributed by it
+pub trait Pdf {
+  fn value(&self, direction: &Vec3) -> f64;
+
+  // Generate a direction from the sampler's current bounce. A zero vector means the sample was
+  // absorbed, for densities that don't integrate to one
+  fn generate(&self, sampler: &mut PixelSampler) -> Vec3;
+}
+
+// Uniform density over the whole sphere of directions
+pub struct SpherePdf {
+}
+
+impl SpherePdf {
+  pub fn new() -> SpherePdf {
+    SpherePdf {}
+  }
+}
+
+impl Default for SpherePdf {
+  fn default() -> Self {
+    SpherePdf::new()
+  }
+}
+
+impl Pdf for SpherePdf {
+  fn value(&self, _direction: &Vec3) -> f64 {
+    1.0 / (4.0 * Common::PI)
+  }
+
+  fn generate(&self, sampler: &mut PixelSampler) -> Vec3 {
+    Vec3::sample_unit_vector(sampler.get_2d())
+  }
+}
+
+// Density proportional to the cosine to a surface normal, matching the Lambertian BRDF
+pub struct CosinePdf {
+  uvw: Onb,
+}
+
+impl CosinePdf {
+  pub fn new(w: &Vec3) -> CosinePdf {
+    CosinePdf {
+      uvw: Onb::new(w)
+    }
+  }
+}
+
+impl Pdf for CosinePdf {
+  fn value(&self, direction: &Vec3) -> f64 {
+    let cosine_theta = direction.unit_vector().dot(&self.uvw.w);
+    (cosine_theta / Common::PI).max(0.0)
+  }
+
+  fn generate(&self, sampler: &mut PixelSampler) -> Vec3 {
+    self.uvw.local(&Vec3::sample_cosine_direction(sampler.get_2d()))
+  }
+}
+
+// Reflection from a rough conductor, generated by sampling the microfacet normals visible from the
+// outgoing direction and mirroring it about them
+pub struct MicrofacetReflectionPdf {
+  uvw: Onb,
+  // Direction back along the incoming ray, in the frame of uvw
+  wo: Vec3,
+  distribution: TrowbridgeReitz,
+}
+
+impl MicrofacetReflectionPdf {
+  // uvw is the shading frame, whose axes line up with the distribution's anisotropic roughness
+  pub fn new(uvw: Onb, incoming: &Vec3, distribution: TrowbridgeReitz) -> MicrofacetReflectionPdf {
+    MicrofacetReflectionPdf {
+      uvw,
+      wo: uvw.to_local(&-incoming.unit_vector()),
+      distribution,
+    }
+  }
+}
+
+impl Pdf for MicrofacetReflectionPdf {
+  fn value(&self, direction: &Vec3) -> f64 {
+    let wi = self.uvw.to_local(&direction.unit_vector());
+    // Any reflectance gives the same density
+    microfacet::conductor(&self.distribution, Color::new(1.0, 1.0, 1.0), &self.wo, &wi).1
+  }
+
+  // Reflections that end up below the surface are absorbed, which is the energy lost by ignoring
+  // light bouncing between microfacets
+  fn generate(&self, sampler: &mut PixelSampler) -> Vec3 {
+    let wm = self.distribution.sample_visible(&self.wo, sampler.get_2d());
+    let wi = microfacet::reflect(&self.wo, &wm);
+    if wi.z <= 0.0 {
+      return Vec3::new(0.0, 0.0, 0.0);
+    }
+    self.uvw.local(&wi)
+  }
+}
+
+// Reflection from or transmission through a rough dielectric, picking between them on a visible
+// microfacet with the probability given by its Fresnel reflectance
+pub struct MicrofacetDielectricPdf {
+  uvw: Onb,
+  wo: Vec3,
+  distribution: TrowbridgeReitz,
+  eta: f64,
+}
+
+impl MicrofacetDielectricPdf {
+  // eta is the index of refraction behind the surface over the one on the side the normal points to
+  pub fn new(uvw: Onb, incoming: &Vec3, distribution: TrowbridgeReitz, eta: f64) -> MicrofacetDielectricPdf {
+    MicrofacetDielectricPdf {
+      uvw,
+      wo: uvw.to_local(&-incoming.unit_vector()),
+      distribution,
+      eta,
+    }
+  }
+}
+
+impl Pdf for MicrofacetDielectricPdf {
+  fn value(&self, direction: &Vec3) -> f64 {
+    let wi = self.uvw.to_local(&direction.unit_vector());
+    microfacet::dielectric(&self.distribution, self.eta, &self.wo, &wi).1
+  }
+
+  fn generate(&self, sampler: &mut PixelSampler) -> Vec3 {
+    // As for conductors, directions leaving on the wrong side of the surface are absorbed
+    let wm = self.distribution.sample_visible(&self.wo, sampler.get_2d());
+    let reflectance = microfacet::fresnel_dielectric(self.wo.dot(&wm), self.eta);
+    let wi = match microfacet::refract(&self.wo, &wm, self.eta) {
+      Some(refracted) if sampler.get_1d() >= reflectance => refracted,
+      _ => microfacet::reflect(&self.wo, &wm),
+    };
+    let reflected = self.wo.dot(&wm) * wi.dot(&wm) > 0.0;
+    if (self.wo.z * wi.z > 0.0) != reflected {
+      return Vec3::new(0.0, 0.0, 0.0);
+    }
+    self.uvw.local(&wi)
+  }
+}
+
+// Henyey-Greenstein phase function around the direction a ray was travelling, where g in (-1, 1)
+// tends from back scattering through isotropic at 0 to forward scattering
+pub struct HenyeyGreensteinPdf {
+  uvw: Onb,
+  g: f64,
+}
+
+impl HenyeyGreensteinPdf {
+  pub fn new(direction: &Vec3, g: f64) -> HenyeyGreensteinPdf {
+    HenyeyGreensteinPdf {
+      uvw: Onb::new(direction),
+      g,
+    }
+  }
+
+  // Phase function value for the cosine of the angle between the incoming and scattered directions
+  pub fn phase(cos_theta: f64, g: f64) -> f64 {
+    let denom = 1.0 + g * g - 2.0 * g * cos_theta;
+    (1.0 - g * g) / (4.0 * Common::PI * denom * denom.max(0.0).sqrt())
+  }
+}
+
+impl Pdf for HenyeyGreensteinPdf {
+  fn value(&self, direction: &Vec3) -> f64 {
+    HenyeyGreensteinPdf::phase(direction.unit_vector().dot(&self.uvw.w), self.g)
+  }
+
+  fn generate(&self, sampler: &mut PixelSampler) -> Vec3 {
+    // Invert the cumulative distribution of the cosine, falling back to uniform for small g
+    let (r1, r2) = sampler.get_2d();
+    let g = self.g;
+    let cos_theta = if g.abs() < 1e-3 {
+      1.0 - 2.0 * r1
+    } else {
+      let s = (1.0 - g * g) / (1.0 - g + 2.0 * g * r1);
+      ((1.0 + g * g - s * s) / (2.0 * g)).clamp(-1.0, 1.0)
+    };
+    let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+    let phi = 2.0 * Common::PI * r2;
+    self.uvw.local(&Vec3::new(sin_theta * phi.cos(), sin_theta * phi.sin(), cos_theta))
+  }
+}
+
+// Density of directions from an origin towards the surface of a hittable, for sampling lights, as
+// placed at the time of the rays leaving the origin
+pub struct HittablePdf<'a> {
+  objects: &'a dyn Hittable,
+  origin: Point3,
+  time: f64,
+}
+
+impl<'a> HittablePdf<'a> {
+  pub fn new(objects: &'a dyn Hittable, origin: Point3, time: f64) -> HittablePdf<'a> {
+    HittablePdf {
+      objects,
+      origin,
+      time,
+    }
+  }
+}
+
+impl Pdf for HittablePdf<'_> {
+  fn value(&self, direction: &Vec3) -> f64 {
+    self.objects.pdf_value(&self.origin, direction, self.time)
+  }
+
+  fn generate(&self, sampler: &mut PixelSampler) -> Vec3 {
+    self.objects.random(&self.origin, self.time, sampler)
+  }
+}
+
+// Density of directions towards an environment, for sampling the light it casts
+pub struct EnvironmentPdf<'a> {
+  environment: &'a dyn Environment,
+}
+
+impl<'a> EnvironmentPdf<'a> {
+  pub fn new(environment: &'a dyn Environment) -> EnvironmentPdf<'a> {
+    EnvironmentPdf {
+      environment
+    }
+  }
+}
+
+impl Pdf for EnvironmentPdf<'_> {
+  fn value(&self, direction: &Vec3) -> f64 {
+    self.environment.pdf_value(direction)
+  }
+
+  fn generate(&self, sampler: &mut PixelSampler) -> Vec3 {
+    self.environment.random(sampler)
+  }
+}
+
+// Weight of a sample drawn with density f_pdf that another strategy could have drawn with density
+// g_pdf, from Veach's power heuristic with an exponent of two
+pub fn power_heuristic(f_pdf: f64, g_pdf: f64) -> f64 {
+  let f = f_pdf * f_pdf;
+  let g = g_pdf * g_pdf;
+  if f + g <= 0.0 { 0.0 } else { f / (f + g) }
+}
+
+// Even mix of two densities, which weights samples from either with the balance heuristic
+pub struct MixturePdf<'a> {
+  p: [&'a dyn Pdf; 2],
+}
+
+impl<'a> MixturePdf<'a> {
+  pub fn new(p0: &'a dyn Pdf, p1: &'a dyn Pdf) -> MixturePdf<'a> {
+    MixturePdf {
+      p: [p0, p1]
+    }
+  }
+}
+
+impl Pdf for MixturePdf<'_> {
+  fn value(&self, direction: &Vec3) -> f64 {
+    0.5 * self.p[0].value(direction) + 0.5 * self.p[1].value(direction)
+  }
+
+  fn generate(&self, sampler: &mut PixelSampler) -> Vec3 {
+    if sampler.get_1d() < 0.5 {
+      self.p[0].generate(sampler)
+    } else {
+      self.p[1].generate(sampler)
+    }
+  }
+}
+
+// Mix of densities chosen with the given probabilities, such as the lobes of a layered material.
+// Weights don't need to sum to one, and densities with zero weight are left out
+pub struct WeightedPdf {
+  pdfs: Vec<(f64, Box<dyn Pdf>)>,
+  // Share of the samples the mix is chosen for, below one when other lobes that can't be sampled by a
+  // density take the rest
+  share: f64,
+}
+
+impl WeightedPdf {
+  pub fn new(pdfs: Vec<(f64, Box<dyn Pdf>)>) -> WeightedPdf {
+    let total = pdfs.iter().map(|(weight, _)| weight.max(0.0)).sum();
+    WeightedPdf::new_partial(pdfs, total)
+  }
+
+  // Mix whose weights are out of a total that includes lobes left out of it, so that its density only
+  // counts the samples it is chosen for
+  pub fn new_partial(pdfs: Vec<(f64, Box<dyn Pdf>)>, total: f64) -> WeightedPdf {
+    let pdfs: Vec<(f64, Box<dyn Pdf>)> = pdfs.into_iter().filter(|(weight, _)| *weight > 0.0).collect();
+    let sum: f64 = pdfs.iter().map(|(weight, _)| weight).sum();
+    WeightedPdf {
+      pdfs: pdfs.into_iter().map(|(weight, pdf)| (weight / sum, pdf)).collect(),
+      share: if total > 0.0 { sum / total } else { 0.0 },
+    }
+  }
+}
+
+impl Pdf for WeightedPdf {
+  fn value(&self, direction: &Vec3) -> f64 {
+    self.share * self.pdfs.iter().map(|(weight, pdf)| weight * pdf.value(direction)).sum::<f64>()
+  }
+
+  fn generate(&self, sampler: &mut PixelSampler) -> Vec3 {
+    let mut u = sampler.get_1d();
+    for (weight, pdf) in &self.pdfs {
+      if u < *weight {
+        return pdf.generate(sampler);
+      }
+      u -= weight;
+    }
+    match self.pdfs.last() {
+      Some((_, pdf)) => pdf.generate(sampler),
+      None => Vec3::new(0.0, 0.0, 0.0),
+    }
+  }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+  use super::*;
+  use crate::sampler::{IndependentSampler, PixelSample};
+
+  // Bins of the histograms, even in the cosine of the polar angle and in the azimuth so that every
+  // bin covers the same solid angle
+  const COS_BINS: usize = 8;
+  const PHI_BINS: usize = 16;
+
+  // Independent numbers for the index-th sample, drawn from the first bounce's slot
+  pub(crate) fn sampler(index: u64) -> PixelSampler<'static> {
+    let mut sampler = PixelSampler::new(&IndependentSampler, PixelSample { x: 0, y: 0, index, count: 1, seed: 7 });
+    sampler.start_bounce(0);
+    sampler
+  }
+
+  fn direction(cos_theta: f64, phi: f64) -> Vec3 {
+    let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+    Vec3::new(sin_theta * phi.cos(), sin_theta * phi.sin(), cos_theta)
+  }
+
+  // Integral of f over the part of the sphere between the given cosines and azimuths, from the
+  // midpoints of an n by n grid that is even in the polar angle, so that peaks near the poles are
+  // resolved as finely as anywhere else
+  fn integrate_region(f: &dyn Fn(&Vec3) -> f64, cos_theta: (f64, f64), phi: (f64, f64), n: usize) -> f64 {
+    let theta = (cos_theta.1.acos(), cos_theta.0.acos());
+    let (d_theta, d_phi) = ((theta.1 - theta.0) / n as f64, (phi.1 - phi.0) / n as f64);
+    let mut sum = 0.0;
+    for i in 0..n {
+      let theta = theta.0 + (i as f64 + 0.5) * d_theta;
+      for j in 0..n {
+        sum += f(&direction(theta.cos(), phi.0 + (j as f64 + 0.5) * d_phi)) * theta.sin();
+      }
+    }
+    sum * d_theta * d_phi
+  }
+
+  // Integral of f over the whole sphere of directions
+  pub(crate) fn integrate(f: &dyn Fn(&Vec3) -> f64) -> f64 {
+    integrate_region(f, (-1.0, 1.0), (0.0, 2.0 * Common::PI), 1000)
+  }
+
+  fn bin(direction: &Vec3) -> usize {
+    let direction = direction.unit_vector();
+    let i = ((direction.z + 1.0) / 2.0 * COS_BINS as f64) as usize;
+    let phi = direction.y.atan2(direction.x).rem_euclid(2.0 * Common::PI);
+    let j = (phi / (2.0 * Common::PI) * PHI_BINS as f64) as usize;
+    i.min(COS_BINS - 1) * PHI_BINS + j.min(PHI_BINS - 1)
+  }
+
+  // Check that the directions pdf generates land in each bin of the sphere as often as its value
+  // divided by share says, and are absorbed as often as that falls short of integrating to one
+  pub(crate) fn assert_generates_value(pdf: &dyn Pdf, share: f64) {
+    let n = 200_000;
+    let mut counts = vec![0.0; COS_BINS * PHI_BINS + 1];
+    for index in 0..n {
+      let direction = pdf.generate(&mut sampler(index));
+      let bin = if direction.near_zero() { COS_BINS * PHI_BINS } else { bin(&direction) };
+      counts[bin] += 1.0;
+    }
+
+    let density = |direction: &Vec3| pdf.value(direction) / share;
+    let mut expected: Vec<f64> = (0..COS_BINS * PHI_BINS)
+      .map(|bin| {
+        let (i, j) = ((bin / PHI_BINS) as f64, (bin % PHI_BINS) as f64);
+        let cos_theta = (-1.0 + 2.0 * i / COS_BINS as f64, -1.0 + 2.0 * (i + 1.0) / COS_BINS as f64);
+        let phi = (2.0 * Common::PI * j / PHI_BINS as f64, 2.0 * Common::PI * (j + 1.0) / PHI_BINS as f64);
+        n as f64 * integrate_region(&density, cos_theta, phi, 32)
+      })
+      .collect();
+    expected.push((n as f64 - expected.iter().sum::<f64>()).max(0.0));
+
+    for (bin, (count, expected)) in counts.iter().zip(&expected).enumerate() {
+      let tolerance = 5.0 * expected.sqrt() + 0.02 * expected + 10.0;
+      assert!((count - expected).abs() < tolerance, "bin {}: {} samples, expected {}", bin, count, expected);
+    }
+  }
+
+  fn normal() -> Vec3 {
+    Vec3::new(0.3, -0.5, 0.8).unit_vector()
+  }
+
+  #[test]
+  fn densities_integrate_to_one() {
+    let cosine = CosinePdf::new(&normal());
+    let sphere = SpherePdf::new();
+    let forward = HenyeyGreensteinPdf::new(&normal(), 0.7);
+    let backward = HenyeyGreensteinPdf::new(&normal(), -0.4);
+    let isotropic = HenyeyGreensteinPdf::new(&normal(), 0.0);
+    let mixture = MixturePdf::new(&cosine, &forward);
+    for pdf in [&cosine as &dyn Pdf, &sphere, &forward, &backward, &isotropic, &mixture] {
+      let integral = integrate(&|direction| pdf.value(direction));
+      assert!((integral - 1.0).abs() < 1e-3, "integral {}", integral);
+    }
+  }
+
+  #[test]
+  fn partial_mix_integrates_to_its_share() {
+    let pdf = WeightedPdf::new_partial(vec![
+      (1.0, Box::new(CosinePdf::new(&normal())) as Box<dyn Pdf>),
+      (3.0, Box::new(SpherePdf::new())),
+      (0.0, Box::new(HenyeyGreensteinPdf::new(&normal(), 0.5))),
+    ], 8.0);
+    let integral = integrate(&|direction| pdf.value(direction));
+    assert!((integral - 0.5).abs() < 1e-3, "integral {}", integral);
+  }
+
+  #[test]
+  fn generated_directions_follow_the_density() {
+    let cosine = CosinePdf::new(&normal());
+    let sphere = SpherePdf::new();
+    let forward = HenyeyGreensteinPdf::new(&normal(), 0.7);
+    let backward = HenyeyGreensteinPdf::new(&normal(), -0.4);
+    let mixture = MixturePdf::new(&cosine, &backward);
+    for pdf in [&cosine as &dyn Pdf, &sphere, &forward, &backward, &mixture] {
+      assert_generates_value(pdf, 1.0);
+    }
+
+    let weighted = WeightedPdf::new_partial(vec![
+      (1.0, Box::new(CosinePdf::new(&normal())) as Box<dyn Pdf>),
+      (3.0, Box::new(HenyeyGreensteinPdf::new(&normal(), 0.7))),
+    ], 8.0);
+    assert_generates_value(&weighted, 0.5);
+  }
+}
\ No newline at end of file
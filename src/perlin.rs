@@ -0,0 +1,127 @@
+use rand::{rngs::SmallRng, Rng, SeedableRng};
+
+use crate::vec3::{Point3, Vec3};
+
+const POINT_COUNT: usize = 256;
+
+// Seed of the gradient and permutation tables, fixed so that noise looks the same in every render
+// whatever the scene seed and the thread building the texture
+const SEED: u64 = 0x9e37_79b9_7f4a_7c15;
+
+pub struct Perlin {
+  ranvec: Vec<Vec3>,
+  perm_x: Vec<usize>,
+  perm_y: Vec<usize>,
+  perm_z: Vec<usize>,
+}
+
+impl Perlin {
+  pub fn new() -> Perlin {
+    let mut rng = SmallRng::seed_from_u64(SEED);
+    let coordinate = |rng: &mut SmallRng| rng.gen_range(-1.0..1.0);
+    Perlin {
+      ranvec: (0..POINT_COUNT)
+        .map(|_| Vec3::new(coordinate(&mut rng), coordinate(&mut rng), coordinate(&mut rng)).unit_vector())
+        .collect(),
+      perm_x: Perlin::generate_perm(&mut rng),
+      perm_y: Perlin::generate_perm(&mut rng),
+      perm_z: Perlin::generate_perm(&mut rng),
+    }
+  }
+
+  // Gradient noise in the range [-1,1]
+  pub fn noise(&self, p: &Point3) -> f64 {
+    let u = p.x - p.x.floor();
+    let v = p.y - p.y.floor();
+    let w = p.z - p.z.floor();
+
+    let i = p.x.floor() as i64;
+    let j = p.y.floor() as i64;
+    let k = p.z.floor() as i64;
+
+    let mut c = [[[Vec3::new(0.0, 0.0, 0.0); 2]; 2]; 2];
+    for (di, plane) in c.iter_mut().enumerate() {
+      for (dj, row) in plane.iter_mut().enumerate() {
+        for (dk, corner) in row.iter_mut().enumerate() {
+          *corner = self.ranvec[
+            self.perm_x[((i + di as i64) & 255) as usize]
+            ^ self.perm_y[((j + dj as i64) & 255) as usize]
+            ^ self.perm_z[((k + dk as i64) & 255) as usize]
+          ];
+        }
+      }
+    }
+
+    Perlin::perlin_interp(&c, u, v, w)
+  }
+
+  // Sum of several octaves of the absolute noise value
+  pub fn turbulence(&self, p: &Point3, depth: usize) -> f64 {
+    let mut accum = 0.0;
+    let mut temp_p = *p;
+    let mut weight = 1.0;
+
+    for _ in 0..depth {
+      accum += weight * self.noise(&temp_p);
+      weight *= 0.5;
+      temp_p = temp_p * 2.0;
+    }
+
+    accum.abs()
+  }
+
+  fn generate_perm(rng: &mut SmallRng) -> Vec<usize> {
+    let mut p: Vec<usize> = (0..POINT_COUNT).collect();
+    for i in (1..POINT_COUNT).rev() {
+      let target = rng.gen_range(0..=i);
+      p.swap(i, target);
+    }
+    p
+  }
+
+  fn perlin_interp(c: &[[[Vec3; 2]; 2]; 2], u: f64, v: f64, w: f64) -> f64 {
+    // Hermitian smoothing removes the grid artifacts of plain trilinear interpolation
+    let uu = u * u * (3.0 - 2.0 * u);
+    let vv = v * v * (3.0 - 2.0 * v);
+    let ww = w * w * (3.0 - 2.0 * w);
+    let mut accum = 0.0;
+
+    for (i, plane) in c.iter().enumerate() {
+      for (j, row) in plane.iter().enumerate() {
+        for (k, corner) in row.iter().enumerate() {
+          let (fi, fj, fk) = (i as f64, j as f64, k as f64);
+          let weight_v = Vec3::new(u - fi, v - fj, w - fk);
+          accum += (fi * uu + (1.0 - fi) * (1.0 - uu))
+            * (fj * vv + (1.0 - fj) * (1.0 - vv))
+            * (fk * ww + (1.0 - fk) * (1.0 - ww))
+            * corner.dot(&weight_v);
+        }
+      }
+    }
+
+    accum
+  }
+}
+
+impl Default for Perlin {
+  fn default() -> Self {
+    Perlin::new()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn noise_is_the_same_on_every_thread() {
+    let points: Vec<Point3> = (0..16).map(|i| Point3::new(i as f64 * 0.37, i as f64 * 1.13, -(i as f64) * 0.71)).collect();
+    let sample = move || {
+      let perlin = Perlin::new();
+      points.iter().map(|p| perlin.noise(p)).collect::<Vec<f64>>()
+    };
+    let expected = sample.clone()();
+    assert_eq!(std::thread::spawn(sample).join().unwrap(), expected);
+    assert!(expected.iter().any(|&value| value != 0.0));
+  }
+}
\ No newline at end of file
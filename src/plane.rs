@@ -0,0 +1,95 @@
+use std::sync::Arc;
+
+use crate::{aabb::Aabb, hittable::{HitRecord, Hittable}, interval::Interval, material::Material, onb::Onb, ray::Ray, vec3::{Point3, Vec3}};
+
+// Infinite plane through point, facing along normal. Its bounding box is unbounded, so the BVH
+// keeps it out of the hierarchy and tests it separately
+#[derive(Clone)]
+pub struct Plane {
+  point: Point3,
+  material: Arc<dyn Material>,
+  // Tangent basis of the plane, with w along the normal
+  uvw: Onb,
+}
+
+impl Plane {
+  pub fn new(point: Point3, normal: Vec3, material: Arc<dyn Material>) -> Plane {
+    Plane {
+      point,
+      material,
+      uvw: Onb::new(&normal),
+    }
+  }
+}
+
+impl Hittable for Plane {
+  fn hit(&self, ray: &Ray, ray_t: &Interval) -> (bool, Option<HitRecord<'_>>) {
+    let normal = self.uvw.w;
+    let denom = normal.dot(&ray.direction);
+
+    // No hit if the ray is parallel to the plane
+    if denom.abs() < 1e-8 {
+      return (false, None);
+    }
+
+    let t = normal.dot(&(self.point - ray.origin)) / denom;
+    if !ray_t.surrounds(t) {
+      return (false, None);
+    }
+
+    // Texture coordinates repeat every unit of distance along the plane
+    let p = ray.at(t);
+    let offset = p - self.point;
+    let u = offset.dot(&self.uvw.u).rem_euclid(1.0);
+    let v = offset.dot(&self.uvw.v).rem_euclid(1.0);
+
+    let rec = HitRecord {
+      p,
+      t,
+      u,
+      v,
+      material: self.material.as_ref(),
+      normal,
+      geometric_normal: normal,
+      dpdu: self.uvw.u,
+      dpdv: self.uvw.v,
+      front_face: false,
+    };
+    let rec = rec.set_face_normal(ray, normal);
+
+    (true, Some(rec))
+  }
+
+  fn bounding_box(&self) -> Aabb {
+    Aabb::UNIVERSE
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{color::Color, material::Lambertian};
+
+  #[test]
+  fn hits_everywhere_with_repeating_uvs() {
+    let plane = Plane::new(Point3::new(0.0, 1.0, 0.0), Vec3::new(0.0, 1.0, 0.0), Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5))));
+    let ray_t = Interval::new_from_range(0.001, f64::INFINITY);
+    let (u, v) = (plane.uvw.u, plane.uvw.v);
+    for (a, b) in [(0.25, 0.5), (1000.25, -3.5), (-0.75, 7.0)] {
+      let target = plane.point + u * a + v * b;
+      let (hit, rec) = plane.hit(&Ray::new(target + Vec3::new(0.3, 2.0, -0.1), Vec3::new(-0.3, -2.0, 0.1)), &ray_t);
+      assert!(hit);
+      let rec = rec.unwrap();
+      assert!((rec.t - 1.0).abs() < 1e-9 && rec.front_face && rec.normal.y > 0.0);
+      assert!((rec.u - a.rem_euclid(1.0)).abs() < 1e-6 && (rec.v - b.rem_euclid(1.0)).abs() < 1e-6, "uv {} {}", rec.u, rec.v);
+    }
+
+    // From below the normal is turned down, while rays parallel to it or leaving it miss
+    let (_, rec) = plane.hit(&Ray::new(Point3::new(0.0, -1.0, 0.0), Vec3::new(0.0, 1.0, 0.0)), &ray_t);
+    let rec = rec.unwrap();
+    assert!(!rec.front_face && rec.normal.y < 0.0);
+    assert!(!plane.hit(&Ray::new(Point3::new(0.0, 2.0, 0.0), Vec3::new(1.0, 0.0, 0.0)), &ray_t).0);
+    assert!(!plane.hit(&Ray::new(Point3::new(0.0, 2.0, 0.0), Vec3::new(0.0, 1.0, 0.0)), &ray_t).0);
+    assert!(!plane.bounding_box().is_bounded());
+  }
+}
\ No newline at end of file
@@ -0,0 +1,374 @@
+use std::sync::Arc;
+
+use crate::{color::Color, common::Common, hittable::HitRecord, material::{Material, ScatterRecord}, microfacet::{self, TrowbridgeReitz}, pdf::{CosinePdf, MicrofacetDielectricPdf, MicrofacetReflectionPdf, Pdf, WeightedPdf}, ray::Ray, sampler::PixelSampler, texture::{SolidColor, Texture}, vec3::Vec3};
+
+// Reflectance of the clear coat at normal incidence, that of a coat with an index of refraction of 1.5
+const CLEARCOAT_F0: f64 = 0.04;
+
+// Layered material after Burley's principled BSDF, covering most real surfaces with a handful of
+// parameters in [0, 1]. A diffuse base with sheen and a subsurface look sits under a GGX specular
+// layer, metallic blends towards a conductor tinted by the base color, transmission towards rough
+// glass, and a clear coat goes on top. Scalar parameters are read from the luminance of their textures
+pub struct Principled {
+  base_color: Arc<dyn Texture>,
+  metallic: Arc<dyn Texture>,
+  roughness: Arc<dyn Texture>,
+  // Reflectance of the dielectric specular layer, where 0.5 is the common 4% at normal incidence
+  specular: Arc<dyn Texture>,
+  sheen: Arc<dyn Texture>,
+  // Blend of the sheen from white towards the hue of the base color
+  sheen_tint: Arc<dyn Texture>,
+  clearcoat: Arc<dyn Texture>,
+  clearcoat_roughness: Arc<dyn Texture>,
+  transmission: Arc<dyn Texture>,
+  // Blend of the diffuse lobe towards a flatter look, approximating light scattered under the surface
+  subsurface: Arc<dyn Texture>,
+  // Index of refraction used for transmission
+  ior: f64,
+}
+
+// Parameters of a Principled material looked up at a hit point
+struct Parameters {
+  base_color: Color,
+  metallic: f64,
+  roughness: f64,
+  specular: f64,
+  sheen: f64,
+  sheen_tint: f64,
+  clearcoat: f64,
+  clearcoat_roughness: f64,
+  transmission: f64,
+  subsurface: f64,
+}
+
+impl Parameters {
+  // Share of the light handled by the diffuse base, the specular reflection and the glass lobe
+  fn diffuse_weight(&self) -> f64 {
+    (1.0 - self.metallic) * (1.0 - self.transmission)
+  }
+
+  fn specular_weight(&self) -> f64 {
+    1.0 - (1.0 - self.metallic) * self.transmission
+  }
+
+  fn transmission_weight(&self) -> f64 {
+    (1.0 - self.metallic) * self.transmission
+  }
+
+  // Reflectance of the specular layer at normal incidence, from dielectric to the base color for metals
+  fn specular_f0(&self) -> Color {
+    let dielectric = 0.08 * self.specular;
+    Color::new(dielectric, dielectric, dielectric) * (1.0 - self.metallic) + self.base_color * self.metallic
+  }
+
+  fn distribution(&self) -> TrowbridgeReitz {
+    TrowbridgeReitz::new(self.roughness, self.roughness)
+  }
+
+  fn clearcoat_distribution(&self) -> TrowbridgeReitz {
+    TrowbridgeReitz::new(self.clearcoat_roughness, self.clearcoat_roughness)
+  }
+
+  // Probability of sampling each lobe, in the order diffuse, specular, transmission and clear coat,
+  // in proportion to a rough estimate of how much light each reflects
+  fn lobe_probabilities(&self) -> [f64; 4] {
+    [
+      self.diffuse_weight() * self.base_color.luminance().max(0.1),
+      self.specular_weight() * self.specular_f0().luminance().max(0.25),
+      self.transmission_weight(),
+      0.25 * self.clearcoat,
+    ]
+  }
+
+  // Which of the lobes, in the same order, are too smooth to be sampled and are specular instead
+  fn smooth_lobes(&self) -> [bool; 4] {
+    let smooth = self.distribution().is_smooth();
+    [false, smooth, smooth, self.clearcoat_distribution().is_smooth()]
+  }
+}
+
+impl Principled {
+  pub fn new(base_color: Color) -> Principled {
+    Principled::new_from_texture(Arc::new(SolidColor::new(base_color)))
+  }
+
+  // A rough, non metallic surface of the base color, with every other lobe turned off
+  pub fn new_from_texture(base_color: Arc<dyn Texture>) -> Principled {
+    Principled {
+      base_color,
+      metallic: Principled::constant(0.0),
+      roughness: Principled::constant(0.5),
+      specular: Principled::constant(0.5),
+      sheen: Principled::constant(0.0),
+      sheen_tint: Principled::constant(0.5),
+      clearcoat: Principled::constant(0.0),
+      clearcoat_roughness: Principled::constant(0.03),
+      transmission: Principled::constant(0.0),
+      subsurface: Principled::constant(0.0),
+      ior: 1.5,
+    }
+  }
+
+  // Texture with the same value everywhere, for setting a parameter to a constant
+  pub fn constant(value: f64) -> Arc<dyn Texture> {
+    Arc::new(SolidColor::new(Color::new(value, value, value)))
+  }
+
+  pub fn set_metallic(&mut self, metallic: Arc<dyn Texture>) {
+    self.metallic = metallic;
+  }
+
+  pub fn set_roughness(&mut self, roughness: Arc<dyn Texture>) {
+    self.roughness = roughness;
+  }
+
+  pub fn set_specular(&mut self, specular: Arc<dyn Texture>) {
+    self.specular = specular;
+  }
+
+  pub fn set_sheen(&mut self, sheen: Arc<dyn Texture>) {
+    self.sheen = sheen;
+  }
+
+  pub fn set_sheen_tint(&mut self, sheen_tint: Arc<dyn Texture>) {
+    self.sheen_tint = sheen_tint;
+  }
+
+  pub fn set_clearcoat(&mut self, clearcoat: Arc<dyn Texture>) {
+    self.clearcoat = clearcoat;
+  }
+
+  pub fn set_clearcoat_roughness(&mut self, clearcoat_roughness: Arc<dyn Texture>) {
+    self.clearcoat_roughness = clearcoat_roughness;
+  }
+
+  pub fn set_transmission(&mut self, transmission: Arc<dyn Texture>) {
+    self.transmission = transmission;
+  }
+
+  pub fn set_subsurface(&mut self, subsurface: Arc<dyn Texture>) {
+    self.subsurface = subsurface;
+  }
+
+  pub fn set_ior(&mut self, ior: f64) {
+    self.ior = ior;
+  }
+
+  fn parameters(&self, rec: &HitRecord) -> Parameters {
+    let scalar = |texture: &Arc<dyn Texture>| texture.value(rec.u, rec.v, &rec.p).luminance().clamp(0.0, 1.0);
+    Parameters {
+      base_color: self.base_color.value(rec.u, rec.v, &rec.p),
+      metallic: scalar(&self.metallic),
+      roughness: scalar(&self.roughness),
+      specular: scalar(&self.specular),
+      sheen: scalar(&self.sheen),
+      sheen_tint: scalar(&self.sheen_tint),
+      clearcoat: scalar(&self.clearcoat),
+      clearcoat_roughness: scalar(&self.clearcoat_roughness),
+      transmission: scalar(&self.transmission),
+      subsurface: scalar(&self.subsurface),
+    }
+  }
+
+  // Index of refraction behind the surface relative to the side the ray arrives from
+  fn eta(&self, rec: &HitRecord) -> f64 {
+    if rec.front_face { self.ior } else { 1.0 / self.ior }
+  }
+
+  // Mirror reflection or glass for a smooth lobe, with its weight among the lobes but not yet divided by
+  // the probability of choosing it
+  fn scatter_smooth(&self, lobe: usize, params: &Parameters, r_in: &Ray, rec: &HitRecord, sampler: &mut PixelSampler) -> (Color, Ray) {
+    let wo = -r_in.direction.unit_vector();
+    let cos_o = wo.dot(&rec.normal);
+    let f0 = Color::new(CLEARCOAT_F0, CLEARCOAT_F0, CLEARCOAT_F0);
+    let coat = microfacet::fresnel_schlick(f0, cos_o).x * params.clearcoat;
+    let reflected = r_in.redirected(rec.p, microfacet::reflect(&wo, &rec.normal));
+    match lobe {
+      1 => (microfacet::fresnel_schlick(params.specular_f0(), cos_o) * (params.specular_weight() * (1.0 - coat)), reflected),
+      2 => {
+        let reflectance = microfacet::fresnel_dielectric(cos_o, self.eta(rec));
+        let weight = params.transmission_weight() * (1.0 - coat);
+        match microfacet::refract(&wo, &rec.normal, self.eta(rec)) {
+          Some(refracted) if sampler.get_1d() >= reflectance => (params.base_color * weight, r_in.redirected(rec.p, refracted)),
+          _ => (Color::new(weight, weight, weight), reflected),
+        }
+      }
+      _ => (Color::new(coat, coat, coat), reflected),
+    }
+  }
+
+  // Burley's diffuse with retro-reflection at grazing angles, blended towards the flatter subsurface
+  // approximation, plus the sheen. Both directions are above the surface
+  fn diffuse(params: &Parameters, wo: &Vec3, wi: &Vec3) -> Color {
+    let schlick_weight = |cos: f64| (1.0 - cos.clamp(0.0, 1.0)).powi(5);
+    let wh = (*wo + *wi).unit_vector();
+    let cos_d = wi.dot(&wh);
+    let (fl, fv) = (schlick_weight(wi.z), schlick_weight(wo.z));
+
+    let fd90 = 0.5 + 2.0 * params.roughness * cos_d * cos_d;
+    let fd = (1.0 + (fd90 - 1.0) * fl) * (1.0 + (fd90 - 1.0) * fv);
+    let fss90 = params.roughness * cos_d * cos_d;
+    let fss = (1.0 + (fss90 - 1.0) * fl) * (1.0 + (fss90 - 1.0) * fv);
+    // Subsurface only blends in the flat term of Burley's diffuse, an approximation of the BSSRDF
+    let ss = 1.25 * (fss * (1.0 / (wi.z + wo.z) - 0.5) + 0.5);
+    let diffuse = params.base_color * ((fd * (1.0 - params.subsurface) + ss * params.subsurface) / Common::PI);
+
+    let luminance = params.base_color.luminance();
+    let tint = if luminance > 0.0 { params.base_color / luminance } else { Color::new(1.0, 1.0, 1.0) };
+    let sheen_color = Color::new(1.0, 1.0, 1.0) * (1.0 - params.sheen_tint) + tint * params.sheen_tint;
+    diffuse + sheen_color * (params.sheen * schlick_weight(cos_d))
+  }
+}
+
+impl Material for Principled {
+  // Lobes are sampled in proportion to a rough estimate of how much light each reflects. Lobes too
+  // smooth to be sampled are followed as a mirror or as glass when they are chosen
+  fn scatter(&self, r_in: &Ray, rec: &HitRecord, sampler: &mut PixelSampler) -> (bool, Option<ScatterRecord>) {
+    let params = self.parameters(rec);
+    let probabilities = params.lobe_probabilities();
+    let smooth = params.smooth_lobes();
+    let rough = |lobe: usize| if smooth[lobe] { 0.0 } else { probabilities[lobe] };
+    let pdfs = vec![
+      (rough(0), Box::new(CosinePdf::new(&rec.normal)) as Box<dyn Pdf>),
+      (rough(1), Box::new(MicrofacetReflectionPdf::new(rec.shading_frame(), &r_in.direction, params.distribution()))),
+      (rough(2), Box::new(MicrofacetDielectricPdf::new(rec.shading_frame(), &r_in.direction, params.distribution(), self.eta(rec)))),
+      (rough(3), Box::new(MicrofacetReflectionPdf::new(rec.shading_frame(), &r_in.direction, params.clearcoat_distribution()))),
+    ];
+    if !smooth.iter().zip(&probabilities).any(|(&smooth, &probability)| smooth && probability > 0.0) {
+      return (true, Some(ScatterRecord::Sampled { pdf: Box::new(WeightedPdf::new(pdfs)) }));
+    }
+
+    let total: f64 = probabilities.iter().sum();
+    let mut u = sampler.get_1d() * total;
+    let lobe = (0..4).find(|&lobe| {
+      let chosen = u < probabilities[lobe];
+      u -= probabilities[lobe];
+      chosen
+    });
+    let specular = match lobe {
+      Some(lobe) if smooth[lobe] => {
+        let (attenuation, scattered) = self.scatter_smooth(lobe, &params, r_in, rec, sampler);
+        Some((attenuation * (total / probabilities[lobe]), scattered))
+      }
+      _ => None,
+    };
+    let pdf = Box::new(WeightedPdf::new_partial(pdfs, total));
+    (true, Some(ScatterRecord::Mixed { pdf, specular }))
+  }
+
+  // Only the rough lobes, as the smooth ones reflect nothing but in their specular direction
+  fn eval(&self, r_in: &Ray, rec: &HitRecord, scattered: &Ray) -> Color {
+    let params = self.parameters(rec);
+    let smooth = params.smooth_lobes();
+    let uvw = rec.shading_frame();
+    let wo = uvw.to_local(&-r_in.direction.unit_vector());
+    let wi = uvw.to_local(&scattered.direction.unit_vector());
+
+    let mut base = Color::new(0.0, 0.0, 0.0);
+    if wo.z > 0.0 && wi.z > 0.0 && params.diffuse_weight() > 0.0 {
+      base = base + Principled::diffuse(&params, &wo, &wi) * (params.diffuse_weight() * wi.z);
+    }
+    if params.specular_weight() > 0.0 && !smooth[1] {
+      base = base + microfacet::conductor(&params.distribution(), params.specular_f0(), &wo, &wi).0 * params.specular_weight();
+    }
+    if params.transmission_weight() > 0.0 && !smooth[2] {
+      // Light passing through the surface takes on the base color
+      let glass = microfacet::dielectric(&params.distribution(), self.eta(rec), &wo, &wi).0;
+      let tint = if wo.z * wi.z < 0.0 { params.base_color } else { Color::new(1.0, 1.0, 1.0) };
+      base = base + tint * (glass * params.transmission_weight());
+    }
+    if params.clearcoat <= 0.0 {
+      return base;
+    }
+
+    // Light reflected by the coat doesn't reach the layers below
+    let f0 = Color::new(CLEARCOAT_F0, CLEARCOAT_F0, CLEARCOAT_F0);
+    let coat_reflectance = microfacet::fresnel_schlick(f0, wo.z).x * params.clearcoat;
+    let coat = if smooth[3] {
+      Color::new(0.0, 0.0, 0.0)
+    } else {
+      microfacet::conductor(&params.clearcoat_distribution(), f0, &wo, &wi).0 * params.clearcoat
+    };
+    base * (1.0 - coat_reflectance) + coat
+  }
+
+  fn albedo(&self, rec: &HitRecord) -> Color {
+    self.base_color.value(rec.u, rec.v, &rec.p)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{pdf::tests::{integrate, sampler}, vec3::Point3};
+
+  fn hit_record(material: &dyn Material) -> HitRecord<'_> {
+    let normal = Vec3::new(0.0, 0.0, 1.0);
+    HitRecord {
+      p: Point3::new(0.0, 0.0, 0.0),
+      normal,
+      geometric_normal: normal,
+      dpdu: Vec3::new(1.0, 0.0, 0.0),
+      dpdv: Vec3::new(0.0, 1.0, 0.0),
+      material,
+      t: 1.0,
+      u: 0.5,
+      v: 0.5,
+      front_face: true,
+    }
+  }
+
+  // Light reflected towards the incoming ray from a uniform environment, as the mean throughput of the
+  // paths that scatter continues, and as the integral of eval plus what the smooth lobes reflect
+  fn albedos(material: &Principled, r_in: &Ray) -> (Color, Color) {
+    let rec = hit_record(material);
+    let n = 50_000;
+    let mut sum = Color::new(0.0, 0.0, 0.0);
+    for index in 0..n {
+      let mut sampler = sampler(index);
+      let (pdf, specular) = match material.scatter(r_in, &rec, &mut sampler).1.unwrap() {
+        ScatterRecord::Specular { attenuation, .. } => (None, Some(attenuation)),
+        ScatterRecord::Sampled { pdf } => (Some(pdf), None),
+        ScatterRecord::Mixed { pdf, specular } => (Some(pdf), specular.map(|(attenuation, _)| attenuation)),
+      };
+      if let Some(attenuation) = specular {
+        sum = sum + attenuation;
+        continue;
+      }
+      let pdf = pdf.unwrap();
+      let direction = pdf.generate(&mut sampler);
+      if direction.near_zero() || pdf.value(&direction) <= 0.0 {
+        continue;
+      }
+      sum = sum + material.eval(r_in, &rec, &r_in.redirected(rec.p, direction)) / pdf.value(&direction);
+    }
+
+    let params = material.parameters(&rec);
+    let integral = |channel: fn(&Color) -> f64| integrate(&|direction| channel(&material.eval(r_in, &rec, &r_in.redirected(rec.p, *direction))));
+    let mut expected = Color::new(integral(|c| c.x), integral(|c| c.y), integral(|c| c.z));
+    for lobe in [1, 3] {
+      if params.smooth_lobes()[lobe] {
+        expected = expected + material.scatter_smooth(lobe, &params, r_in, &rec, &mut sampler(0)).0;
+      }
+    }
+    (sum / n as f64, expected)
+  }
+
+  #[test]
+  fn scattered_throughput_matches_eval() {
+    let r_in = Ray::new(Point3::new(-0.6, 0.1, 0.8), Vec3::new(0.6, -0.1, -0.8));
+    for (roughness, clearcoat_roughness) in [(0.0, 0.0), (0.0, 0.4), (0.5, 0.0), (0.5, 0.4)] {
+      let mut material = Principled::new(Color::new(0.8, 0.3, 0.2));
+      material.set_roughness(Principled::constant(roughness));
+      material.set_clearcoat(Principled::constant(0.7));
+      material.set_clearcoat_roughness(Principled::constant(clearcoat_roughness));
+      material.set_sheen(Principled::constant(0.5));
+      material.set_subsurface(Principled::constant(0.3));
+
+      let (estimate, expected) = albedos(&material, &r_in);
+      for (estimate, expected) in [(estimate.x, expected.x), (estimate.y, expected.y), (estimate.z, expected.z)] {
+        assert!((estimate - expected).abs() < 0.02 * expected, "roughness {}: estimate {}, expected {}", roughness, estimate, expected);
+      }
+    }
+  }
+}
\ No newline at end of file
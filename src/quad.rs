@@ -0,0 +1,206 @@
+use std::sync::Arc;
+
+use crate::{aabb::Aabb, common::Common, hittable::{HitRecord, Hittable}, hittable_list::HittableList, interval::Interval, material::Material, ray::Ray, sampler::PixelSampler, vec3::{Point3, Vec3}};
+
+// Parallelogram spanned by the edges u and v from the corner q
+#[derive(Clone)]
+pub struct Quad {
+  q: Point3,
+  u: Vec3,
+  v: Vec3,
+  material: Arc<dyn Material>,
+  // Plane of the quad as normal . p = d, and w used to find the planar coordinates of a hit
+  normal: Vec3,
+  d: f64,
+  w: Vec3,
+  area: f64,
+  bbox: Aabb,
+}
+
+impl Quad {
+  pub fn new(q: Point3, u: Vec3, v: Vec3, material: Arc<dyn Material>) -> Quad {
+    let n = u.cross(&v);
+    let normal = n.unit_vector();
+    let d = normal.dot(&q);
+    let w = n / n.dot(&n);
+
+    // Compute the bounding box of all four vertices
+    let bbox_diagonal1 = Aabb::new_from_points(&q, &(q + u + v));
+    let bbox_diagonal2 = Aabb::new_from_points(&(q + u), &(q + v));
+
+    Quad {
+      q,
+      u,
+      v,
+      material,
+      normal,
+      d,
+      w,
+      area: n.length(),
+      bbox: Aabb::new_from_boxes(&bbox_diagonal1, &bbox_diagonal2),
+    }
+  }
+}
+
+impl Hittable for Quad {
+  fn hit(&self, ray: &Ray, ray_t: &Interval) -> (bool, Option<HitRecord<'_>>) {
+    let denom = self.normal.dot(&ray.direction);
+
+    // No hit if the ray is parallel to the plane
+    if denom.abs() < 1e-8 {
+      return (false, None);
+    }
+
+    let t = (self.d - self.normal.dot(&ray.origin)) / denom;
+    if !ray_t.surrounds(t) {
+      return (false, None);
+    }
+
+    // Determine if the hit point lies within the quad using its planar coordinates
+    let p = ray.at(t);
+    let planar_hitpt_vector = p - self.q;
+    let alpha = self.w.dot(&planar_hitpt_vector.cross(&self.v));
+    let beta = self.w.dot(&self.u.cross(&planar_hitpt_vector));
+
+    let unit = Interval::new_from_range(0.0, 1.0);
+    if !unit.contains(alpha) || !unit.contains(beta) {
+      return (false, None);
+    }
+
+    let rec = HitRecord {
+      p,
+      t,
+      u: alpha,
+      v: beta,
+      material: self.material.as_ref(),
+      normal: self.normal,
+      geometric_normal: self.normal,
+      dpdu: self.u,
+      dpdv: self.v,
+      front_face: false,
+    };
+    let rec = rec.set_face_normal(ray, self.normal);
+
+    (true, Some(rec))
+  }
+
+  fn bounding_box(&self) -> Aabb {
+    self.bbox
+  }
+
+  fn pdf_value(&self, origin: &Point3, direction: &Vec3, _time: f64) -> f64 {
+    let (hit, rec) = self.hit(&Ray::new(*origin, *direction), &Interval::new_from_range(0.001, Common::INFINITY));
+    if !hit {
+      return 0.0;
+    }
+
+    let t = rec.unwrap().t;
+    let distance_squared = t * t * direction.length_squared();
+    let cosine = (direction.dot(&self.normal) / direction.length()).abs();
+    if cosine < 1e-8 {
+      return 0.0;
+    }
+    distance_squared / (cosine * self.area)
+  }
+
+  fn random(&self, origin: &Point3, _time: f64, sampler: &mut PixelSampler) -> Vec3 {
+    let (u1, u2) = sampler.get_2d();
+    let p = self.q + (self.u * u1) + (self.v * u2);
+    p - origin
+  }
+}
+
+// Axis-aligned box with opposite corners a and b, made of six quads facing outwards
+pub fn make_box(a: &Point3, b: &Point3, material: Arc<dyn Material>) -> HittableList {
+  let min = Point3::new(a.x.min(b.x), a.y.min(b.y), a.z.min(b.z));
+  let max = Point3::new(a.x.max(b.x), a.y.max(b.y), a.z.max(b.z));
+
+  let dx = Vec3::new(max.x - min.x, 0.0, 0.0);
+  let dy = Vec3::new(0.0, max.y - min.y, 0.0);
+  let dz = Vec3::new(0.0, 0.0, max.z - min.z);
+
+  let sides: [Box<dyn Hittable>; 6] = [
+    Box::new(Quad::new(Point3::new(min.x, min.y, max.z), dx, dy, material.clone())), // front
+    Box::new(Quad::new(Point3::new(max.x, min.y, max.z), -dz, dy, material.clone())), // right
+    Box::new(Quad::new(Point3::new(max.x, min.y, min.z), -dx, dy, material.clone())), // back
+    Box::new(Quad::new(Point3::new(min.x, min.y, min.z), dz, dy, material.clone())), // left
+    Box::new(Quad::new(Point3::new(min.x, max.y, max.z), dx, -dz, material.clone())), // top
+    Box::new(Quad::new(Point3::new(min.x, min.y, min.z), dx, dz, material)), // bottom
+  ];
+
+  HittableList {
+    objects: sides.into_iter().collect()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{color::Color, material::Lambertian, pdf::{tests::{assert_generates_value, integrate}, HittablePdf}};
+
+  fn material() -> Arc<dyn Material> {
+    Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)))
+  }
+
+  fn quad() -> Quad {
+    Quad::new(Point3::new(-1.0, -1.0, 0.0), Vec3::new(2.0, 0.0, 0.0), Vec3::new(0.0, 1.5, 0.0), material())
+  }
+
+  fn hit(object: &dyn Hittable, origin: Point3, direction: Vec3) -> Option<HitRecord<'_>> {
+    object.hit(&Ray::new(origin, direction), &Interval::new_from_range(0.001, Common::INFINITY)).1
+  }
+
+  #[test]
+  fn hits_inside_the_edges_with_planar_uvs() {
+    let quad = quad();
+    let down = Vec3::new(0.0, 0.0, -1.0);
+    for (x, y, u, v) in [(-0.5, 0.125, 0.25, 0.75), (0.6, -0.7, 0.8, 0.2), (-0.999, -0.999, 0.0005, 0.0005 / 0.75)] {
+      let rec = hit(&quad, Point3::new(x, y, 2.0), down).unwrap_or_else(|| panic!("missed at {} {}", x, y));
+      assert!((rec.t - 2.0).abs() < 1e-12 && rec.front_face);
+      assert!((rec.u - u).abs() < 1e-9 && (rec.v - v).abs() < 1e-9, "uv {} {} at {} {}", rec.u, rec.v, x, y);
+    }
+
+    for (x, y) in [(-1.01, 0.0), (1.01, 0.0), (0.0, -1.01), (0.0, 0.51), (2.0, 2.0)] {
+      assert!(hit(&quad, Point3::new(x, y, 2.0), down).is_none(), "hit at {} {}", x, y);
+    }
+
+    // From behind the normal is turned towards the ray, and parallel rays miss
+    let rec = hit(&quad, Point3::new(0.0, 0.0, -2.0), -down).unwrap();
+    assert!(!rec.front_face && rec.normal.z < 0.0);
+    assert!(hit(&quad, Point3::new(-2.0, 0.0, 0.0), Vec3::new(1.0, 0.0, 0.0)).is_none());
+  }
+
+  #[test]
+  fn sampled_directions_follow_the_density() {
+    let quad = quad();
+    for origin in [Point3::new(0.3, 0.2, 0.4), Point3::new(-1.2, 0.3, -0.3)] {
+      let integral = integrate(&|direction| quad.pdf_value(&origin, direction, 0.0));
+      assert!((integral - 1.0).abs() < 2e-3, "integral {}", integral);
+      assert_generates_value(&HittablePdf::new(&quad, origin, 0.0), 1.0);
+    }
+  }
+
+  #[test]
+  fn boxes_span_their_corners_and_face_out() {
+    let (a, b) = (Point3::new(1.0, 2.0, 3.0), Point3::new(-1.0, 0.5, 5.0));
+    let sides = make_box(&a, &b, material());
+    assert_eq!(sides.objects.len(), 6);
+
+    let bbox = sides.bounding_box();
+    for (interval, min, max) in [(bbox.x, -1.0, 1.0), (bbox.y, 0.5, 2.0), (bbox.z, 3.0, 5.0)] {
+      assert!((interval.min - min).abs() < 1e-3 && (interval.max - max).abs() < 1e-3, "{} {}", interval.min, interval.max);
+    }
+
+    // Seen from the center every side is hit from the inside, at the face the ray leaves through
+    let center = Point3::new(0.0, 1.25, 4.0);
+    for (direction, distance) in [
+      (Vec3::new(1.0, 0.0, 0.0), 1.0), (Vec3::new(-1.0, 0.0, 0.0), 1.0),
+      (Vec3::new(0.0, 1.0, 0.0), 0.75), (Vec3::new(0.0, -1.0, 0.0), 0.75),
+      (Vec3::new(0.0, 0.0, 1.0), 1.0), (Vec3::new(0.0, 0.0, -1.0), 1.0),
+    ] {
+      let rec = hit(&sides, center, direction).unwrap();
+      assert!((rec.t - distance).abs() < 1e-12, "t {}", rec.t);
+      assert!(!rec.front_face && rec.normal.dot(&direction) < 0.0);
+    }
+  }
+}
\ No newline at end of file
@@ -0,0 +1,241 @@
+use std::io::{BufRead, Write};
+
+use crate::{color::Color, image::{Image, ImageError}};
+
+// Scanlines of this width or more are run-length encoded, shorter ones are stored flat
+const MIN_RLE_WIDTH: usize = 8;
+const MAX_RLE_WIDTH: usize = 0x7fff;
+
+// Write the image as a Radiance RGBE (.hdr) file, which shares one exponent between the channels
+pub fn write_hdr<W: Write>(image: &Image, mut writer: W) -> Result<(), ImageError> {
+  write!(writer, "#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y {} +X {}\n", image.height, image.width)?;
+
+  let rle = (MIN_RLE_WIDTH..=MAX_RLE_WIDTH).contains(&image.width);
+  for row in image.pixels.chunks(image.width.max(1)) {
+    let rgbe: Vec<[u8; 4]> = row.iter().map(to_rgbe).collect();
+    if !rle {
+      writer.write_all(&rgbe.concat())?;
+      continue;
+    }
+
+    // Adaptive RLE scanline: a marker with the width, then each component encoded separately
+    writer.write_all(&[2, 2, (image.width >> 8) as u8, (image.width & 0xff) as u8])?;
+    for component in 0..4 {
+      let values: Vec<u8> = rgbe.iter().map(|pixel| pixel[component]).collect();
+      write_rle_component(&values, &mut writer)?;
+    }
+  }
+  Ok(())
+}
+
+// Read a Radiance RGBE (.hdr) file stored top to bottom and left to right, the orientation every
+// common tool writes
+pub fn read_hdr<R: BufRead>(mut reader: R) -> Result<Image, ImageError> {
+  let mut line = String::new();
+  reader.read_line(&mut line)?;
+  if !line.starts_with("#?") {
+    return Err(ImageError::Format(String::from("not a Radiance HDR file")));
+  }
+
+  // Header variables end at an empty line
+  loop {
+    line.clear();
+    if reader.read_line(&mut line)? == 0 {
+      return Err(ImageError::Format(String::from("unexpected end of the Radiance HDR header")));
+    }
+    let variable = line.trim();
+    if variable.is_empty() {
+      break;
+    }
+    if let Some(format) = variable.strip_prefix("FORMAT=") {
+      if format != "32-bit_rle_rgbe" {
+        return Err(ImageError::Format(format!("unsupported Radiance HDR format '{}'", format)));
+      }
+    }
+  }
+
+  line.clear();
+  reader.read_line(&mut line)?;
+  let (height, width) = match line.split_whitespace().collect::<Vec<&str>>()[..] {
+    ["-Y", height, "+X", width] => match (height.parse::<usize>(), width.parse::<usize>()) {
+      (Ok(height), Ok(width)) => (height, width),
+      _ => return Err(ImageError::Format(format!("invalid Radiance HDR resolution '{}'", line.trim()))),
+    },
+    _ => return Err(ImageError::Format(format!("unsupported Radiance HDR orientation '{}'", line.trim()))),
+  };
+
+  let mut image = Image::new(width, height);
+  for j in 0..height {
+    let scanline = read_scanline(&mut reader, width)?;
+    for (i, rgbe) in scanline.iter().enumerate() {
+      image.set_pixel(i, j, from_rgbe(rgbe));
+    }
+  }
+  Ok(image)
+}
+
+fn read_scanline<R: BufRead>(reader: &mut R, width: usize) -> Result<Vec<[u8; 4]>, ImageError> {
+  let mut first = [0u8; 4];
+  reader.read_exact(&mut first)?;
+
+  let adaptive_rle = (MIN_RLE_WIDTH..=MAX_RLE_WIDTH).contains(&width) && first[0] == 2 && first[1] == 2 && first[2] < 128;
+  if !adaptive_rle {
+    return read_flat_scanline(reader, width, first);
+  }
+  if ((first[2] as usize) << 8 | first[3] as usize) != width {
+    return Err(ImageError::Format(String::from("Radiance HDR scanline width does not match the image")));
+  }
+
+  let mut scanline = vec![[0u8; 4]; width];
+  for component in 0..4 {
+    let mut pos = 0;
+    while pos < width {
+      let mut count = [0u8; 1];
+      reader.read_exact(&mut count)?;
+      let (run, count) = if count[0] > 128 { (true, (count[0] - 128) as usize) } else { (false, count[0] as usize) };
+      if count == 0 || pos + count > width {
+        return Err(ImageError::Format(String::from("corrupt Radiance HDR scanline")));
+      }
+
+      if run {
+        let mut value = [0u8; 1];
+        reader.read_exact(&mut value)?;
+        scanline[pos..pos + count].iter_mut().for_each(|pixel| pixel[component] = value[0]);
+      } else {
+        let mut values = vec![0u8; count];
+        reader.read_exact(&mut values)?;
+        scanline[pos..pos + count].iter_mut().zip(values).for_each(|(pixel, value)| pixel[component] = value);
+      }
+      pos += count;
+    }
+  }
+  Ok(scanline)
+}
+
+// Flat pixels, where the old-style encoding marks repeats of the previous pixel with (1, 1, 1, count)
+fn read_flat_scanline<R: BufRead>(reader: &mut R, width: usize, first: [u8; 4]) -> Result<Vec<[u8; 4]>, ImageError> {
+  let mut scanline: Vec<[u8; 4]> = Vec::with_capacity(width);
+  let mut pixel = first;
+  let mut shift = 0;
+  loop {
+    if pixel[0] == 1 && pixel[1] == 1 && pixel[2] == 1 {
+      let previous = *scanline.last()
+        .ok_or_else(|| ImageError::Format(String::from("corrupt Radiance HDR scanline")))?;
+      let count = ((pixel[3] as usize) << shift).min(width - scanline.len());
+      scanline.extend(std::iter::repeat_n(previous, count));
+      shift += 8;
+    } else {
+      scanline.push(pixel);
+      shift = 0;
+    }
+
+    if scanline.len() >= width {
+      return Ok(scanline);
+    }
+    reader.read_exact(&mut pixel)?;
+  }
+}
+
+fn from_rgbe(rgbe: &[u8; 4]) -> Color {
+  if rgbe[3] == 0 {
+    return Color::new(0.0, 0.0, 0.0);
+  }
+  // Bytes are taken at the middle of the range they were quantized from
+  let scale = 2f64.powi(rgbe[3] as i32 - (128 + 8));
+  Color::new(
+    (rgbe[0] as f64 + 0.5) * scale,
+    (rgbe[1] as f64 + 0.5) * scale,
+    (rgbe[2] as f64 + 0.5) * scale,
+  )
+}
+
+fn to_rgbe(color: &Color) -> [u8; 4] {
+  let r = color.x.max(0.0);
+  let g = color.y.max(0.0);
+  let b = color.z.max(0.0);
+  let v = r.max(g).max(b);
+  if v.is_nan() || v < 1e-32 {
+    return [0, 0, 0, 0];
+  }
+
+  // Split v into a mantissa in [0.5,1) and a power of two exponent
+  let exponent = v.log2().floor() as i32 + 1;
+  let scale = 256.0 / 2f64.powi(exponent);
+  [
+    (r * scale).min(255.0) as u8,
+    (g * scale).min(255.0) as u8,
+    (b * scale).min(255.0) as u8,
+    (exponent + 128).clamp(0, 255) as u8,
+  ]
+}
+
+// Encode runs of at least four equal bytes as (128 + length, value) and everything else as
+// (length, literal bytes...), with both kinds of packets limited to 127 bytes
+fn write_rle_component<W: Write>(values: &[u8], writer: &mut W) -> Result<(), ImageError> {
+  let mut pos = 0;
+  while pos < values.len() {
+    let run = values[pos..].iter().take(127).take_while(|&&v| v == values[pos]).count();
+    if run >= 4 {
+      writer.write_all(&[128 + run as u8, values[pos]])?;
+      pos += run;
+      continue;
+    }
+
+    // Collect literal bytes until the next run worth encoding starts
+    let start = pos;
+    while pos < values.len() && pos - start < 127 {
+      let next_run = values[pos..].iter().take(4).take_while(|&&v| v == values[pos]).count();
+      if next_run >= 4 {
+        break;
+      }
+      pos += 1;
+    }
+    writer.write_all(&[(pos - start) as u8])?;
+    writer.write_all(&values[start..pos])?;
+  }
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn round_trip(image: &Image) -> Image {
+    let mut data = vec![];
+    write_hdr(image, &mut data).unwrap();
+    read_hdr(data.as_slice()).unwrap()
+  }
+
+  // RGBE keeps 8 bits of mantissa relative to the largest channel of each pixel
+  fn assert_close(a: &Image, b: &Image) {
+    assert_eq!((a.width, a.height), (b.width, b.height));
+    for (p, q) in a.pixels.iter().zip(&b.pixels) {
+      let tolerance = p.x.max(p.y).max(p.z) / 128.0;
+      for (x, y) in [(p.x, q.x), (p.y, q.y), (p.z, q.z)] {
+        assert!((x - y).abs() <= tolerance, "{} was read back as {}", x, y);
+      }
+    }
+  }
+
+  #[test]
+  fn round_trips_flat_and_run_length_encoded_scanlines() {
+    for width in [4, 40] {
+      let mut image = Image::new(width, 3);
+      for j in 0..3 {
+        for i in 0..width {
+          // Runs of equal pixels mixed with varying ones, over a wide range of exponents
+          let value = if i < width / 2 { 0.75 } else { (i * (j + 1)) as f64 * 37.3 };
+          image.set_pixel(i, j, Color::new(value, value * 0.01, if j == 1 { 0.0 } else { 1e-3 }));
+        }
+      }
+      assert_close(&image, &round_trip(&image));
+    }
+  }
+
+  #[test]
+  fn rejects_other_files() {
+    assert!(read_hdr(&b"PF\n1 1\n-1.0\n"[..]).is_err());
+    assert!(read_hdr(&b"#?RADIANCE\nFORMAT=32-bit_rle_xyze\n\n-Y 1 +X 1\n"[..]).is_err());
+    assert!(read_hdr(&b"#?RADIANCE\n\n+Y 1 +X 1\n"[..]).is_err());
+  }
+}
\ No newline at end of file
@@ -1,16 +1,34 @@
-use crate::vec3::{Point3, Vec3};
+use crate::{spectrum::Wavelengths, vec3::{Point3, Vec3}};
 
+#[derive(Copy, Clone)]
 pub struct Ray {
   pub origin: Point3,
   pub direction: Vec3,
+  // Moment within the camera's shutter interval at which the ray travels, for motion blur
+  pub time: f64,
+  // Single wavelength in nanometers the ray carries once dispersion has split its light, or None for
+  // the full spectrum
+  pub wavelength: Option<f64>,
+  // Wavelengths the channels of the ray's radiance stand for when rendering spectrally, or None for RGB
+  pub wavelengths: Option<Wavelengths>,
 }
 
 impl Ray {
   pub fn new(origin: Point3, direction: Vec3) -> Ray {
-    Ray { origin, direction }
+    Ray::new_with_time(origin, direction, 0.0)
+  }
+
+  pub fn new_with_time(origin: Point3, direction: Vec3, time: f64) -> Ray {
+    Ray { origin, direction, time, wavelength: None, wavelengths: None }
+  }
+
+  // Ray from a new origin in a new direction, such as after a bounce or into an object's space, that
+  // keeps this ray's time and wavelengths
+  pub fn redirected(&self, origin: Point3, direction: Vec3) -> Ray {
+    Ray { origin, direction, ..*self }
   }
 
   pub fn at(&self, t: f64) -> Point3 {
-    self.origin + &(self.direction * t)
+    self.origin + self.direction * t
   }
 }
\ No newline at end of file
@@ -0,0 +1,490 @@
+use std::sync::OnceLock;
+
+use rand::{rngs::SmallRng, Rng, SeedableRng};
+
+// Largest float below 1, which sample values are clamped to
+const ONE_MINUS_EPSILON: f64 = 1.0 - f64::EPSILON / 2.0;
+
+// The index-th of the count samples taken in the pixel at (x, y), for a render with the given seed
+#[derive(Copy, Clone)]
+pub struct PixelSample {
+  pub x: i64,
+  pub y: i64,
+  pub index: u64,
+  pub count: u64,
+  pub seed: u64,
+}
+
+// Source of the numbers in [0, 1) that a path consumes, one dimension at a time, as it picks a point
+// in the pixel, on the lens, in time and then directions at every bounce. Samplers other than the
+// independent one spread the samples of a pixel evenly in each dimension, and in each pair of an even
+// dimension and the odd one after it, which lowers the noise for the same number of samples
+pub trait Sampler: Send + Sync {
+  fn get(&self, sample: &PixelSample, dimension: u32) -> f64;
+}
+
+// Dimensions of the camera: the point in the pixel, the point on the lens, the time and the wavelengths
+pub const CAMERA_DIMENSIONS: u32 = 6;
+// Dimensions set aside for every bounce, enough for what the medium, lights and material usually draw
+pub const BOUNCE_DIMENSIONS: u32 = 16;
+
+// Hands out the dimensions of one pixel sample in turn. The camera and every bounce draw from slots
+// at fixed dimensions, so the dimensions used at a bounce don't depend on how many numbers earlier
+// bounces happened to draw, and each keeps the stratification of its slot
+pub struct PixelSampler<'a> {
+  sampler: &'a dyn Sampler,
+  sample: PixelSample,
+  dimension: u32,
+  end: u32,
+}
+
+impl<'a> PixelSampler<'a> {
+  // Start at the camera's dimensions
+  pub fn new(sampler: &'a dyn Sampler, sample: PixelSample) -> PixelSampler<'a> {
+    PixelSampler {
+      sampler,
+      sample,
+      dimension: 0,
+      end: CAMERA_DIMENSIONS,
+    }
+  }
+
+  // Move on to the slot of the given bounce, counting from 0 for the camera ray's first hit
+  pub fn start_bounce(&mut self, bounce: u32) {
+    self.dimension = CAMERA_DIMENSIONS + bounce * BOUNCE_DIMENSIONS;
+    self.end = self.dimension + BOUNCE_DIMENSIONS;
+  }
+
+  // Numbers drawn past the end of a slot are independent ones, so they never reuse the next slot's
+  pub fn get_1d(&mut self) -> f64 {
+    let dimension = self.dimension;
+    self.dimension += 1;
+    if dimension < self.end {
+      return self.sampler.get(&self.sample, dimension);
+    }
+    let sample = &self.sample;
+    to_float(hash(&[sample.seed, sample.x as u64, sample.y as u64, sample.index, self.end as u64, dimension as u64]))
+  }
+
+  // Two numbers from an even dimension and the one after it, which are stratified together
+  pub fn get_2d(&mut self) -> (f64, f64) {
+    if self.dimension % 2 == 1 {
+      self.dimension += 1;
+    }
+    (self.get_1d(), self.get_1d())
+  }
+}
+
+// Uncorrelated random numbers, derived from a hash of the sample and dimension so that renders with
+// the same seed are the same
+pub struct IndependentSampler;
+
+impl Sampler for IndependentSampler {
+  fn get(&self, sample: &PixelSample, dimension: u32) -> f64 {
+    to_float(hash(&[sample.seed, sample.x as u64, sample.y as u64, sample.index, dimension as u64]))
+  }
+}
+
+// Jittered samples, one in each stratum of the pixel's samples, with the strata visited in a random
+// order per dimension so that the dimensions stay uncorrelated. Pairs of dimensions are stratified on
+// a grid, with leftover cells left out at random when the sample count isn't a product of its sides
+pub struct StratifiedSampler;
+
+impl Sampler for StratifiedSampler {
+  fn get(&self, sample: &PixelSample, dimension: u32) -> f64 {
+    let count = sample.count.max(1);
+    let columns = (count as f64).sqrt().ceil() as u64;
+    let rows = count.div_ceil(columns);
+
+    let pair = dimension / 2;
+    let seed = hash(&[sample.seed, sample.x as u64, sample.y as u64, pair as u64]);
+    let stratum = permute(sample.index % (columns * rows), columns * rows, seed);
+    let jitter = to_float(hash(&[seed, sample.index, dimension as u64]));
+    let value = if dimension % 2 == 0 {
+      ((stratum % columns) as f64 + jitter) / columns as f64
+    } else {
+      ((stratum / columns) as f64 + jitter) / rows as f64
+    };
+    value.min(ONE_MINUS_EPSILON)
+  }
+}
+
+// Halton sequence, the radical inverse of the sample index in a different prime base for each
+// dimension, Owen scrambled per pixel and dimension. Without scrambling the first samples of the large
+// bases would all be bunched up near zero. Dimensions beyond the primes in the table are independent
+pub struct HaltonSampler;
+
+const PRIMES: [u64; 64] = [
+  2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37, 41, 43, 47, 53, 59, 61, 67, 71, 73, 79, 83, 89, 97, 101, 103, 107,
+  109, 113, 127, 131, 137, 139, 149, 151, 157, 163, 167, 173, 179, 181, 191, 193, 197, 199, 211, 223, 227, 229,
+  233, 239, 241, 251, 257, 263, 269, 271, 277, 281, 283, 293, 307, 311,
+];
+
+impl Sampler for HaltonSampler {
+  fn get(&self, sample: &PixelSample, dimension: u32) -> f64 {
+    let seed = hash(&[sample.seed, sample.x as u64, sample.y as u64, dimension as u64]);
+    match PRIMES.get(dimension as usize) {
+      Some(&base) => scrambled_radical_inverse(sample.index, sample.count, base, seed),
+      None => IndependentSampler.get(sample, dimension),
+    }
+  }
+}
+
+// Owen scrambled Sobol points, following Burley, "Practical Hash-based Owen Scrambling", 2020. Each
+// pair of dimensions takes the first two Sobol dimensions, which form a (0, 2) sequence, with the
+// sample order shuffled per pixel and pair so that the pairs are independent of each other
+pub struct SobolSampler;
+
+impl Sampler for SobolSampler {
+  fn get(&self, sample: &PixelSample, dimension: u32) -> f64 {
+    let seed = hash(&[sample.seed, sample.x as u64, sample.y as u64]);
+    scrambled_sobol(sample.index, dimension, seed)
+  }
+}
+
+// Scrambled Sobol points shared by every pixel, each pixel offsetting them by the value of a blue
+// noise mask, after Georgiev and Fajardo, "Blue-noise Dithered Sampling", 2016. Neighboring pixels
+// then make errors of opposite sign, which leaves the noise at low sample counts at high frequencies
+// where it is much less visible
+pub struct BlueNoiseSampler;
+
+const BLUE_NOISE_SIZE: usize = 64;
+
+impl Sampler for BlueNoiseSampler {
+  fn get(&self, sample: &PixelSample, dimension: u32) -> f64 {
+    // Every dimension reads the mask at a different offset, so that the dimensions don't shift together
+    let offset = hash(&[sample.seed, dimension as u64]);
+    let size = BLUE_NOISE_SIZE as i64;
+    let x = (sample.x + (offset & 0xffff) as i64).rem_euclid(size) as usize;
+    let y = (sample.y + (offset >> 16 & 0xffff) as i64).rem_euclid(size) as usize;
+    let shift = blue_noise_mask()[y * BLUE_NOISE_SIZE + x];
+    (scrambled_sobol(sample.index, dimension, sample.seed) + shift).fract().min(ONE_MINUS_EPSILON)
+  }
+}
+
+fn scrambled_sobol(index: u64, dimension: u32, seed: u64) -> f64 {
+  let pair = dimension / 2;
+  let index = nested_uniform_scramble(index as u32, hash(&[seed, pair as u64]) as u32);
+  let value = if dimension % 2 == 0 { index.reverse_bits() } else { sobol_second_dimension(index) };
+  let value = nested_uniform_scramble(value, hash(&[seed, pair as u64, dimension as u64]) as u32);
+  value as f64 / 4294967296.0
+}
+
+// Second dimension of the Sobol sequence, whose generator matrix is Pascal's triangle modulo 2
+fn sobol_second_dimension(mut index: u32) -> u32 {
+  let mut value = 0;
+  let mut v = 1 << 31;
+  while index != 0 {
+    if index & 1 != 0 {
+      value ^= v;
+    }
+    index >>= 1;
+    v ^= v >> 1;
+  }
+  value
+}
+
+// Owen scrambling: a random permutation of the bits' subintervals, where each bit is flipped based on
+// the bits above it. The Laine-Karras hash only lets bits depend on the ones below them, so it is
+// applied to the reversed bits
+fn nested_uniform_scramble(x: u32, seed: u32) -> u32 {
+  let mut x = x.reverse_bits().wrapping_add(seed);
+  x ^= x.wrapping_mul(0x6c50b47c);
+  x ^= x.wrapping_mul(0xb82f1e52);
+  x ^= x.wrapping_mul(0xc7afe638);
+  x ^= x.wrapping_mul(0x8d22f6e6);
+  x.reverse_bits()
+}
+
+// Radical inverse with every digit permuted by a permutation that depends on the digits before it.
+// Zero digits past the last one of the index are permuted too for as many digits as the indices
+// below count have, since those indices have their own digits there. Past them no two indices share
+// their leading digits, and the rest are drawn at once as uniformly random ones
+fn scrambled_radical_inverse(mut index: u64, count: u64, base: u64, seed: u64) -> f64 {
+  let inverse_base = 1.0 / base as f64;
+  let mut scale = inverse_base;
+  let mut value = 0.0;
+  let mut prefix = seed;
+  let mut indices = 1;
+  while index > 0 || indices < count {
+    let digit = index % base;
+    value += permute(digit, base, prefix) as f64 * scale;
+    prefix = hash(&[prefix, digit]);
+    scale *= inverse_base;
+    index /= base;
+    indices = indices.saturating_mul(base);
+  }
+  (value + to_float(prefix) * scale * base as f64).min(ONE_MINUS_EPSILON)
+}
+
+// Element i of a random permutation of [0, length), chosen by the seed, without storing the permutation.
+// From Kensler, "Correlated Multi-Jittered Sampling", 2013, cycling through a hash that is a bijection
+// on the next power of two until it lands in range
+fn permute(i: u64, length: u64, seed: u64) -> u64 {
+  let (mut i, length, p) = (i as u32, length as u32, seed as u32);
+  let mut w = length - 1;
+  w |= w >> 1;
+  w |= w >> 2;
+  w |= w >> 4;
+  w |= w >> 8;
+  w |= w >> 16;
+  loop {
+    i ^= p;
+    i = i.wrapping_mul(0xe170893d);
+    i ^= p >> 16;
+    i ^= (i & w) >> 4;
+    i ^= p >> 8;
+    i = i.wrapping_mul(0x0929eb3f);
+    i ^= p >> 23;
+    i ^= (i & w) >> 1;
+    i = i.wrapping_mul(1 | p >> 27);
+    i = i.wrapping_mul(0x6935fa69);
+    i ^= (i & w) >> 11;
+    i = i.wrapping_mul(0x74dcb303);
+    i ^= (i & w) >> 2;
+    i = i.wrapping_mul(0x9e501cc3);
+    i ^= (i & w) >> 2;
+    i = i.wrapping_mul(0xc860a3df);
+    i &= w;
+    i ^= i >> 5;
+    if i < length {
+      break;
+    }
+  }
+  ((i as u64) + (p as u64)) % length as u64
+}
+
+// Dither mask of values in [0, 1) by Ulichney's void-and-cluster method, built once. Pixels are ranked
+// by the order in which they fill the largest void of a binary pattern, so the pixels below any
+// threshold are spread evenly, with no clumps or low-frequency structure
+fn blue_noise_mask() -> &'static [f64] {
+  static MASK: OnceLock<Vec<f64>> = OnceLock::new();
+  MASK.get_or_init(|| {
+    let pixels = BLUE_NOISE_SIZE * BLUE_NOISE_SIZE;
+    let mut pattern = DitherPattern::new();
+    let mut rng = SmallRng::seed_from_u64(0);
+    while pattern.ones < pixels / 10 {
+      let p = rng.gen_range(0..pixels);
+      if !pattern.bits[p] {
+        pattern.toggle(p);
+      }
+    }
+
+    // Move points from their tightest cluster to the largest void until the pattern is even
+    loop {
+      let cluster = pattern.tightest_cluster();
+      pattern.toggle(cluster);
+      let void = pattern.largest_void();
+      pattern.toggle(void);
+      if void == cluster {
+        break;
+      }
+    }
+
+    let mut ranks = vec![0; pixels];
+    let mut removing = pattern.clone();
+    for rank in (0..removing.ones).rev() {
+      let cluster = removing.tightest_cluster();
+      ranks[cluster] = rank;
+      removing.toggle(cluster);
+    }
+    // Filling the largest void of the points is filling the tightest cluster of the gaps, so this
+    // carries on past half full as well
+    for rank in pattern.ones..pixels {
+      let void = pattern.largest_void();
+      ranks[void] = rank;
+      pattern.toggle(void);
+    }
+
+    ranks.iter().map(|&rank| (rank as f64 + 0.5) / pixels as f64).collect()
+  })
+}
+
+// Binary pattern that wraps around its edges, along with the Gaussian weighted density of points around
+// every pixel
+#[derive(Clone)]
+struct DitherPattern {
+  bits: Vec<bool>,
+  energy: Vec<f64>,
+  kernel: Vec<f64>,
+  ones: usize,
+}
+
+impl DitherPattern {
+  fn new() -> DitherPattern {
+    let size = BLUE_NOISE_SIZE;
+    let sigma = 1.5;
+    let kernel = (0..size * size)
+      .map(|i| {
+        let dx = (i % size).min(size - i % size) as f64;
+        let dy = (i / size).min(size - i / size) as f64;
+        (-(dx * dx + dy * dy) / (2.0 * sigma * sigma)).exp()
+      })
+      .collect();
+    DitherPattern {
+      bits: vec![false; size * size],
+      energy: vec![0.0; size * size],
+      kernel,
+      ones: 0,
+    }
+  }
+
+  fn toggle(&mut self, p: usize) {
+    let size = BLUE_NOISE_SIZE;
+    self.bits[p] = !self.bits[p];
+    let sign = if self.bits[p] { 1.0 } else { -1.0 };
+    self.ones = if self.bits[p] { self.ones + 1 } else { self.ones - 1 };
+
+    let (px, py) = (p % size, p / size);
+    for qy in 0..size {
+      let dy = (qy + size - py) % size;
+      for qx in 0..size {
+        let dx = (qx + size - px) % size;
+        self.energy[qy * size + qx] += sign * self.kernel[dy * size + dx];
+      }
+    }
+  }
+
+  fn tightest_cluster(&self) -> usize {
+    (0..self.bits.len())
+      .filter(|&p| self.bits[p])
+      .max_by(|&a, &b| self.energy[a].total_cmp(&self.energy[b]))
+      .unwrap()
+  }
+
+  fn largest_void(&self) -> usize {
+    (0..self.bits.len())
+      .filter(|&p| !self.bits[p])
+      .min_by(|&a, &b| self.energy[a].total_cmp(&self.energy[b]))
+      .unwrap()
+  }
+}
+
+// Float in [0, 1) from the top 53 bits of a hash
+fn to_float(bits: u64) -> f64 {
+  (bits >> 11) as f64 / (1u64 << 53) as f64
+}
+
+fn hash(values: &[u64]) -> u64 {
+  values.iter().fold(0x243f6a8885a308d3, |h, &v| mix(h.wrapping_add(v).wrapping_mul(0x9e3779b97f4a7c15)))
+}
+
+// Finalizer of splitmix64, which spreads every input bit across the output
+fn mix(z: u64) -> u64 {
+  let z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+  let z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+  z ^ (z >> 31)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn pixel_sample(index: u64, count: u64) -> PixelSample {
+    PixelSample { x: 7, y: -3, index, count, seed: 42 }
+  }
+
+  // Whether every pair of count points falls in its own cell of each grid of count cells whose sides
+  // are powers of the two bases, which is what a (0, m, 2)-net in those bases has
+  fn is_net(points: &[(f64, f64)], bases: (u64, u64)) -> bool {
+    let count = points.len() as u64;
+    let mut columns = 1;
+    while columns <= count {
+      if count % columns == 0 {
+        let rows = count / columns;
+        let mut cells = vec![false; count as usize];
+        for &(x, y) in points {
+          let cell = (y * rows as f64) as usize * columns as usize + (x * columns as f64) as usize;
+          if std::mem::replace(&mut cells[cell], true) {
+            return false;
+          }
+        }
+      }
+      columns *= bases.0;
+      if bases.0 != bases.1 {
+        break;
+      }
+    }
+    true
+  }
+
+  #[test]
+  fn sobol_matches_reference_points() {
+    let first: Vec<f64> = (0..8u32).map(|i| i.reverse_bits() as f64 / 4294967296.0).collect();
+    assert_eq!(first, [0.0, 0.5, 0.25, 0.75, 0.125, 0.625, 0.375, 0.875]);
+    let second: Vec<f64> = (0..8).map(|i| sobol_second_dimension(i) as f64 / 4294967296.0).collect();
+    assert_eq!(second, [0.0, 0.5, 0.75, 0.25, 0.625, 0.125, 0.375, 0.875]);
+  }
+
+  #[test]
+  fn scrambled_sobol_pairs_are_nets() {
+    for pair in [0, 3] {
+      let points: Vec<(f64, f64)> = (0..64)
+        .map(|i| {
+          let sample = pixel_sample(i, 64);
+          (SobolSampler.get(&sample, 2 * pair), SobolSampler.get(&sample, 2 * pair + 1))
+        })
+        .collect();
+      assert!(is_net(&points, (2, 2)));
+      assert!(is_net(&points[..16], (2, 2)));
+    }
+  }
+
+  #[test]
+  fn scrambled_halton_stratifies_each_base() {
+    for (dimension, base) in PRIMES.iter().take(5).enumerate() {
+      let count = base * base;
+      let mut strata = vec![false; count as usize];
+      for i in 0..count {
+        let value = HaltonSampler.get(&pixel_sample(i, count), dimension as u32);
+        assert!((0.0..1.0).contains(&value));
+        assert!(!std::mem::replace(&mut strata[(value * count as f64) as usize], true));
+      }
+    }
+
+    // The first two dimensions together put the first 2^a 3^b points in a grid of 2^a by 3^b cells
+    let points: Vec<(f64, f64)> = (0..72)
+      .map(|i| (HaltonSampler.get(&pixel_sample(i, 72), 0), HaltonSampler.get(&pixel_sample(i, 72), 1)))
+      .collect();
+    let mut cells = [false; 72];
+    for (x, y) in points {
+      assert!(!std::mem::replace(&mut cells[(y * 9.0) as usize * 8 + (x * 8.0) as usize], true));
+    }
+  }
+
+  #[test]
+  fn stratified_samples_cover_every_stratum() {
+    let points: Vec<(f64, f64)> = (0..16)
+      .map(|i| (StratifiedSampler.get(&pixel_sample(i, 16), 4), StratifiedSampler.get(&pixel_sample(i, 16), 5)))
+      .collect();
+    let mut cells = [false; 16];
+    for (x, y) in points {
+      assert!(!std::mem::replace(&mut cells[(y * 4.0) as usize * 4 + (x * 4.0) as usize], true));
+    }
+  }
+
+  // Returns the dimension it is asked for, to follow how a PixelSampler hands them out
+  struct DimensionSampler;
+
+  impl Sampler for DimensionSampler {
+    fn get(&self, _: &PixelSample, dimension: u32) -> f64 {
+      dimension as f64
+    }
+  }
+
+  #[test]
+  fn pixel_sampler_uses_fixed_slots() {
+    let mut sampler = PixelSampler::new(&DimensionSampler, pixel_sample(0, 1));
+    assert_eq!(sampler.get_1d(), 0.0);
+    assert_eq!(sampler.get_2d(), (2.0, 3.0));
+    sampler.start_bounce(1);
+    let start = (CAMERA_DIMENSIONS + BOUNCE_DIMENSIONS) as f64;
+    assert_eq!(sampler.get_1d(), start);
+    assert_eq!(sampler.get_2d(), (start + 2.0, start + 3.0));
+    // Past the slot the numbers come from a hash instead of the next slot
+    for _ in 4..BOUNCE_DIMENSIONS {
+      sampler.get_1d();
+    }
+    assert!(sampler.get_1d() < 1.0);
+  }
+}
\ No newline at end of file
@@ -0,0 +1,1189 @@
+use std::{collections::{HashMap, HashSet}, fmt, fs, io, ops::Range, path::{Path, PathBuf}, sync::Arc};
+
+use toml::{de::{DeTable, DeValue}, Spanned};
+
+use crate::{atmosphere::Atmosphere, background::Background, camera::Camera, color::Color, constant_medium::ConstantMedium, disk::Disk, environment::ImageEnvironment, filter::{BoxFilter, Filter, GaussianFilter, LanczosFilter, MitchellFilter, TentFilter}, hittable::Hittable, hittable_list::HittableList, image::{Image, ImageError, ImageFormat, PngBitDepth}, instance::Instance, light::{DirectionalLight, Light, Lights, PointLight, SpectralLight, SpotLight}, motion::{Animated, Keyframe}, material::{Dielectric, DiffuseLight, Dispersion, HenyeyGreenstein, Isotropic, Lambertian, Material, Metal, RoughConductor, RoughDielectric}, normal_map::{BumpMap, NormalMap}, obj::load_obj, openexr::{self, ExrPrecision}, plane::Plane, principled::Principled, quad::{make_box, Quad}, sampler::{BlueNoiseSampler, HaltonSampler, IndependentSampler, Sampler, SobolSampler, StratifiedSampler}, sky::PreethamSky, spectrum::Spectrum, sphere::Sphere, texture::{CheckerTexture, ImageTexture, NoiseStyle, NoiseTexture, SolidColor, Texture}, tone_mapping::{Aces, DisplayTransform, Hable, Linear, Reinhard, ReinhardExtended, ToneMapper}, transform::Transform, triangle::Triangle, vec3::{Point3, Vec3}};
+
+#[derive(Debug)]
+pub enum SceneError {
+  Io(PathBuf, io::Error),
+  Parse { line: usize, message: String },
+}
+
+impl fmt::Display for SceneError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      SceneError::Io(path, err) => write!(f, "could not read '{}': {}", path.display(), err),
+      SceneError::Parse { line, message } => write!(f, "line {}: {}", line, message),
+    }
+  }
+}
+
+impl std::error::Error for SceneError {}
+
+// A camera together with the objects it looks at, as described by a scene file
+pub struct Scene {
+  pub camera: Camera,
+  pub world: HittableList,
+  // Punctual lights, and objects other than meshes and planes with a diffuse_light material, sampled
+  // directly when rendering
+  pub lights: Lights,
+  pub output: Output,
+}
+
+// Where and how the rendered image is written
+pub struct Output {
+  pub path: String,
+  pub format: ImageFormat,
+  // Add albedo, normal and depth layers to OpenEXR output
+  pub aovs: bool,
+  // Exposure and tone mapping for PNG and PPM output
+  pub display: DisplayTransform,
+}
+
+impl Output {
+  pub fn render(&self, camera: &Camera, world: &dyn Hittable, lights: &Lights) -> Result<(), ImageError> {
+    match self.format {
+      ImageFormat::Exr(precision) if self.aovs => {
+        let layers = camera.render_layers(world, lights);
+        let layers: Vec<(&str, &Image)> = layers.iter().map(|(name, image)| (name.as_str(), image)).collect();
+        openexr::save_exr(&self.path, &layers, precision)
+      }
+      format => camera.render(world, lights).save_as(&self.path, format, &self.display),
+    }
+  }
+}
+
+impl Scene {
+  // Load a TOML scene file, resolving texture and mesh paths relative to the file
+  pub fn load<P: AsRef<Path>>(path: P) -> Result<Scene, SceneError> {
+    let path = path.as_ref();
+    let source = fs::read_to_string(path).map_err(|err| SceneError::Io(path.to_path_buf(), err))?;
+    Scene::parse(&source, path.parent().unwrap_or(Path::new("")))
+  }
+
+  pub fn parse(source: &str, base_dir: &Path) -> Result<Scene, SceneError> {
+    let root = DeTable::parse(source).map_err(|err| SceneError::Parse {
+      line: err.span().map_or(1, |span| line_at(source, span.start)),
+      message: err.message().to_string(),
+    })?;
+    let root = Section { table: root.get_ref(), source, name: "the scene", span: 0..0 };
+    root.check_keys(&["camera", "output", "background", "atmosphere", "textures", "materials", "objects", "lights"])?;
+
+    let mut loader = SceneLoader {
+      base_dir: base_dir.to_path_buf(),
+      textures: HashMap::new(),
+      materials: HashMap::new(),
+      light_materials: HashSet::new(),
+      lights: vec![],
+      meshes: HashMap::new(),
+    };
+
+    for section in root.tables("textures", "[[textures]]")? {
+      let name = section.required_string("name")?;
+      let texture = loader.texture(&section)?;
+      if loader.textures.insert(name.to_string(), texture).is_some() {
+        return Err(section.error_at("name", &format!("texture '{}' is defined more than once", name)));
+      }
+    }
+    for section in root.tables("materials", "[[materials]]")? {
+      let name = section.required_string("name")?;
+      let material = loader.material(&section)?;
+      if loader.materials.insert(name.to_string(), material).is_some() {
+        return Err(section.error_at("name", &format!("material '{}' is defined more than once", name)));
+      }
+      if section.required_string("type")? == "diffuse_light" {
+        loader.light_materials.insert(name.to_string());
+      }
+    }
+
+    let mut objects = vec![];
+    for section in root.tables("objects", "[[objects]]")? {
+      objects.push(loader.object(&section, None)?);
+    }
+    let mut punctual_lights = vec![];
+    for section in root.tables("lights", "[[lights]]")? {
+      punctual_lights.push(SceneLoader::light(&section)?);
+    }
+
+    let mut camera = match root.table("camera", "[camera]")? {
+      Some(section) => SceneLoader::camera(&section)?,
+      None => SceneLoader::camera(&Section { table: &DeTable::default(), source, name: "[camera]", span: 0..0 })?,
+    };
+    if let Some(section) = root.table("background", "[background]")? {
+      camera.set_background(loader.background(&section)?);
+    }
+    if let Some(section) = root.table("atmosphere", "[atmosphere]")? {
+      camera.set_atmosphere(SceneLoader::atmosphere(&section)?);
+    }
+
+    let output = match root.table("output", "[output]")? {
+      Some(section) => SceneLoader::output(&section)?,
+      None => Output { path: String::from("image.ppm"), format: ImageFormat::Ppm, aovs: false, display: DisplayTransform::default() },
+    };
+
+    Ok(Scene {
+      camera,
+      world: HittableList { objects },
+      lights: Lights { objects: HittableList { objects: loader.lights }, punctual: punctual_lights },
+      output,
+    })
+  }
+}
+
+// Keys accepted by every object to place it in the world, applied in the order listed
+const PLACEMENT_KEYS: &[&str] = &["scale", "rotate", "translate", "keyframes"];
+
+struct SceneLoader {
+  base_dir: PathBuf,
+  textures: HashMap<String, Arc<dyn Texture>>,
+  materials: HashMap<String, Arc<dyn Material>>,
+  // Names of the emissive materials, whose objects are added to the lights
+  light_materials: HashSet<String>,
+  lights: Vec<Box<dyn Hittable>>,
+  meshes: HashMap<(PathBuf, Option<String>), Arc<dyn Hittable>>,
+}
+
+impl SceneLoader {
+  fn camera(section: &Section) -> Result<Camera, SceneError> {
+    section.check_keys(&["aspect_ratio", "image_width", "samples_per_pixel", "max_depth", "vfov",
+      "look_from", "look_at", "vup", "defocus_angle", "focus_dist", "threads", "tile_size", "seed",
+      "shutter_open", "shutter_close", "spectral", "sampler", "filter"])?;
+
+    let mut camera = Camera::new(
+      section.float("aspect_ratio")?.unwrap_or(1.0),
+      section.positive_integer("image_width")?.unwrap_or(100),
+      section.positive_integer("samples_per_pixel")?.unwrap_or(10),
+      section.positive_integer("max_depth")?.unwrap_or(10),
+      section.float("vfov")?.unwrap_or(90.0),
+      section.vec3("look_from")?.unwrap_or(Point3::new(0.0, 0.0, 0.0)),
+      section.vec3("look_at")?.unwrap_or(Point3::new(0.0, 0.0, -1.0)),
+      section.vec3("vup")?.unwrap_or(Vec3::new(0.0, 1.0, 0.0)),
+      section.float("defocus_angle")?.unwrap_or(0.0),
+      section.float("focus_dist")?.unwrap_or(10.0),
+    );
+    if let Some(threads) = section.positive_integer("threads")? {
+      camera.set_threads(threads as usize);
+    }
+    if let Some(tile_size) = section.positive_integer("tile_size")? {
+      camera.set_tile_size(tile_size);
+    }
+    if let Some(seed) = section.integer("seed")? {
+      if seed < 0 {
+        return Err(section.error_at("seed", "seed must not be negative"));
+      }
+      camera.set_seed(seed as u64);
+    }
+    let shutter_open = section.float("shutter_open")?.unwrap_or(0.0);
+    let shutter_close = section.float("shutter_close")?.unwrap_or(1.0);
+    if shutter_close < shutter_open {
+      return Err(section.error_at("shutter_close", "the shutter must not close before it opens"));
+    }
+    camera.set_shutter(shutter_open, shutter_close);
+    camera.set_spectral(section.boolean("spectral")?.unwrap_or(false));
+    let sampler: Box<dyn Sampler> = match section.string("sampler")?.unwrap_or("independent") {
+      "independent" => Box::new(IndependentSampler),
+      "stratified" => Box::new(StratifiedSampler),
+      "halton" => Box::new(HaltonSampler),
+      "sobol" => Box::new(SobolSampler),
+      "blue_noise" => Box::new(BlueNoiseSampler),
+      other => return Err(section.error_at("sampler", &format!("unknown sampler '{}'", other))),
+    };
+    camera.set_sampler(sampler);
+    if let Some(filter) = section.table("filter", "[camera.filter]")? {
+      camera.set_filter(SceneLoader::filter(&filter)?);
+    }
+    Ok(camera)
+  }
+
+  fn output(section: &Section) -> Result<Output, SceneError> {
+    section.check_keys(&["path", "bit_depth", "precision", "aovs", "exposure", "tone_mapping", "white_point"])?;
+    let path = section.required_string("path")?;
+    let format = ImageFormat::from_path(path).map_err(|err| section.error_at("path", &err.to_string()))?;
+
+    let format = match (format, section.integer("bit_depth")?) {
+      (format, None) => format,
+      (ImageFormat::Png(_), Some(8)) => ImageFormat::Png(PngBitDepth::Eight),
+      (ImageFormat::Png(_), Some(16)) => ImageFormat::Png(PngBitDepth::Sixteen),
+      (ImageFormat::Png(_), Some(_)) => return Err(section.error_at("bit_depth", "'bit_depth' must be 8 or 16")),
+      (_, Some(_)) => return Err(section.error_at("bit_depth", "'bit_depth' is only supported for PNG output")),
+    };
+    let format = match (format, section.string("precision")?) {
+      (format, None) => format,
+      (ImageFormat::Exr(_), Some("half")) => ImageFormat::Exr(ExrPrecision::Half),
+      (ImageFormat::Exr(_), Some("float")) => ImageFormat::Exr(ExrPrecision::Float),
+      (ImageFormat::Exr(_), Some(_)) => return Err(section.error_at("precision", "'precision' must be \"half\" or \"float\"")),
+      (_, Some(_)) => return Err(section.error_at("precision", "'precision' is only supported for OpenEXR output")),
+    };
+
+    let aovs = section.boolean("aovs")?.unwrap_or(false);
+    if aovs && !matches!(format, ImageFormat::Exr(_)) {
+      return Err(section.error_at("aovs", "'aovs' is only supported for OpenEXR output"));
+    }
+
+    let low_dynamic_range = matches!(format, ImageFormat::Png(_) | ImageFormat::Ppm);
+    for key in ["exposure", "tone_mapping", "white_point"] {
+      if !low_dynamic_range && section.table.get(key).is_some() {
+        return Err(section.error_at(key, &format!("'{}' is only supported for PNG and PPM output", key)));
+      }
+    }
+    let tone_mapper: Box<dyn ToneMapper> = match section.string("tone_mapping")?.unwrap_or("none") {
+      "none" => Box::new(Linear),
+      "reinhard" => Box::new(Reinhard),
+      "reinhard_extended" => Box::new(ReinhardExtended::new(section.float("white_point")?.unwrap_or(4.0))),
+      "hable" => Box::new(Hable),
+      "aces" => Box::new(Aces),
+      other => return Err(section.error_at("tone_mapping", &format!("unknown tone mapping '{}'", other))),
+    };
+    if section.table.get("white_point").is_some() && section.string("tone_mapping")? != Some("reinhard_extended") {
+      return Err(section.error_at("white_point", "'white_point' is only used by the reinhard_extended tone mapping"));
+    }
+
+    Ok(Output {
+      path: path.to_string(),
+      format,
+      aovs,
+      display: DisplayTransform::new(section.float("exposure")?.unwrap_or(0.0), tone_mapper),
+    })
+  }
+
+  fn background(&self, section: &Section) -> Result<Background, SceneError> {
+    match section.required_string("type")? {
+      "none" => {
+        section.check_keys(&["type"])?;
+        Ok(Background::None)
+      }
+      "solid" => {
+        section.check_keys(&["type", "color"])?;
+        Ok(Background::Solid(section.required_vec3("color")?))
+      }
+      "gradient" => {
+        section.check_keys(&["type", "bottom", "top"])?;
+        Ok(Background::Gradient {
+          bottom: section.required_vec3("bottom")?,
+          top: section.required_vec3("top")?,
+        })
+      }
+      "environment" => {
+        section.check_keys(&["type", "path", "intensity", "rotation"])?;
+        let path = self.base_dir.join(section.required_string("path")?);
+        let mut environment = ImageEnvironment::load(&path)
+          .map_err(|err| section.error_at("path", &format!("could not load '{}': {}", path.display(), err)))?;
+        environment.set_intensity(section.float("intensity")?.unwrap_or(1.0));
+        environment.set_rotation(section.float("rotation")?.unwrap_or(0.0));
+        Ok(Background::Environment(Arc::new(environment)))
+      }
+      // The sun is placed either by direction or by elevation and azimuth in degrees
+      "sky" => {
+        section.check_keys(&[
+          "type", "sun_direction", "sun_elevation", "sun_azimuth", "turbidity", "ground_albedo", "sun_size", "intensity",
+        ])?;
+        let turbidity = section.float("turbidity")?.unwrap_or(3.0);
+        if !(1.7..=10.0).contains(&turbidity) {
+          return Err(section.error_at("turbidity", "turbidity must be between 1.7 and 10"));
+        }
+        let mut sky = match section.vec3("sun_direction")? {
+          Some(direction) => {
+            if section.float("sun_elevation")?.is_some() || section.float("sun_azimuth")?.is_some() {
+              return Err(section.error_at("sun_direction", "give either sun_direction or sun_elevation and sun_azimuth"));
+            }
+            if direction.length_squared() == 0.0 {
+              return Err(section.error_at("sun_direction", "sun_direction must not be zero"));
+            }
+            PreethamSky::new(direction, turbidity)
+          }
+          None => PreethamSky::new_from_angles(
+            section.float("sun_elevation")?.unwrap_or(45.0),
+            section.float("sun_azimuth")?.unwrap_or(0.0),
+            turbidity,
+          ),
+        };
+        if let Some(albedo) = section.vec3("ground_albedo")? {
+          sky.set_ground_albedo(albedo);
+        }
+        if let Some(size) = section.float("sun_size")? {
+          if size <= 0.0 {
+            return Err(section.error_at("sun_size", "sun_size must be positive"));
+          }
+          sky.set_sun_size(size);
+        }
+        sky.set_intensity(section.float("intensity")?.unwrap_or(1.0));
+        Ok(Background::Environment(Arc::new(sky)))
+      }
+      other => Err(section.error_at("type", &format!("unknown background type '{}'", other))),
+    }
+  }
+
+  // Each filter has a default radius in pixels, about as wide as it is commonly used
+  fn filter(section: &Section) -> Result<Box<dyn Filter>, SceneError> {
+    let radius = |default: f64| -> Result<f64, SceneError> {
+      let radius = section.float("radius")?.unwrap_or(default);
+      if radius <= 0.0 {
+        return Err(section.error_at("radius", "radius must be positive"));
+      }
+      Ok(radius)
+    };
+
+    let filter: Box<dyn Filter> = match section.required_string("type")? {
+      "box" => {
+        section.check_keys(&["type", "radius"])?;
+        Box::new(BoxFilter::new(radius(0.5)?))
+      }
+      "tent" => {
+        section.check_keys(&["type", "radius"])?;
+        Box::new(TentFilter::new(radius(1.0)?))
+      }
+      "gaussian" => {
+        section.check_keys(&["type", "radius", "sigma"])?;
+        let sigma = section.float("sigma")?.unwrap_or(0.5);
+        if sigma <= 0.0 {
+          return Err(section.error_at("sigma", "sigma must be positive"));
+        }
+        Box::new(GaussianFilter::new(radius(1.5)?, sigma))
+      }
+      "mitchell" => {
+        section.check_keys(&["type", "radius", "b", "c"])?;
+        let b = section.float("b")?.unwrap_or(1.0 / 3.0);
+        let c = section.float("c")?.unwrap_or(1.0 / 3.0);
+        Box::new(MitchellFilter::new(radius(2.0)?, b, c))
+      }
+      "lanczos" => {
+        section.check_keys(&["type", "radius"])?;
+        Box::new(LanczosFilter::new(radius(3.0)?))
+      }
+      other => return Err(section.error_at("type", &format!("unknown filter type '{}'", other))),
+    };
+    Ok(filter)
+  }
+
+  // The light's power is its color scaled by its intensity, and by its spectrum if it has one. Spot and
+  // directional lights point along their direction, and spot light angles are in degrees from that axis
+  fn light(section: &Section) -> Result<Box<dyn Light>, SceneError> {
+    let power = |key: &str| -> Result<Color, SceneError> {
+      let color = section.vec3("color")?.unwrap_or(Color::new(1.0, 1.0, 1.0));
+      let intensity = section.float(key)?.unwrap_or(1.0);
+      if intensity < 0.0 {
+        return Err(section.error_at(key, &format!("{} must not be negative", key)));
+      }
+      Ok(color * intensity)
+    };
+    let direction = || -> Result<Vec3, SceneError> {
+      let direction = section.required_vec3("direction")?;
+      if direction.length_squared() == 0.0 {
+        return Err(section.error_at("direction", "direction must not be zero"));
+      }
+      Ok(direction)
+    };
+
+    let light: Box<dyn Light> = match section.required_string("type")? {
+      "point" => {
+        section.check_keys(&["type", "position", "color", "intensity", "temperature", "spectrum"])?;
+        Box::new(PointLight::new(section.required_vec3("position")?, power("intensity")?))
+      }
+      "spot" => {
+        section.check_keys(&["type", "position", "direction", "color", "intensity", "inner_angle", "outer_angle", "falloff",
+          "temperature", "spectrum"])?;
+        let outer_angle = section.float("outer_angle")?.unwrap_or(30.0);
+        let inner_angle = section.float("inner_angle")?.unwrap_or(outer_angle);
+        if !(0.0..=180.0).contains(&outer_angle) {
+          return Err(section.error_at("outer_angle", "outer_angle must be between 0 and 180"));
+        }
+        if !(0.0..=outer_angle).contains(&inner_angle) {
+          return Err(section.error_at("inner_angle", "inner_angle must be between 0 and outer_angle"));
+        }
+        let mut light = SpotLight::new(section.required_vec3("position")?, direction()?, power("intensity")?, inner_angle, outer_angle);
+        if let Some(falloff) = section.float("falloff")? {
+          if falloff < 0.0 {
+            return Err(section.error_at("falloff", "falloff must not be negative"));
+          }
+          light.set_falloff(falloff);
+        }
+        Box::new(light)
+      }
+      "directional" => {
+        section.check_keys(&["type", "direction", "color", "irradiance", "angular_diameter", "temperature", "spectrum"])?;
+        let mut light = DirectionalLight::new(direction()?, power("irradiance")?);
+        let angular_diameter = section.float("angular_diameter")?.unwrap_or(0.0);
+        if !(0.0..=180.0).contains(&angular_diameter) {
+          return Err(section.error_at("angular_diameter", "angular_diameter must be between 0 and 180"));
+        }
+        light.set_angular_diameter(angular_diameter);
+        Box::new(light)
+      }
+      other => return Err(section.error_at("type", &format!("unknown light type '{}'", other))),
+    };
+    match SceneLoader::spectrum(section)? {
+      Some(spectrum) => Ok(Box::new(SpectralLight::new(light, spectrum))),
+      None => Ok(light),
+    }
+  }
+
+  // The medium's color is given either as its albedo or as a scattering coefficient per channel
+  fn atmosphere(section: &Section) -> Result<Atmosphere, SceneError> {
+    section.check_keys(&["extinction", "albedo", "scattering", "g", "height_falloff", "base_height"])?;
+    let extinction = section.required_float("extinction")?;
+    if extinction < 0.0 {
+      return Err(section.error_at("extinction", "extinction must not be negative"));
+    }
+
+    let albedo = match (section.vec3("albedo")?, section.vec3("scattering")?) {
+      (Some(_), Some(_)) => return Err(section.error_at("scattering", "give either albedo or scattering, not both")),
+      (Some(albedo), None) => albedo,
+      (None, Some(scattering)) => {
+        if [scattering.x, scattering.y, scattering.z].iter().any(|&s| s < 0.0 || s > extinction) {
+          return Err(section.error_at("scattering", "scattering must be between 0 and the extinction"));
+        }
+        if extinction > 0.0 { scattering / extinction } else { scattering }
+      }
+      (None, None) => Color::new(1.0, 1.0, 1.0),
+    };
+
+    let mut atmosphere = match section.float("g")? {
+      Some(g) if g <= -1.0 || g >= 1.0 => return Err(section.error_at("g", "g must be between -1 and 1")),
+      Some(g) => Atmosphere::new_anisotropic(extinction, albedo, g),
+      None => Atmosphere::new(extinction, albedo),
+    };
+    if let Some(falloff) = section.float("height_falloff")? {
+      if falloff < 0.0 {
+        return Err(section.error_at("height_falloff", "height_falloff must not be negative"));
+      }
+      atmosphere.set_height_falloff(falloff, section.float("base_height")?.unwrap_or(0.0));
+    }
+    Ok(atmosphere)
+  }
+
+  fn texture(&self, section: &Section) -> Result<Arc<dyn Texture>, SceneError> {
+    let texture: Arc<dyn Texture> = match section.required_string("type")? {
+      "solid" => {
+        section.check_keys(&["name", "type", "color"])?;
+        Arc::new(SolidColor::new(section.required_vec3("color")?))
+      }
+      "checker" => {
+        section.check_keys(&["name", "type", "scale", "even", "odd"])?;
+        Arc::new(CheckerTexture::new(
+          section.required_float("scale")?,
+          self.texture_ref(section, "even")?,
+          self.texture_ref(section, "odd")?,
+        ))
+      }
+      // Raw images hold data such as normal or height maps, which is read without sRGB decoding
+      "image" => {
+        section.check_keys(&["name", "type", "path", "raw"])?;
+        let path = self.base_dir.join(section.required_string("path")?);
+        let texture = if section.boolean("raw")?.unwrap_or(false) { ImageTexture::load_raw(&path) } else { ImageTexture::load(&path) }
+          .map_err(|err| section.error_at("path", &format!("could not load '{}': {}", path.display(), err)))?;
+        Arc::new(texture)
+      }
+      "noise" => {
+        section.check_keys(&["name", "type", "scale", "style"])?;
+        let style = match section.string("style")?.unwrap_or("noise") {
+          "noise" => NoiseStyle::Noise,
+          "turbulence" => NoiseStyle::Turbulence,
+          "marble" => NoiseStyle::Marble,
+          other => return Err(section.error_at("style", &format!("unknown noise style '{}'", other))),
+        };
+        Arc::new(NoiseTexture::new(section.float("scale")?.unwrap_or(1.0), style))
+      }
+      other => return Err(section.error_at("type", &format!("unknown texture type '{}'", other))),
+    };
+    Ok(texture)
+  }
+
+  fn material(&self, section: &Section) -> Result<Arc<dyn Material>, SceneError> {
+    let material: Arc<dyn Material> = match section.required_string("type")? {
+      "lambertian" => {
+        section.check_keys(&["name", "type", "albedo"])?;
+        Arc::new(Lambertian::new_from_texture(self.texture_ref(section, "albedo")?))
+      }
+      "metal" => {
+        section.check_keys(&["name", "type", "albedo", "fuzz"])?;
+        Arc::new(Metal::new_from_texture(self.texture_ref(section, "albedo")?, section.float("fuzz")?.unwrap_or(0.0)))
+      }
+      // A dispersive glass gives its index of refraction as Cauchy coefficients [a, b] or Sellmeier
+      // coefficients [b1, b2, b3, c1, c2, c3], for wavelengths in micrometers, instead of ir
+      "dielectric" => {
+        section.check_keys(&["name", "type", "ir", "absorption", "cauchy", "sellmeier"])?;
+        let dispersion = match (section.floats("cauchy", 2)?, section.floats("sellmeier", 6)?) {
+          (Some(_), Some(_)) => return Err(section.error_at("sellmeier", "give either cauchy or sellmeier coefficients")),
+          (Some(c), None) => Some(Dispersion::Cauchy { a: c[0], b: c[1] }),
+          (None, Some(s)) => Some(Dispersion::Sellmeier { b: [s[0], s[1], s[2]], c: [s[3], s[4], s[5]] }),
+          (None, None) => None,
+        };
+        let mut material = match (dispersion, section.float("ir")?) {
+          (Some(_), Some(_)) => return Err(section.error_at("ir", "a dispersive dielectric takes its ir from its coefficients")),
+          // The index at the sodium d line, for rays that don't carry a wavelength
+          (Some(dispersion), None) => {
+            let mut material = Dielectric::new(dispersion.ior(587.6));
+            material.set_dispersion(dispersion);
+            material
+          }
+          (None, _) => Dielectric::new(section.required_float("ir")?),
+        };
+        if let Some(absorption) = section.vec3("absorption")? {
+          if absorption.x < 0.0 || absorption.y < 0.0 || absorption.z < 0.0 {
+            return Err(section.error_at("absorption", "absorption must not be negative"));
+          }
+          material.set_absorption(absorption);
+        }
+        Arc::new(material)
+      }
+      // Roughness is a single number, or a pair for different roughness along the two tangent directions
+      "rough_conductor" => {
+        section.check_keys(&["name", "type", "albedo", "roughness"])?;
+        let (roughness_u, roughness_v) = SceneLoader::roughness(section)?;
+        let mut material = RoughConductor::new_from_texture(self.texture_ref(section, "albedo")?, roughness_u);
+        material.set_anisotropic_roughness(roughness_u, roughness_v);
+        Arc::new(material)
+      }
+      "rough_dielectric" => {
+        section.check_keys(&["name", "type", "ir", "roughness"])?;
+        let (roughness_u, roughness_v) = SceneLoader::roughness(section)?;
+        let mut material = RoughDielectric::new(section.required_float("ir")?, roughness_u);
+        material.set_anisotropic_roughness(roughness_u, roughness_v);
+        Arc::new(material)
+      }
+      "principled" => {
+        section.check_keys(&["name", "type", "base_color", "metallic", "roughness", "specular", "sheen", "sheen_tint",
+          "clearcoat", "clearcoat_roughness", "transmission", "subsurface", "ior"])?;
+        let mut material = match section.table.get("base_color") {
+          Some(_) => Principled::new_from_texture(self.texture_ref(section, "base_color")?),
+          None => Principled::new(Color::new(0.8, 0.8, 0.8)),
+        };
+        type Setter = fn(&mut Principled, Arc<dyn Texture>);
+        let setters: [(&str, Setter); 9] = [
+          ("metallic", Principled::set_metallic),
+          ("roughness", Principled::set_roughness),
+          ("specular", Principled::set_specular),
+          ("sheen", Principled::set_sheen),
+          ("sheen_tint", Principled::set_sheen_tint),
+          ("clearcoat", Principled::set_clearcoat),
+          ("clearcoat_roughness", Principled::set_clearcoat_roughness),
+          ("transmission", Principled::set_transmission),
+          ("subsurface", Principled::set_subsurface),
+        ];
+        for (key, set) in setters {
+          if let Some(texture) = self.scalar_texture_ref(section, key)? {
+            set(&mut material, texture);
+          }
+        }
+        if let Some(ior) = section.float("ior")? {
+          if ior <= 0.0 {
+            return Err(section.error_at("ior", "ior must be positive"));
+          }
+          material.set_ior(ior);
+        }
+        Arc::new(material)
+      }
+      // Normal and bump maps wrap another, previously defined material
+      "normal_map" => {
+        section.check_keys(&["name", "type", "material", "map", "strength"])?;
+        let mut material = NormalMap::new(self.material_ref(section, "material")?, self.texture_ref(section, "map")?);
+        material.set_strength(section.float("strength")?.unwrap_or(1.0));
+        Arc::new(material)
+      }
+      "bump_map" => {
+        section.check_keys(&["name", "type", "material", "height", "scale"])?;
+        let scale = section.float("scale")?.unwrap_or(1.0);
+        Arc::new(BumpMap::new(self.material_ref(section, "material")?, self.texture_ref(section, "height")?, scale))
+      }
+      "diffuse_light" => {
+        section.check_keys(&["name", "type", "emit", "temperature", "spectrum"])?;
+        let mut material = DiffuseLight::new_from_texture(self.texture_ref(section, "emit")?);
+        if let Some(spectrum) = SceneLoader::spectrum(section)? {
+          material.set_spectrum(spectrum);
+        }
+        Arc::new(material)
+      }
+      "isotropic" => {
+        section.check_keys(&["name", "type", "albedo"])?;
+        Arc::new(Isotropic::new_from_texture(self.texture_ref(section, "albedo")?))
+      }
+      "henyey_greenstein" => {
+        section.check_keys(&["name", "type", "albedo", "g"])?;
+        let g = section.required_float("g")?;
+        if g <= -1.0 || g >= 1.0 {
+          return Err(section.error_at("g", "g must be between -1 and 1"));
+        }
+        Arc::new(HenyeyGreenstein::new_from_texture(self.texture_ref(section, "albedo")?, g))
+      }
+      other => return Err(section.error_at("type", &format!("unknown material type '{}'", other))),
+    };
+    Ok(material)
+  }
+
+  // Build an object, taking its material from the section unless an enclosing object such as a medium
+  // passes one down
+  fn object(&mut self, section: &Section, inherited_material: Option<&Arc<dyn Material>>) -> Result<Box<dyn Hittable>, SceneError> {
+    let first_light = self.lights.len();
+    let object: Box<dyn Hittable> = match section.required_string("type")? {
+      "sphere" => {
+        section.check_keys(&[&["type", "center", "center2", "radius", "material"], PLACEMENT_KEYS].concat())?;
+        let (center, radius) = (section.required_vec3("center")?, section.required_float("radius")?);
+        let center2 = section.vec3("center2")?.unwrap_or(center);
+        let material = self.object_material(section, inherited_material)?;
+        self.place(section, || Box::new(Sphere::new_moving(center, center2, radius, material.clone())))?
+      }
+      "triangle" => {
+        section.check_keys(&[&["type", "a", "b", "c", "material"], PLACEMENT_KEYS].concat())?;
+        let (a, b, c) = (section.required_vec3("a")?, section.required_vec3("b")?, section.required_vec3("c")?);
+        let material = self.object_material(section, inherited_material)?;
+        self.place(section, || Box::new(Triangle::new(a, b, c, material.clone())))?
+      }
+      "quad" => {
+        section.check_keys(&[&["type", "q", "u", "v", "material"], PLACEMENT_KEYS].concat())?;
+        let (q, u, v) = (section.required_vec3("q")?, section.required_vec3("u")?, section.required_vec3("v")?);
+        let material = self.object_material(section, inherited_material)?;
+        self.place(section, || Box::new(Quad::new(q, u, v, material.clone())))?
+      }
+      "disk" => {
+        section.check_keys(&[&["type", "center", "normal", "radius", "material"], PLACEMENT_KEYS].concat())?;
+        let (center, normal) = (section.required_vec3("center")?, section.required_vec3("normal")?);
+        let radius = section.required_float("radius")?;
+        let material = self.object_material(section, inherited_material)?;
+        self.place(section, || Box::new(Disk::new(center, normal, radius, material.clone())))?
+      }
+      "box" => {
+        section.check_keys(&[&["type", "a", "b", "material"], PLACEMENT_KEYS].concat())?;
+        let (a, b) = (section.required_vec3("a")?, section.required_vec3("b")?);
+        let material = self.object_material(section, inherited_material)?;
+        self.place(section, || Box::new(make_box(&a, &b, material.clone())))?
+      }
+      "plane" => {
+        section.check_keys(&[&["type", "point", "normal", "material"], PLACEMENT_KEYS].concat())?;
+        let plane = Plane::new(
+          section.required_vec3("point")?,
+          section.required_vec3("normal")?,
+          self.object_material(section, inherited_material)?,
+        );
+        match SceneLoader::transform(section)? {
+          Some(transform) => Box::new(Instance::new(Arc::new(plane), transform)),
+          None => Box::new(plane),
+        }
+      }
+      "mesh" => {
+        section.check_keys(&[&["type", "path", "group", "material"], PLACEMENT_KEYS].concat())?;
+        let material = self.object_material(section, inherited_material)?;
+        let transform = SceneLoader::transform(section)?.unwrap_or_default();
+        Box::new(Instance::new_with_material(self.mesh(section, &material)?, transform, material))
+      }
+      "constant_medium" => {
+        section.check_keys(&[&["type", "boundary", "density", "material"], PLACEMENT_KEYS].concat())?;
+        let density = section.required_float("density")?;
+        if density <= 0.0 {
+          return Err(section.error_at("density", "density must be positive"));
+        }
+        let phase_function = self.object_material(section, inherited_material)?;
+        let boundary = section.table("boundary", "the boundary")?
+          .ok_or_else(|| section.error_at("boundary", "missing required key 'boundary'"))?;
+        let boundary = self.object(&boundary, Some(&phase_function))?;
+        let medium: Box<dyn Hittable> = Box::new(ConstantMedium::new_with_phase(boundary, density, phase_function));
+        match SceneLoader::transform(section)? {
+          Some(transform) => Box::new(Instance::new(Arc::from(medium), transform)),
+          None => medium,
+        }
+      }
+      other => return Err(section.error_at("type", &format!("unknown object type '{}'", other))),
+    };
+
+    let keyframes = section.tables("keyframes", "a keyframe")?;
+    if keyframes.is_empty() {
+      return Ok(object);
+    }
+    let keyframes = keyframes.iter().map(|key| {
+      key.check_keys(&["time", "translate", "rotate"])?;
+      Ok(Keyframe {
+        time: key.required_float("time")?,
+        translate: key.vec3("translate")?.unwrap_or(Vec3::new(0.0, 0.0, 0.0)),
+        rotate: key.vec3("rotate")?.unwrap_or(Vec3::new(0.0, 0.0, 0.0)),
+      })
+    }).collect::<Result<Vec<Keyframe>, SceneError>>()?;
+    // Copies of the object added to the lights move with it
+    let lights = self.lights.split_off(first_light);
+    for light in lights {
+      self.lights.push(Box::new(Animated::new(Arc::from(light), keyframes.clone())));
+    }
+    Ok(Box::new(Animated::new(Arc::from(object), keyframes)))
+  }
+
+  // Build the object, placed by the section's transform if it has one. Objects with an emissive
+  // material are built a second time for the lights, unless the transform distorts their solid angles
+  fn place(&mut self, section: &Section, build: impl Fn() -> Box<dyn Hittable>) -> Result<Box<dyn Hittable>, SceneError> {
+    let light = self.is_light(section)?;
+    match SceneLoader::transform(section)? {
+      Some(transform) => {
+        if light && transform.is_similarity() {
+          self.lights.push(Box::new(Instance::new(Arc::from(build()), transform)));
+        }
+        Ok(Box::new(Instance::new(Arc::from(build()), transform)))
+      }
+      None => {
+        if light {
+          self.lights.push(build());
+        }
+        Ok(build())
+      }
+    }
+  }
+
+  fn roughness(section: &Section) -> Result<(f64, f64), SceneError> {
+    let (roughness_u, roughness_v) = match section.table.get("roughness") {
+      None => (0.0, 0.0),
+      Some(item) => match (to_float(item), &item.get_ref()) {
+        (Some(roughness), _) => (roughness, roughness),
+        (None, DeValue::Array(values)) if values.len() == 2 => match (to_float(&values[0]), to_float(&values[1])) {
+          (Some(roughness_u), Some(roughness_v)) => (roughness_u, roughness_v),
+          _ => return Err(section.type_error("roughness", item, "a number or an array of two numbers")),
+        },
+        _ => return Err(section.type_error("roughness", item, "a number or an array of two numbers")),
+      },
+    };
+    if !(0.0..=1.0).contains(&roughness_u) || !(0.0..=1.0).contains(&roughness_v) {
+      return Err(section.error_at("roughness", "roughness must be between 0 and 1"));
+    }
+    Ok((roughness_u, roughness_v))
+  }
+
+  // An emitter's spectrum is either a black body temperature in kelvin or measured [wavelength, value]
+  // pairs, with wavelengths in nanometers
+  fn spectrum(section: &Section) -> Result<Option<Spectrum>, SceneError> {
+    let samples = match (section.float("temperature")?, section.table.get("spectrum")) {
+      (Some(_), Some(_)) => return Err(section.error_at("spectrum", "give either a temperature or a spectrum")),
+      (Some(temperature), None) if temperature <= 0.0 => return Err(section.error_at("temperature", "temperature must be positive")),
+      (Some(temperature), None) => return Ok(Some(Spectrum::new_blackbody(temperature))),
+      (None, None) => return Ok(None),
+      (None, Some(item)) => item,
+    };
+
+    let expected = "an array of [wavelength, value] pairs";
+    let pairs = match samples.get_ref() {
+      DeValue::Array(pairs) if pairs.len() >= 2 => pairs,
+      _ => return Err(section.type_error("spectrum", samples, expected)),
+    };
+    let samples = pairs.iter()
+      .map(|pair| match pair.get_ref() {
+        DeValue::Array(values) if values.len() == 2 => to_float(&values[0]).zip(to_float(&values[1])),
+        _ => None,
+      })
+      .collect::<Option<Vec<(f64, f64)>>>()
+      .ok_or_else(|| section.type_error("spectrum", samples, expected))?;
+    if samples.iter().any(|&(wavelength, value)| wavelength <= 0.0 || value < 0.0) {
+      return Err(section.error_at("spectrum", "spectrum wavelengths must be positive and values must not be negative"));
+    }
+    Ok(Some(Spectrum::new_sampled(samples)))
+  }
+
+  // Objects are scaled, then rotated around the X, Y and Z axes in turn, and then translated
+  fn transform(section: &Section) -> Result<Option<Transform>, SceneError> {
+    let scale = match section.table.get("scale") {
+      Some(item) => match to_float(item) {
+        Some(factor) => Some(Vec3::new(factor, factor, factor)),
+        None => section.vec3("scale")?,
+      },
+      None => None,
+    };
+    let rotate = section.vec3("rotate")?;
+    let translate = section.vec3("translate")?;
+    if scale.is_none() && rotate.is_none() && translate.is_none() {
+      return Ok(None);
+    }
+
+    let mut transform = Transform::IDENTITY;
+    if let Some(scale) = scale {
+      if scale.x == 0.0 || scale.y == 0.0 || scale.z == 0.0 {
+        return Err(section.error_at("scale", "scale factors must not be zero"));
+      }
+      transform = Transform::scaling(&scale);
+    }
+    if let Some(rotate) = rotate {
+      transform = Transform::rotation(&Vec3::new(1.0, 0.0, 0.0), rotate.x) * transform;
+      transform = Transform::rotation(&Vec3::new(0.0, 1.0, 0.0), rotate.y) * transform;
+      transform = Transform::rotation(&Vec3::new(0.0, 0.0, 1.0), rotate.z) * transform;
+    }
+    if let Some(translate) = translate {
+      transform = Transform::translation(&translate) * transform;
+    }
+    Ok(Some(transform))
+  }
+
+  // Meshes are loaded once per file and group and shared by every object placing them, which give
+  // the instances their own material
+  fn mesh(&mut self, section: &Section, material: &Arc<dyn Material>) -> Result<Arc<dyn Hittable>, SceneError> {
+    let path = self.base_dir.join(section.required_string("path")?);
+    let group = section.string("group")?;
+    let key = (path.clone(), group.map(String::from));
+    if let Some(mesh) = self.meshes.get(&key) {
+      return Ok(mesh.clone());
+    }
+
+    let mesh = load_obj(&path, material.clone())
+      .map_err(|err| section.error_at("path", &format!("could not load '{}': {}", path.display(), err)))?;
+    let mesh: Arc<dyn Hittable> = match group {
+      Some(group) => Arc::new(mesh.group(group)
+        .ok_or_else(|| section.error_at("group", &format!("'{}' has no group '{}'", path.display(), group)))?),
+      None => Arc::new(mesh),
+    };
+    self.meshes.insert(key, mesh.clone());
+    Ok(mesh)
+  }
+
+  // A texture is either given inline as a color or refers to a named texture
+  fn texture_ref(&self, section: &Section, key: &str) -> Result<Arc<dyn Texture>, SceneError> {
+    let item = section.required(key)?;
+    match &item.get_ref() {
+      DeValue::String(name) => self.textures.get(name.as_ref()).cloned()
+        .ok_or_else(|| section.error_at(key, &format!("unknown texture '{}'", name))),
+      _ => Ok(Arc::new(SolidColor::new(section.required_vec3(key)?))),
+    }
+  }
+
+  // A parameter in [0, 1] is either given as a number or refers to a named texture
+  fn scalar_texture_ref(&self, section: &Section, key: &str) -> Result<Option<Arc<dyn Texture>>, SceneError> {
+    let item = match section.table.get(key) {
+      None => return Ok(None),
+      Some(item) => item,
+    };
+    match (&item.get_ref(), to_float(item)) {
+      (DeValue::String(name), _) => self.textures.get(name.as_ref()).cloned().map(Some)
+        .ok_or_else(|| section.error_at(key, &format!("unknown texture '{}'", name))),
+      (_, Some(value)) if (0.0..=1.0).contains(&value) => Ok(Some(Principled::constant(value))),
+      (_, Some(_)) => Err(section.error_at(key, &format!("'{}' must be between 0 and 1", key))),
+      _ => Err(section.type_error(key, item, "a number or a texture name")),
+    }
+  }
+
+  fn material_ref(&self, section: &Section, key: &str) -> Result<Arc<dyn Material>, SceneError> {
+    let name = section.required_string(key)?;
+    self.materials.get(name).cloned()
+      .ok_or_else(|| section.error_at(key, &format!("unknown material '{}'", name)))
+  }
+
+  fn object_material(&self, section: &Section, inherited_material: Option<&Arc<dyn Material>>) -> Result<Arc<dyn Material>, SceneError> {
+    match inherited_material {
+      Some(material) if section.table.get("material").is_none() => Ok(material.clone()),
+      _ => self.material_ref(section, "material"),
+    }
+  }
+
+  fn is_light(&self, section: &Section) -> Result<bool, SceneError> {
+    Ok(section.string("material")?.is_some_and(|name| self.light_materials.contains(name)))
+  }
+}
+
+// A value of the scene file along with the span of the source it was read from
+type Item<'t> = Spanned<DeValue<'t>>;
+
+// A table of the scene file along with what to call it in error messages
+struct Section<'t> {
+  table: &'t DeTable<'t>,
+  source: &'t str,
+  name: &'static str,
+  span: Range<usize>,
+}
+
+impl<'t> Section<'t> {
+  fn check_keys(&self, allowed: &[&str]) -> Result<(), SceneError> {
+    for (key, _) in self.table {
+      if !allowed.contains(&key.get_ref().as_ref()) {
+        return Err(SceneError::Parse {
+          line: line_at(self.source, key.span().start),
+          message: format!("unknown key '{}' in {}, expected one of: {}", key.get_ref(), self.name, allowed.join(", ")),
+        });
+      }
+    }
+    Ok(())
+  }
+
+  fn section(&self, item: &'t Item<'t>, table: &'t DeTable<'t>, name: &'static str) -> Section<'t> {
+    Section { table, source: self.source, name, span: item.span() }
+  }
+
+  fn table(&self, key: &str, name: &'static str) -> Result<Option<Section<'t>>, SceneError> {
+    match self.table.get(key) {
+      None => Ok(None),
+      Some(item) => match item.get_ref() {
+        DeValue::Table(table) => Ok(Some(self.section(item, table, name))),
+        _ => Err(self.type_error(key, item, "a table")),
+      },
+    }
+  }
+
+  fn tables(&self, key: &str, name: &'static str) -> Result<Vec<Section<'t>>, SceneError> {
+    let items = match self.table.get(key) {
+      None => return Ok(vec![]),
+      Some(item) => match item.get_ref() {
+        DeValue::Array(items) => items,
+        _ => return Err(self.type_error(key, item, "an array of tables")),
+      },
+    };
+    items.iter()
+      .map(|item| match item.get_ref() {
+        DeValue::Table(table) => Ok(self.section(item, table, name)),
+        _ => Err(self.type_error(key, item, "a table")),
+      })
+      .collect()
+  }
+
+  fn required(&self, key: &str) -> Result<&'t Item<'t>, SceneError> {
+    self.table.get(key).ok_or_else(|| SceneError::Parse {
+      line: line_at(self.source, self.span.start),
+      message: format!("missing key '{}' in {}", key, self.name),
+    })
+  }
+
+  fn float(&self, key: &str) -> Result<Option<f64>, SceneError> {
+    match self.table.get(key) {
+      None => Ok(None),
+      Some(item) => to_float(item).map(Some).ok_or_else(|| self.type_error(key, item, "a number")),
+    }
+  }
+
+  fn required_float(&self, key: &str) -> Result<f64, SceneError> {
+    self.required(key)?;
+    Ok(self.float(key)?.unwrap())
+  }
+
+  fn integer(&self, key: &str) -> Result<Option<i64>, SceneError> {
+    match self.table.get(key) {
+      None => Ok(None),
+      Some(item) => match item.get_ref() {
+        DeValue::Integer(value) => i64::from_str_radix(value.as_str(), value.radix()).map(Some)
+          .map_err(|_| self.error_at(key, &format!("'{}' is out of range", key))),
+        _ => Err(self.type_error(key, item, "an integer")),
+      },
+    }
+  }
+
+  fn positive_integer(&self, key: &str) -> Result<Option<i64>, SceneError> {
+    match self.integer(key)? {
+      Some(value) if value < 1 => Err(self.error_at(key, &format!("'{}' must be at least 1, found {}", key, value))),
+      value => Ok(value),
+    }
+  }
+
+  fn boolean(&self, key: &str) -> Result<Option<bool>, SceneError> {
+    match self.table.get(key) {
+      None => Ok(None),
+      Some(item) => match item.get_ref() {
+        DeValue::Boolean(value) => Ok(Some(*value)),
+        _ => Err(self.type_error(key, item, "a boolean")),
+      },
+    }
+  }
+
+  fn string(&self, key: &str) -> Result<Option<&'t str>, SceneError> {
+    match self.table.get(key) {
+      None => Ok(None),
+      Some(item) => match item.get_ref() {
+        DeValue::String(value) => Ok(Some(value.as_ref())),
+        _ => Err(self.type_error(key, item, "a string")),
+      },
+    }
+  }
+
+  fn required_string(&self, key: &str) -> Result<&'t str, SceneError> {
+    self.required(key)?;
+    Ok(self.string(key)?.unwrap())
+  }
+
+  fn vec3(&self, key: &str) -> Result<Option<Vec3>, SceneError> {
+    let item = match self.table.get(key) {
+      None => return Ok(None),
+      Some(item) => item,
+    };
+    match item.get_ref() {
+      DeValue::Array(values) if values.len() == 3 => {
+        let components = values.iter().map(to_float).collect::<Option<Vec<f64>>>()
+          .ok_or_else(|| self.type_error(key, item, "an array of three numbers"))?;
+        Ok(Some(Vec3::new(components[0], components[1], components[2])))
+      }
+      _ => Err(self.type_error(key, item, "an array of three numbers")),
+    }
+  }
+
+  fn floats(&self, key: &str, len: usize) -> Result<Option<Vec<f64>>, SceneError> {
+    let item = match self.table.get(key) {
+      None => return Ok(None),
+      Some(item) => item,
+    };
+    let expected = format!("an array of {} numbers", len);
+    match item.get_ref() {
+      DeValue::Array(values) if values.len() == len => values.iter().map(to_float).collect::<Option<Vec<f64>>>()
+        .map(Some)
+        .ok_or_else(|| self.type_error(key, item, &expected)),
+      _ => Err(self.type_error(key, item, &expected)),
+    }
+  }
+
+  fn required_vec3(&self, key: &str) -> Result<Vec3, SceneError> {
+    self.required(key)?;
+    Ok(self.vec3(key)?.unwrap())
+  }
+
+  fn type_error(&self, key: &str, item: &Item, expected: &str) -> SceneError {
+    SceneError::Parse {
+      line: line_at(self.source, item.span().start),
+      message: format!("expected {} for '{}' in {}, found {}", expected, key, self.name, type_name(item.get_ref())),
+    }
+  }
+
+  fn error_at(&self, key: &str, message: &str) -> SceneError {
+    let start = self.table.get(key).map_or(self.span.start, |item| item.span().start);
+    SceneError::Parse { line: line_at(self.source, start), message: message.to_string() }
+  }
+}
+
+// Line, counting from 1, of a byte offset into the source
+fn line_at(source: &str, offset: usize) -> usize {
+  source[..offset.min(source.len())].matches('\n').count() + 1
+}
+
+fn type_name(value: &DeValue) -> &'static str {
+  match value {
+    DeValue::String(_) => "a string",
+    DeValue::Integer(_) => "an integer",
+    DeValue::Float(_) => "a float",
+    DeValue::Boolean(_) => "a boolean",
+    DeValue::Datetime(_) => "a date-time",
+    DeValue::Array(_) => "an array",
+    DeValue::Table(_) => "a table",
+  }
+}
+
+fn to_float(item: &Item) -> Option<f64> {
+  match item.get_ref() {
+    DeValue::Float(value) => value.as_str().parse().ok(),
+    DeValue::Integer(value) => i64::from_str_radix(value.as_str(), value.radix()).ok().map(|value| value as f64),
+    _ => None,
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{interval::Interval, ray::Ray};
+
+  fn parse(source: &str) -> Result<Scene, SceneError> {
+    Scene::parse(source, Path::new(""))
+  }
+
+  fn error(source: &str) -> (usize, String) {
+    match parse(source) {
+      Err(SceneError::Parse { line, message }) => (line, message),
+      Err(err) => panic!("unexpected error: {}", err),
+      Ok(_) => panic!("expected an error"),
+    }
+  }
+
+  const SPHERE: &str = r#"
+[[textures]]
+name = "solid_red"
+type = "solid"
+color = [1.0, 0.0, 0.0]
+
+[[textures]]
+name = "red"
+type = "checker"
+scale = 1.0
+even = [1.0, 0.0, 0.0]
+odd = "solid_red"
+
+[[materials]]
+name = "ground"
+type = "lambertian"
+albedo = "red"
+
+[[objects]]
+type = "sphere"
+center = [0.0, 0.0, -5.0]
+radius = 1.0
+material = "ground"
+"#;
+
+  #[test]
+  fn unknown_keys_report_their_line() {
+    let (line, message) = error("[camera]\nimage_width = 10\n\nbogus = 1\n");
+    assert_eq!(line, 4);
+    assert!(message.contains("unknown key 'bogus' in [camera]"), "{}", message);
+
+    let (line, message) = error("[[objects]]\ntype = \"sphere\"\ncentre = [0, 0, 0]\n");
+    assert_eq!(line, 3);
+    assert!(message.contains("'centre'"), "{}", message);
+  }
+
+  #[test]
+  fn values_of_the_wrong_type_are_rejected() {
+    let (line, message) = error("[camera]\nimage_width = \"10\"\n");
+    assert_eq!(line, 2);
+    assert!(message.contains("expected an integer for 'image_width'"), "{}", message);
+
+    let (line, message) = error("[camera]\n\nlook_from = [0, 1]\n");
+    assert_eq!(line, 3);
+    assert!(message.contains("an array of three numbers"), "{}", message);
+
+    // Malformed TOML is reported where the parser stopped
+    let (line, _) = error("[camera]\nimage_width = 1__0\n");
+    assert_eq!(line, 2);
+  }
+
+  #[test]
+  fn named_materials_and_textures_resolve() {
+    let scene = parse(SPHERE).unwrap_or_else(|err| panic!("{}", err));
+    let ray = Ray::new(Point3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, -1.0));
+    let (hit, rec) = scene.world.hit(&ray, &Interval::new_from_range(0.001, f64::INFINITY));
+    assert!(hit);
+    let rec = rec.unwrap();
+    let albedo = rec.material.albedo(&rec);
+    assert_eq!((albedo.x, albedo.y, albedo.z), (1.0, 0.0, 0.0));
+  }
+
+  #[test]
+  fn missing_references_are_rejected() {
+    let (line, message) = error(&SPHERE.replace("material = \"ground\"", "material = \"stone\""));
+    assert_eq!(line, 23);
+    assert!(message.contains("unknown material 'stone'"), "{}", message);
+
+    let (line, message) = error(&SPHERE.replace("albedo = \"red\"", "albedo = \"blue\""));
+    assert_eq!(line, 17);
+    assert!(message.contains("unknown texture 'blue'"), "{}", message);
+  }
+
+  #[test]
+  fn negative_seeds_are_rejected() {
+    let (line, message) = error("[camera]\nseed = -1\n");
+    assert_eq!(line, 2);
+    assert!(message.contains("seed must not be negative"), "{}", message);
+    assert!(parse("[camera]\nseed = 0\n").is_ok());
+  }
+
+  #[test]
+  fn animated_emitters_are_sampled_where_they_move() {
+    let scene = parse(r#"
+[[materials]]
+name = "lamp"
+type = "diffuse_light"
+emit = [4.0, 4.0, 4.0]
+
+[[objects]]
+type = "sphere"
+center = [0.0, 0.0, 0.0]
+radius = 0.5
+material = "lamp"
+keyframes = [{ time = 0.0 }, { time = 1.0, translate = [10.0, 0.0, 0.0] }]
+"#).unwrap_or_else(|err| panic!("{}", err));
+    assert_eq!(scene.lights.objects.objects.len(), 1);
+    let origin = Point3::new(10.0, 5.0, 0.0);
+    let down = Vec3::new(0.0, -1.0, 0.0);
+    assert!(scene.lights.objects.pdf_value(&origin, &down, 1.0) > 0.0);
+    assert_eq!(scene.lights.objects.pdf_value(&origin, &down, 0.0), 0.0);
+  }
+
+  #[test]
+  fn bundled_scenes_load() {
+    for entry in fs::read_dir(Path::new(env!("CARGO_MANIFEST_DIR")).join("scenes")).unwrap() {
+      let path = entry.unwrap().path();
+      if path.extension().is_some_and(|extension| extension == "toml") {
+        Scene::load(&path).unwrap_or_else(|err| panic!("{}: {}", path.display(), err));
+      }
+    }
+  }
+}
\ No newline at end of file
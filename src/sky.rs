@@ -0,0 +1,262 @@
+use crate::{color::Color, common::Common, environment::Environment, onb::Onb, sampler::PixelSampler, vec3::Vec3};
+
+// Radiance of the sun above the atmosphere, in the same units as the sky model (thousands of cd/m^2)
+const SUN_RADIANCE: f64 = 2.0e6;
+// Brings daylight into a usable range, so that a white diffuse surface in the midday sun is about 1
+const DAYLIGHT_SCALE: f64 = 0.03;
+// Probability of sampling the sun rather than the whole sky when it is above the horizon
+const SUN_SAMPLING_PROBABILITY: f64 = 0.5;
+
+// Analytic daylight sky from Preetham, Shirley and Smits, "A Practical Analytic Model for Daylight",
+// with a sun disk attenuated by the atmosphere and a uniformly lit ground below the horizon
+pub struct PreethamSky {
+  sun_direction: Vec3,
+  // Cosine of the sun's angular radius
+  sun_cos_radius: f64,
+  sun_radiance: Color,
+  // Zenith value and Perez coefficients A to E for the luminance Y and chromaticities x and y
+  zenith: [f64; 3],
+  perez: [[f64; 5]; 3],
+  // Perez function towards the zenith, which the sky is normalized by
+  perez_zenith: [f64; 3],
+  ground_albedo: Color,
+  ground_radiance: Color,
+  intensity: f64,
+}
+
+impl PreethamSky {
+  // Sky for a sun in the given direction, which is kept above the horizon, and a turbidity between
+  // about 2 (very clear) and 10 (hazy)
+  pub fn new(sun_direction: Vec3, turbidity: f64) -> PreethamSky {
+    let mut sun_direction = sun_direction.unit_vector();
+    if sun_direction.y < 0.0 {
+      sun_direction = Vec3::new(sun_direction.x, 0.0, sun_direction.z).unit_vector();
+    }
+    let t = turbidity.clamp(1.7, 10.0);
+    let theta_s = sun_direction.y.clamp(-1.0, 1.0).acos();
+
+    // Zenith luminance and chromaticity
+    let chi = (4.0 / 9.0 - t / 120.0) * (Common::PI - 2.0 * theta_s);
+    let zenith_y = (4.0453 * t - 4.9710) * chi.tan() - 0.2155 * t + 2.4192;
+    let theta = [theta_s.powi(3), theta_s.powi(2), theta_s, 1.0];
+    let t_vec = [t * t, t, 1.0];
+    let chromaticity = |m: [[f64; 4]; 3]| -> f64 {
+      (0..3).map(|i| t_vec[i] * (0..4).map(|j| m[i][j] * theta[j]).sum::<f64>()).sum()
+    };
+    let zenith_x = chromaticity([
+      [0.00166, -0.00375, 0.00209, 0.0],
+      [-0.02903, 0.06377, -0.03202, 0.00394],
+      [0.11693, -0.21196, 0.06052, 0.25886],
+    ]);
+    let zenith_y_chroma = chromaticity([
+      [0.00275, -0.00610, 0.00317, 0.0],
+      [-0.04214, 0.08970, -0.04153, 0.00516],
+      [0.15346, -0.26756, 0.06670, 0.26688],
+    ]);
+
+    let perez = [
+      [0.1787 * t - 1.4630, -0.3554 * t + 0.4275, -0.0227 * t + 5.3251, 0.1206 * t - 2.5771, -0.0670 * t + 0.3703],
+      [-0.0193 * t - 0.2592, -0.0665 * t + 0.0008, -0.0004 * t + 0.2125, -0.0641 * t - 0.8989, -0.0033 * t + 0.0452],
+      [-0.0167 * t - 0.2608, -0.0950 * t + 0.0092, -0.0079 * t + 0.2102, -0.0441 * t - 1.6537, -0.0109 * t + 0.0529],
+    ];
+    let perez_zenith = perez.map(|coefficients| PreethamSky::perez(&coefficients, 0.0, theta_s));
+
+    let mut sky = PreethamSky {
+      sun_direction,
+      sun_cos_radius: Common::degrees_to_radians(0.53 / 2.0).cos(),
+      sun_radiance: PreethamSky::sun_radiance(theta_s, t),
+      zenith: [zenith_y.max(0.0), zenith_x, zenith_y_chroma],
+      perez,
+      perez_zenith,
+      ground_albedo: Color::new(0.3, 0.3, 0.3),
+      ground_radiance: Color::new(0.0, 0.0, 0.0),
+      intensity: 1.0,
+    };
+    sky.update_ground();
+    sky
+  }
+
+  // Sky for a sun at an elevation above the horizon and an azimuth in degrees, measured from -Z
+  // towards +X
+  pub fn new_from_angles(elevation: f64, azimuth: f64, turbidity: f64) -> PreethamSky {
+    let elevation = Common::degrees_to_radians(elevation);
+    let azimuth = Common::degrees_to_radians(azimuth);
+    let direction = Vec3::new(elevation.cos() * azimuth.sin(), elevation.sin(), -elevation.cos() * azimuth.cos());
+    PreethamSky::new(direction, turbidity)
+  }
+
+  // Fraction of the light reaching the ground that it reflects back up
+  pub fn set_ground_albedo(&mut self, albedo: Color) {
+    self.ground_albedo = albedo;
+    self.update_ground();
+  }
+
+  // Angular diameter of the sun disk in degrees, 0.53 for the real sun. The sun's radiance is scaled
+  // to keep its total light the same
+  pub fn set_sun_size(&mut self, degrees: f64) {
+    let old_solid_angle = self.sun_solid_angle();
+    self.sun_cos_radius = Common::degrees_to_radians(degrees.clamp(0.01, 90.0) / 2.0).cos();
+    self.sun_radiance = self.sun_radiance * (old_solid_angle / self.sun_solid_angle());
+    self.update_ground();
+  }
+
+  // Scale factor applied to the sky, sun and ground
+  pub fn set_intensity(&mut self, intensity: f64) {
+    self.intensity = intensity;
+  }
+
+  // Perez luminance distribution for a view at zenith angle theta and angle gamma from the sun
+  fn perez(coefficients: &[f64; 5], theta: f64, gamma: f64) -> f64 {
+    let [a, b, c, d, e] = *coefficients;
+    (1.0 + a * (b / theta.cos().max(0.01)).exp()) * (1.0 + c * (d * gamma).exp() + e * gamma.cos().powi(2))
+  }
+
+  // Direct sun light after Rayleigh and aerosol extinction along the air mass for its zenith
+  // angle, evaluated at representative red, green and blue wavelengths
+  fn sun_radiance(theta_s: f64, turbidity: f64) -> Color {
+    let theta_degrees = theta_s.to_degrees();
+    let air_mass = 1.0 / (theta_s.cos() + 0.15 * (93.885 - theta_degrees).max(0.01).powf(-1.253));
+    let beta = 0.04608 * turbidity - 0.04586;
+    let transmittance = |lambda: f64| -> f64 {
+      let rayleigh = (-0.008735 * lambda.powf(-4.08) * air_mass).exp();
+      let aerosol = (-beta * lambda.powf(-1.3) * air_mass).exp();
+      rayleigh * aerosol
+    };
+    Color::new(transmittance(0.680), transmittance(0.550), transmittance(0.440)) * SUN_RADIANCE
+  }
+
+  fn sky_radiance(&self, direction: &Vec3) -> Color {
+    let theta = direction.y.clamp(-1.0, 1.0).acos();
+    let gamma = direction.dot(&self.sun_direction).clamp(-1.0, 1.0).acos();
+
+    let [luminance, x, y] = [0, 1, 2].map(|i| {
+      self.zenith[i] * PreethamSky::perez(&self.perez[i], theta, gamma) / self.perez_zenith[i]
+    });
+    if y <= 0.0 {
+      return Color::new(0.0, 0.0, 0.0);
+    }
+
+    let rgb = Color::new_from_xyz(x / y * luminance, luminance, (1.0 - x - y) / y * luminance);
+    Color::new(rgb.x.max(0.0), rgb.y.max(0.0), rgb.z.max(0.0))
+  }
+
+  // The ground reflects the light falling on it from the sky and sun diffusely
+  fn update_ground(&mut self) {
+    let (n_theta, n_phi) = (32, 64);
+    let mut irradiance = Color::new(0.0, 0.0, 0.0);
+    for i in 0..n_theta {
+      let theta = (i as f64 + 0.5) / n_theta as f64 * Common::PI / 2.0;
+      for j in 0..n_phi {
+        let phi = (j as f64 + 0.5) / n_phi as f64 * 2.0 * Common::PI;
+        let direction = Vec3::new(theta.sin() * phi.cos(), theta.cos(), theta.sin() * phi.sin());
+        let d_omega = theta.sin() * (Common::PI / 2.0 / n_theta as f64) * (2.0 * Common::PI / n_phi as f64);
+        irradiance = irradiance + self.sky_radiance(&direction) * (theta.cos() * d_omega);
+      }
+    }
+    irradiance = irradiance + self.sun_radiance * (self.sun_solid_angle() * self.sun_direction.y);
+
+    self.ground_radiance = self.ground_albedo * irradiance / Common::PI;
+  }
+
+  fn sun_solid_angle(&self) -> f64 {
+    2.0 * Common::PI * (1.0 - self.sun_cos_radius)
+  }
+}
+
+impl Environment for PreethamSky {
+  fn value(&self, direction: &Vec3) -> Color {
+    let direction = direction.unit_vector();
+    let radiance = if direction.y < 0.0 {
+      self.ground_radiance
+    } else if direction.dot(&self.sun_direction) >= self.sun_cos_radius {
+      self.sky_radiance(&direction) + self.sun_radiance
+    } else {
+      self.sky_radiance(&direction)
+    };
+    radiance * (DAYLIGHT_SCALE * self.intensity)
+  }
+
+  // The sun is sampled uniformly within its disk, the rest of the sky uniformly over all directions
+  fn pdf_value(&self, direction: &Vec3) -> f64 {
+    let in_sun = direction.unit_vector().dot(&self.sun_direction) >= self.sun_cos_radius;
+    let sun_pdf = if in_sun { 1.0 / self.sun_solid_angle() } else { 0.0 };
+    SUN_SAMPLING_PROBABILITY * sun_pdf + (1.0 - SUN_SAMPLING_PROBABILITY) / (4.0 * Common::PI)
+  }
+
+  fn random(&self, sampler: &mut PixelSampler) -> Vec3 {
+    let choice = sampler.get_1d();
+    let (u1, u2) = sampler.get_2d();
+    if choice >= SUN_SAMPLING_PROBABILITY {
+      return Vec3::sample_unit_vector((u1, u2));
+    }
+
+    let z = 1.0 + u1 * (self.sun_cos_radius - 1.0);
+    let phi = 2.0 * Common::PI * u2;
+    let r = (1.0 - z * z).max(0.0).sqrt();
+    Onb::new(&self.sun_direction).local(&Vec3::new(r * phi.cos(), r * phi.sin(), z))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::pdf::{tests::{assert_generates_value, integrate, sampler}, Pdf};
+
+  // Suns far larger than the real one, so that the grids of the tests resolve their disk. Diameters
+  // a multiple of 0.36 degrees put the edge between rows of the integration grid
+  fn sky(elevation: f64, azimuth: f64, sun_size: f64) -> PreethamSky {
+    let mut sky = PreethamSky::new_from_angles(elevation, azimuth, 3.0);
+    sky.set_sun_size(sun_size);
+    sky
+  }
+
+  #[test]
+  fn density_integrates_to_one() {
+    for sky in [sky(45.0, 30.0, 3.6), sky(10.0, -120.0, 18.0), sky(80.0, 0.0, 36.0)] {
+      // Integrated about the sun, where the grid is finest
+      let frame = Onb::new(&sky.sun_direction);
+      let integral = integrate(&|direction| sky.pdf_value(&frame.local(direction)));
+      assert!((integral - 1.0).abs() < 1e-3, "integral {}", integral);
+    }
+  }
+
+  // Directions of the sky's density turned so that the sun is along +Z
+  struct AboutTheSun<'a> {
+    sky: &'a PreethamSky,
+    frame: Onb,
+  }
+
+  impl Pdf for AboutTheSun<'_> {
+    fn value(&self, direction: &Vec3) -> f64 {
+      self.sky.pdf_value(&self.frame.local(direction))
+    }
+
+    fn generate(&self, sampler: &mut PixelSampler) -> Vec3 {
+      self.frame.to_local(&self.sky.random(sampler))
+    }
+  }
+
+  #[test]
+  fn sampled_directions_follow_the_density() {
+    // With the sun at the pole of the histogram, diameters of an even number of rows of the grid
+    // integrating its top bins put the edge of the disk between rows, and the widest fills them
+    let row = 0.75f64.acos().to_degrees() / 32.0;
+    for sky in [sky(45.0, 30.0, 16.0 * row), sky(10.0, -120.0, 64.0 * row)] {
+      assert_generates_value(&AboutTheSun { sky: &sky, frame: Onb::new(&sky.sun_direction) }, 1.0);
+    }
+  }
+  #[test]
+  fn the_sun_is_sampled_evenly_over_its_disk() {
+    // Even in solid angle is even in the cosine of the angle to the sun, and the rest of the sky
+    // is sampled evenly over the sphere
+    let sky = sky(30.0, 60.0, 0.53);
+    let n = 100_000;
+    let cosines: Vec<f64> = (0..n).map(|index| sky.random(&mut sampler(index)).unit_vector().dot(&sky.sun_direction)).collect();
+    for fraction in [0.0, 0.25, 0.5, 0.75] {
+      let c = 1.0 + fraction * (sky.sun_cos_radius - 1.0);
+      let within = cosines.iter().filter(|&&cosine| cosine >= c).count() as f64 / n as f64;
+      let expected = SUN_SAMPLING_PROBABILITY * (1.0 - c) / (1.0 - sky.sun_cos_radius) + (1.0 - SUN_SAMPLING_PROBABILITY) * (1.0 - c) / 2.0;
+      assert!((within - expected).abs() < 0.006, "{} sampled {} of the way to the edge, expected {}", within, fraction, expected);
+    }
+  }
+}
\ No newline at end of file
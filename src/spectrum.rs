@@ -0,0 +1,290 @@
+use std::sync::OnceLock;
+
+use crate::{color::Color, vec3::Vec3};
+
+// Range of visible wavelengths in nanometers that rays sample from
+pub const WAVELENGTH_MIN: f64 = 380.0;
+pub const WAVELENGTH_MAX: f64 = 780.0;
+
+// Visible wavelength for a number in [0, 1), uniform over the range
+pub fn sample_wavelength(u: f64) -> f64 {
+  WAVELENGTH_MIN + u * (WAVELENGTH_MAX - WAVELENGTH_MIN)
+}
+
+// CIE 1931 color matching functions, using the multi-lobe Gaussian fit of Wyman, Sloan and Shirley,
+// "Simple Analytic Approximations to the CIE XYZ Color Matching Functions", 2013
+pub fn cie_xyz(wavelength: f64) -> Vec3 {
+  let g = |mu: f64, sigma_below: f64, sigma_above: f64| {
+    let t = (wavelength - mu) / if wavelength < mu { sigma_below } else { sigma_above };
+    (-0.5 * t * t).exp()
+  };
+  Vec3::new(
+    1.056 * g(599.8, 37.9, 31.0) + 0.362 * g(442.0, 16.0, 26.7) - 0.065 * g(501.1, 20.4, 26.2),
+    0.821 * g(568.8, 46.9, 40.5) + 0.286 * g(530.9, 16.3, 31.1),
+    1.217 * g(437.0, 11.8, 36.0) + 0.681 * g(459.0, 26.0, 13.8),
+  )
+}
+
+// Linear sRGB weight of light at a single wavelength, scaled so that the weights of uniformly sampled
+// wavelengths average to white. Wavelengths outside the sRGB gamut are clipped to it rather than given
+// negative channels, which would show up as noise
+pub fn wavelength_weight(wavelength: f64) -> Color {
+  static NORMALIZATION: OnceLock<Color> = OnceLock::new();
+  let normalization = NORMALIZATION.get_or_init(|| average_over_spectrum(|wavelength| clipped_rgb(cie_xyz(wavelength))));
+
+  let rgb = clipped_rgb(cie_xyz(wavelength));
+  Color::new(rgb.x / normalization.x, rgb.y / normalization.y, rgb.z / normalization.z)
+}
+
+// Wavelengths carried by a path in spectral mode, one per channel of a Color. The first, the hero
+// wavelength, is sampled uniformly and the others are spaced evenly after it, wrapping around the
+// visible range, so that a single path covers the whole spectrum
+#[derive(Copy, Clone)]
+pub struct Wavelengths {
+  pub lambda: [f64; 3],
+}
+
+impl Wavelengths {
+  pub fn sample(u: f64) -> Wavelengths {
+    let range = WAVELENGTH_MAX - WAVELENGTH_MIN;
+    let hero = sample_wavelength(u) - WAVELENGTH_MIN;
+    Wavelengths {
+      lambda: [0.0, 1.0, 2.0].map(|i| WAVELENGTH_MIN + (hero + i * range / 3.0) % range),
+    }
+  }
+
+  pub fn hero(&self) -> f64 {
+    self.lambda[0]
+  }
+
+  // Convert radiance at the wavelengths to linear sRGB through CIE XYZ. The result is white balanced
+  // so that a flat spectrum is white, which keeps RGB albedos and lights looking as they do in RGB mode
+  pub fn to_rgb(&self, values: Color) -> Color {
+    let xyz = cie_xyz(self.lambda[0]) * values.x + cie_xyz(self.lambda[1]) * values.y + cie_xyz(self.lambda[2]) * values.z;
+    let rgb = Color::new_from_xyz(xyz.x, xyz.y, xyz.z) / 3.0;
+    let white = white_balance();
+    Color::new(rgb.x / white.x, rgb.y / white.y, rgb.z / white.z)
+  }
+
+  // Drop the secondary wavelengths from values carried along a path, for when the path can only follow
+  // the hero wavelength, such as after dispersion. The hero is tripled since to_rgb divides the sum of
+  // all three wavelengths by 3, which keeps the path an unbiased estimate
+  pub fn terminate_secondary(values: Color) -> Color {
+    Color::new(3.0 * values.x, 0.0, 0.0)
+  }
+}
+
+// Spectral values at the wavelengths of a linear sRGB color, using the basis spectra of Smits, "An
+// RGB to Spectrum Conversion for Reflectances", 1999. The conversion scales with the color, so it
+// serves for reflectances and emission alike, and colors within [0, 1] stay within [0, 1]
+pub fn upsample(rgb: Color, wavelengths: &Wavelengths) -> Color {
+  let (r, g, b) = (rgb.x.max(0.0), rgb.y.max(0.0), rgb.z.max(0.0));
+  let weights = if r <= g && r <= b {
+    if g <= b { [r, g - r, 0.0, 0.0, 0.0, 0.0, b - g] } else { [r, b - r, 0.0, 0.0, 0.0, g - b, 0.0] }
+  } else if g <= r && g <= b {
+    if r <= b { [g, 0.0, r - g, 0.0, 0.0, 0.0, b - r] } else { [g, 0.0, b - g, 0.0, r - b, 0.0, 0.0] }
+  } else if r <= g {
+    [b, 0.0, 0.0, r - b, 0.0, g - r, 0.0]
+  } else {
+    [b, 0.0, 0.0, g - b, r - g, 0.0, 0.0]
+  };
+
+  let value = |wavelength: f64| {
+    let x = ((wavelength - SMITS_MIN) / (SMITS_MAX - SMITS_MIN) * 9.0).clamp(0.0, 9.0);
+    let i = (x as usize).min(8);
+    let f = x - i as f64;
+    weights.iter().zip(SMITS_BASIS.iter()).map(|(w, basis)| w * (basis[i] * (1.0 - f) + basis[i + 1] * f)).sum()
+  };
+  Color::new(value(wavelengths.lambda[0]), value(wavelengths.lambda[1]), value(wavelengths.lambda[2]))
+}
+
+// Smits basis spectra sampled evenly from 380 to 720 nm, in the order white, cyan, magenta, yellow,
+// red, green and blue
+const SMITS_MIN: f64 = 380.0;
+const SMITS_MAX: f64 = 720.0;
+const SMITS_BASIS: [[f64; 10]; 7] = [
+  [1.0000, 1.0000, 0.9999, 0.9993, 0.9992, 0.9998, 1.0000, 1.0000, 1.0000, 1.0000],
+  [0.9710, 0.9426, 1.0007, 1.0007, 1.0007, 1.0007, 0.1564, 0.0000, 0.0000, 0.0000],
+  [1.0000, 1.0000, 0.9685, 0.2229, 0.0000, 0.0458, 0.8369, 1.0000, 1.0000, 0.9959],
+  [0.0001, 0.0000, 0.1088, 0.6651, 1.0000, 1.0000, 0.9996, 0.9586, 0.9685, 0.9840],
+  [0.1012, 0.0515, 0.0000, 0.0000, 0.0000, 0.0000, 0.8325, 1.0149, 1.0149, 1.0149],
+  [0.0000, 0.0000, 0.0273, 0.7937, 1.0000, 0.9418, 0.1719, 0.0000, 0.0000, 0.0025],
+  [1.0000, 1.0000, 0.8916, 0.3323, 0.0000, 0.0000, 0.0003, 0.0369, 0.0483, 0.0496],
+];
+
+// Emission spectrum of a light, normalized to unit luminance so that it only sets the color of the
+// light and its intensity is given separately. Spectra are taken relative to a black body at the
+// temperature of D65, the white of sRGB, the way a camera is white balanced for daylight, so that such
+// a light is white while a flat spectrum stands for the white of RGB colors
+pub struct Spectrum {
+  shape: Shape,
+  scale: f64,
+  rgb: Color,
+}
+
+enum Shape {
+  // Black body at a temperature in kelvin
+  Blackbody(f64),
+  // Measured values at increasing wavelengths in nanometers, interpolated linearly between them and
+  // zero outside of them
+  Sampled(Vec<(f64, f64)>),
+}
+
+impl Spectrum {
+  pub fn new_blackbody(temperature: f64) -> Spectrum {
+    Spectrum::new_from_shape(Shape::Blackbody(temperature.max(1.0)))
+  }
+
+  // Spectrum from (wavelength, value) pairs, which are sorted by wavelength
+  pub fn new_sampled(mut samples: Vec<(f64, f64)>) -> Spectrum {
+    samples.sort_by(|a, b| a.0.total_cmp(&b.0));
+    Spectrum::new_from_shape(Shape::Sampled(samples))
+  }
+
+  fn new_from_shape(shape: Shape) -> Spectrum {
+    let mut spectrum = Spectrum { shape, scale: 1.0, rgb: Color::new(0.0, 0.0, 0.0) };
+    let rgb = average_over_spectrum(|wavelength| balanced_rgb(cie_xyz(wavelength)) * spectrum.value(wavelength));
+    let luminance = rgb.luminance();
+    if luminance > 0.0 {
+      spectrum.scale = 1.0 / luminance;
+      spectrum.rgb = rgb / luminance;
+    }
+    spectrum
+  }
+
+  pub fn value(&self, wavelength: f64) -> f64 {
+    self.shape.value(wavelength) / planck(wavelength, WHITE_TEMPERATURE) * self.scale
+  }
+
+  pub fn values(&self, wavelengths: &Wavelengths) -> Color {
+    Color::new(self.value(wavelengths.lambda[0]), self.value(wavelengths.lambda[1]), self.value(wavelengths.lambda[2]))
+  }
+
+  // Linear sRGB color of the spectrum, used when rendering in RGB. Colors outside the sRGB gamut,
+  // such as very low temperatures, are clipped to it
+  pub fn rgb(&self) -> Color {
+    Color::new(self.rgb.x.max(0.0), self.rgb.y.max(0.0), self.rgb.z.max(0.0))
+  }
+}
+
+impl Shape {
+  fn value(&self, wavelength: f64) -> f64 {
+    match self {
+      Shape::Blackbody(temperature) => planck(wavelength, *temperature),
+      Shape::Sampled(samples) => {
+        let i = samples.partition_point(|(lambda, _)| *lambda < wavelength);
+        match (samples.get(i.wrapping_sub(1)), samples.get(i)) {
+          (Some(&(l0, v0)), Some(&(l1, v1))) => v0 + (v1 - v0) * (wavelength - l0) / (l1 - l0),
+          (None, Some(&(l1, v1))) if l1 == wavelength => v1,
+          _ => 0.0,
+        }
+      }
+    }
+  }
+}
+
+// Correlated color temperature of D65 in kelvin
+const WHITE_TEMPERATURE: f64 = 6504.0;
+
+// Spectral radiance of a black body, in arbitrary units
+fn planck(wavelength: f64, temperature: f64) -> f64 {
+  const C: f64 = 2.99792458e8;
+  const H: f64 = 6.62607015e-34;
+  const K: f64 = 1.380649e-23;
+  let lambda = wavelength * 1e-9;
+  2.0 * H * C * C / (lambda.powi(5) * ((H * C / (lambda * K * temperature)).exp_m1())) * 1e-12
+}
+
+// Average of a function of wavelength over the visible range, by the midpoint rule
+fn average_over_spectrum<F: Fn(f64) -> Color>(f: F) -> Color {
+  let steps = 4000;
+  let step = (WAVELENGTH_MAX - WAVELENGTH_MIN) / steps as f64;
+  let total = (0..steps)
+    .map(|i| f(WAVELENGTH_MIN + (i as f64 + 0.5) * step))
+    .fold(Color::new(0.0, 0.0, 0.0), |sum, rgb| sum + rgb);
+  total / steps as f64
+}
+
+// Average linear sRGB color of the visible wavelengths, which a flat spectrum is divided by to be white
+fn white_balance() -> Color {
+  static WHITE: OnceLock<Color> = OnceLock::new();
+  *WHITE.get_or_init(|| average_over_spectrum(|wavelength| {
+    let xyz = cie_xyz(wavelength);
+    Color::new_from_xyz(xyz.x, xyz.y, xyz.z)
+  }))
+}
+
+fn balanced_rgb(xyz: Vec3) -> Color {
+  let rgb = Color::new_from_xyz(xyz.x, xyz.y, xyz.z);
+  let white = white_balance();
+  Color::new(rgb.x / white.x, rgb.y / white.y, rgb.z / white.z)
+}
+
+fn clipped_rgb(xyz: Vec3) -> Color {
+  let rgb = Color::new_from_xyz(xyz.x, xyz.y, xyz.z);
+  Color::new(rgb.x.max(0.0), rgb.y.max(0.0), rgb.z.max(0.0))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  // Every wavelength of the visible range, a nanometer apart
+  fn visible() -> impl Iterator<Item = f64> {
+    (0..=(WAVELENGTH_MAX - WAVELENGTH_MIN) as usize).map(|i| WAVELENGTH_MIN + i as f64)
+  }
+
+  #[test]
+  fn white_upsamples_to_a_flat_spectrum() {
+    for wavelength in visible() {
+      let values = upsample(Color::new(1.0, 1.0, 1.0), &Wavelengths { lambda: [wavelength; 3] });
+      assert!((values.x - 1.0).abs() < 1e-3, "{} nm: {}", wavelength, values.x);
+    }
+  }
+
+  #[test]
+  fn flat_spectra_average_to_white() {
+    let n = 3000;
+    let mut sum = Color::new(0.0, 0.0, 0.0);
+    for i in 0..n {
+      sum = sum + Wavelengths::sample((i as f64 + 0.5) / n as f64).to_rgb(Color::new(1.0, 1.0, 1.0));
+    }
+    let mean = sum / n as f64;
+    for value in [mean.x, mean.y, mean.z] {
+      assert!((value - 1.0).abs() < 1e-3, "mean {} {} {}", mean.x, mean.y, mean.z);
+    }
+  }
+
+  #[test]
+  fn d65_black_body_is_white() {
+    let spectrum = Spectrum::new_blackbody(6504.0);
+    let rgb = spectrum.rgb();
+    assert!((rgb.luminance() - 1.0).abs() < 1e-9, "luminance {}", rgb.luminance());
+    for value in [rgb.x, rgb.y, rgb.z] {
+      assert!((value - 1.0).abs() < 1e-3, "color {} {} {}", rgb.x, rgb.y, rgb.z);
+    }
+
+    // Rendered from its spectrum it is the same white
+    for wavelength in visible() {
+      assert!((spectrum.value(wavelength) - 1.0).abs() < 1e-3, "{} nm: {}", wavelength, spectrum.value(wavelength));
+    }
+
+    // Colder bodies are redder and hotter ones bluer
+    let (cold, hot) = (Spectrum::new_blackbody(3000.0).rgb(), Spectrum::new_blackbody(10000.0).rgb());
+    assert!(cold.x > cold.z && hot.z > hot.x);
+  }
+
+  #[test]
+  fn sampled_spectra_interpolate_between_samples() {
+    let shape = Shape::Sampled(vec![(500.0, 1.0), (600.0, 3.0), (650.0, 2.0)]);
+    for (wavelength, expected) in [(500.0, 1.0), (550.0, 2.0), (575.0, 2.5), (600.0, 3.0), (640.0, 2.2), (650.0, 2.0)] {
+      assert!((shape.value(wavelength) - expected).abs() < 1e-12, "{} nm: {}", wavelength, shape.value(wavelength));
+    }
+
+    let spectrum = Spectrum::new_sampled(vec![(600.0, 3.0), (500.0, 1.0), (650.0, 2.0)]);
+    for wavelength in [380.0, 499.9, 650.1, 780.0] {
+      assert_eq!(spectrum.value(wavelength), 0.0, "{} nm", wavelength);
+    }
+    assert!(spectrum.value(550.0) > 0.0);
+  }
+}
\ No newline at end of file
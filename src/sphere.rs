@@ -1,27 +1,75 @@
-use crate::{hittable::{HitRecord, Hittable}, interval::Interval, material::Material, ray::Ray, vec3::Point3};
+use std::sync::Arc;
 
+use crate::{aabb::Aabb, common::Common, hittable::{HitRecord, Hittable}, interval::Interval, material::Material, onb::Onb, ray::Ray, sampler::PixelSampler, vec3::{Point3, Vec3}};
+
+#[derive(Clone)]
 pub struct Sphere {
   pub center: Point3,
   pub radius: f64,
-  pub material: Box<dyn Material>,
+  pub material: Arc<dyn Material>,
+  // Distance the center travels from time 0 to time 1, zero for stationary spheres
+  pub motion: Vec3,
 }
 
 impl Sphere {
-  pub fn new(center: Point3, radius: f64, material: Box<dyn Material>) -> Sphere {
+  pub fn new(center: Point3, radius: f64, material: Arc<dyn Material>) -> Sphere {
+    Sphere::new_moving(center, center, radius, material)
+  }
+
+  // Sphere whose center moves linearly from center1 at time 0 to center2 at time 1
+  pub fn new_moving(center1: Point3, center2: Point3, radius: f64, material: Arc<dyn Material>) -> Sphere {
     Sphere {
-      center,
+      center: center1,
       radius,
       material,
+      motion: center2 - center1,
     }
   }
+
+  pub fn center_at(&self, time: f64) -> Point3 {
+    self.center + self.motion * time
+  }
+
+  // Map a point on the unit sphere to texture coordinates, with u the angle around the Y axis
+  // from X=-1 and v the angle from Y=-1 to Y=+1, both normalized to [0,1]
+  fn get_sphere_uv(p: &Point3) -> (f64, f64) {
+    let theta = (-p.y).acos();
+    let phi = (-p.z).atan2(p.x) + Common::PI;
+
+    (phi / (2.0 * Common::PI), theta / Common::PI)
+  }
+
+  // Derivatives of a point on the sphere with respect to u, around the Y axis, and v, from the bottom to
+  // the top, which vanish at the poles
+  fn tangents(&self, n: &Vec3) -> (Vec3, Vec3) {
+    let dpdu = Vec3::new(n.z, 0.0, -n.x) * (2.0 * Common::PI * self.radius);
+    let sin_theta = (n.x * n.x + n.z * n.z).sqrt();
+    if sin_theta < 1e-9 {
+      return (dpdu, Vec3::new(0.0, 0.0, 0.0));
+    }
+    let dpdv = Vec3::new(-n.x * n.y / sin_theta, sin_theta, -n.y * n.z / sin_theta) * (Common::PI * self.radius);
+    (dpdu, dpdv)
+  }
+
+  // Cosine of the half angle of the cone the sphere subtends as seen from origin, or None when
+  // origin lies inside the sphere
+  fn cos_theta_max(&self, origin: &Point3, time: f64) -> Option<f64> {
+    let distance_squared = (self.center_at(time) - origin).length_squared();
+    let radius_squared = self.radius * self.radius;
+    if distance_squared <= radius_squared {
+      return None;
+    }
+    Some((1.0 - radius_squared / distance_squared).sqrt())
+  }
 }
 
 impl Hittable for Sphere {
-  fn hit<'a>(&'a self, ray: &Ray, ray_t: &Interval) -> (bool, Option<HitRecord>) {
-    let oc = ray.origin - &self.center;
+  fn hit(&self, ray: &Ray, ray_t: &Interval) -> (bool, Option<HitRecord<'_>>) {
+    let center = self.center_at(ray.time);
+    let oc = ray.origin - center;
     let a = ray.direction.length_squared();
     let half_b = oc.dot(&ray.direction);
-    let c = oc.length_squared() - &self.radius * &self.radius;
+    let c = oc.length_squared() - self.radius * self.radius;
 
     let discriminant = half_b * half_b - a * c;
     if discriminant < 0.0 { 
@@ -41,19 +89,97 @@ impl Hittable for Sphere {
 
     let t = root;
     let p = ray.at(t);
-    let normal = (&p - &self.center) / self.radius;
+    let outward_normal = (p - center) / self.radius;
+    let (u, v) = Sphere::get_sphere_uv(&outward_normal);
+    let (dpdu, dpdv) = self.tangents(&outward_normal);
 
     let rec = HitRecord {
       p,
       t,
-      material: &self.material,
-      normal,
+      u,
+      v,
+      material: self.material.as_ref(),
+      normal: outward_normal,
+      geometric_normal: outward_normal,
+      dpdu,
+      dpdv,
       front_face: false,
     };
-
-    let outward_normal = (rec.p - self.center) / self.radius;
     let rec = rec.set_face_normal(ray, outward_normal);
 
-    return (true, Some(rec));
+    (true, Some(rec))
+  }
+
+  fn bounding_box(&self) -> Aabb {
+    // Enclose the sphere at both ends of its motion
+    let rvec = Vec3::new(self.radius, self.radius, self.radius);
+    let box1 = Aabb::new_from_points(&(self.center - rvec), &(self.center + rvec));
+    let center2 = self.center_at(1.0);
+    let box2 = Aabb::new_from_points(&(center2 - rvec), &(center2 + rvec));
+    Aabb::new_from_boxes(&box1, &box2)
+  }
+
+  // Directions are sampled uniformly within the cone of the sphere, or over all directions from inside it
+  fn pdf_value(&self, origin: &Point3, direction: &Vec3, time: f64) -> f64 {
+    let (hit, _) = self.hit(&Ray::new_with_time(*origin, *direction, time), &Interval::new_from_range(0.001, Common::INFINITY));
+    if !hit {
+      return 0.0;
+    }
+
+    match self.cos_theta_max(origin, time) {
+      Some(cos_theta_max) => 1.0 / (2.0 * Common::PI * (1.0 - cos_theta_max)),
+      None => 1.0 / (4.0 * Common::PI),
+    }
+  }
+
+  fn random(&self, origin: &Point3, time: f64, sampler: &mut PixelSampler) -> Vec3 {
+    let u = sampler.get_2d();
+    let cos_theta_max = match self.cos_theta_max(origin, time) {
+      Some(cos_theta_max) => cos_theta_max,
+      None => return Vec3::sample_unit_vector(u),
+    };
+
+    let (r1, r2) = u;
+    let z = 1.0 + r2 * (cos_theta_max - 1.0);
+    let phi = 2.0 * Common::PI * r1;
+    let x = phi.cos() * (1.0 - z * z).sqrt();
+    let y = phi.sin() * (1.0 - z * z).sqrt();
+
+    Onb::new(&(self.center_at(time) - origin)).local(&Vec3::new(x, y, z))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{material::Lambertian, pdf::tests::{integrate, sampler}};
+
+  fn moving() -> Sphere {
+    Sphere::new_moving(Point3::new(0.0, 0.0, 0.0), Point3::new(4.0, 2.0, 0.0), 1.0, Arc::new(Lambertian::new(Vec3::new(0.5, 0.5, 0.5))))
+  }
+
+  #[test]
+  fn center_moves_linearly() {
+    let sphere = moving();
+    assert!((sphere.center_at(0.0) - Point3::new(0.0, 0.0, 0.0)).length() < 1e-12);
+    assert!((sphere.center_at(0.25) - Point3::new(1.0, 0.5, 0.0)).length() < 1e-12);
+    assert!((sphere.center_at(1.0) - Point3::new(4.0, 2.0, 0.0)).length() < 1e-12);
+  }
+
+  #[test]
+  fn moving_sphere_is_sampled_where_it_is_at_the_time() {
+    let sphere = moving();
+    let origin = Point3::new(2.0, -5.0, 3.0);
+    for time in [0.0, 0.5, 1.0] {
+      for index in 0..100 {
+        let direction = sphere.random(&origin, time, &mut sampler(index));
+        let ray = Ray::new_with_time(origin, direction, time);
+        assert!(sphere.hit(&ray, &Interval::new_from_range(0.001, Common::INFINITY)).0);
+      }
+      let integral = integrate(&|direction| sphere.pdf_value(&origin, direction, time));
+      assert!((integral - 1.0).abs() < 1e-2, "integral {}", integral);
+    }
+    // At the end of its motion the sphere has left the direction of its start
+    assert_eq!(sphere.pdf_value(&origin, &(sphere.center_at(0.0) - origin), 1.0), 0.0);
   }
 }
\ No newline at end of file
@@ -0,0 +1,140 @@
+use std::{path::Path, sync::Arc};
+
+use crate::{color::Color, image::{Image, ImageError}, interval::Interval, perlin::Perlin, vec3::Point3};
+
+pub trait Texture: Send + Sync {
+  fn value(&self, u: f64, v: f64, p: &Point3) -> Color;
+}
+
+pub struct SolidColor {
+  albedo: Color,
+}
+
+impl SolidColor {
+  pub fn new(albedo: Color) -> SolidColor {
+    SolidColor {
+      albedo
+    }
+  }
+
+  pub fn new_from_rgb(red: f64, green: f64, blue: f64) -> SolidColor {
+    SolidColor::new(Color::new(red, green, blue))
+  }
+}
+
+impl Texture for SolidColor {
+  fn value(&self, _: f64, _: f64, _: &Point3) -> Color {
+    self.albedo
+  }
+}
+
+// Alternates between two textures on a 3D grid of cubes with the given size
+pub struct CheckerTexture {
+  inv_scale: f64,
+  even: Arc<dyn Texture>,
+  odd: Arc<dyn Texture>,
+}
+
+impl CheckerTexture {
+  pub fn new(scale: f64, even: Arc<dyn Texture>, odd: Arc<dyn Texture>) -> CheckerTexture {
+    CheckerTexture {
+      inv_scale: 1.0 / scale,
+      even,
+      odd,
+    }
+  }
+
+  pub fn new_from_colors(scale: f64, even: Color, odd: Color) -> CheckerTexture {
+    CheckerTexture::new(scale, Arc::new(SolidColor::new(even)), Arc::new(SolidColor::new(odd)))
+  }
+}
+
+impl Texture for CheckerTexture {
+  fn value(&self, u: f64, v: f64, p: &Point3) -> Color {
+    let x = (self.inv_scale * p.x).floor() as i64;
+    let y = (self.inv_scale * p.y).floor() as i64;
+    let z = (self.inv_scale * p.z).floor() as i64;
+
+    if (x + y + z) % 2 == 0 {
+      self.even.value(u, v, p)
+    } else {
+      self.odd.value(u, v, p)
+    }
+  }
+}
+
+pub struct ImageTexture {
+  image: Image,
+}
+
+impl ImageTexture {
+  pub fn new(image: Image) -> ImageTexture {
+    ImageTexture {
+      image
+    }
+  }
+
+  pub fn load<P: AsRef<Path>>(path: P) -> Result<ImageTexture, ImageError> {
+    Ok(ImageTexture::new(Image::load(path)?))
+  }
+
+  // Load an image holding data rather than colors, such as a normal map, without decoding sRGB
+  pub fn load_raw<P: AsRef<Path>>(path: P) -> Result<ImageTexture, ImageError> {
+    Ok(ImageTexture::new(Image::load_raw(path)?))
+  }
+}
+
+impl Texture for ImageTexture {
+  fn value(&self, u: f64, v: f64, _: &Point3) -> Color {
+    // Return solid cyan as a debugging aid if there is no texture data
+    if self.image.height == 0 || self.image.width == 0 {
+      return Color::new(0.0, 1.0, 1.0);
+    }
+
+    // Clamp input texture coordinates to [0,1] x [1,0], flipping V to image coordinates
+    let unit = Interval::new_from_range(0.0, 1.0);
+    let u = unit.clamp(u);
+    let v = 1.0 - unit.clamp(v);
+
+    let i = ((u * self.image.width as f64) as usize).min(self.image.width - 1);
+    let j = ((v * self.image.height as f64) as usize).min(self.image.height - 1);
+    self.image.pixel(i, j)
+  }
+}
+
+pub enum NoiseStyle {
+  // Smooth gradient noise
+  Noise,
+  // Several octaves of noise summed together
+  Turbulence,
+  // Sine stripes along z, phase-shifted by turbulence
+  Marble,
+}
+
+pub struct NoiseTexture {
+  noise: Perlin,
+  scale: f64,
+  style: NoiseStyle,
+}
+
+impl NoiseTexture {
+  pub fn new(scale: f64, style: NoiseStyle) -> NoiseTexture {
+    NoiseTexture {
+      noise: Perlin::new(),
+      scale,
+      style,
+    }
+  }
+}
+
+impl Texture for NoiseTexture {
+  fn value(&self, _: f64, _: f64, p: &Point3) -> Color {
+    let white = Color::new(1.0, 1.0, 1.0);
+    let scaled = *p * self.scale;
+    match self.style {
+      NoiseStyle::Noise => white * 0.5 * (1.0 + self.noise.noise(&scaled)),
+      NoiseStyle::Turbulence => white * self.noise.turbulence(&scaled, 7),
+      NoiseStyle::Marble => white * 0.5 * (1.0 + (scaled.z + 10.0 * self.noise.turbulence(p, 7)).sin()),
+    }
+  }
+}
\ No newline at end of file
@@ -0,0 +1,212 @@
+use crate::{color::Color, interval::Interval};
+
+// Compresses linear scene radiance into the [0,1] range of a display
+pub trait ToneMapper: Send + Sync {
+  fn map(&self, color: Color) -> Color;
+}
+
+// Leaves colors untouched, so everything above 1 is clipped when quantized
+pub struct Linear;
+
+impl ToneMapper for Linear {
+  fn map(&self, color: Color) -> Color {
+    color
+  }
+}
+
+// Maps luminance L to L / (1 + L), preserving the hue of the color
+pub struct Reinhard;
+
+impl ToneMapper for Reinhard {
+  fn map(&self, color: Color) -> Color {
+    let l = color.luminance();
+    scale_luminance(color, l / (1.0 + l))
+  }
+}
+
+// Reinhard with a white point, the smallest luminance that is mapped to pure white
+pub struct ReinhardExtended {
+  white_point: f64,
+}
+
+impl ReinhardExtended {
+  pub fn new(white_point: f64) -> ReinhardExtended {
+    ReinhardExtended {
+      white_point
+    }
+  }
+}
+
+impl ToneMapper for ReinhardExtended {
+  fn map(&self, color: Color) -> Color {
+    let l = color.luminance();
+    let white_sq = self.white_point * self.white_point;
+    scale_luminance(color, l * (1.0 + l / white_sq) / (1.0 + l))
+  }
+}
+
+// John Hable's filmic curve from Uncharted 2
+pub struct Hable;
+
+impl Hable {
+  const EXPOSURE_BIAS: f64 = 2.0;
+  const WHITE_POINT: f64 = 11.2;
+
+  fn partial(x: f64) -> f64 {
+    let (a, b, c, d, e, f) = (0.15, 0.50, 0.10, 0.20, 0.02, 0.30);
+    ((x * (a * x + c * b) + d * e) / (x * (a * x + b) + d * f)) - e / f
+  }
+}
+
+impl ToneMapper for Hable {
+  fn map(&self, color: Color) -> Color {
+    let white_scale = 1.0 / Hable::partial(Hable::WHITE_POINT);
+    let curve = |x: f64| Hable::partial(x.max(0.0) * Hable::EXPOSURE_BIAS) * white_scale;
+    Color::new(curve(color.x), curve(color.y), curve(color.z))
+  }
+}
+
+// Stephen Hill's fit of the ACES reference rendering and sRGB output transforms
+pub struct Aces;
+
+impl Aces {
+  // sRGB => XYZ => D65_2_D60 => AP1 => RRT_SAT
+  const INPUT: [[f64; 3]; 3] = [
+    [0.59719, 0.35458, 0.04823],
+    [0.07600, 0.90834, 0.01566],
+    [0.02840, 0.13383, 0.83777],
+  ];
+
+  // ODT_SAT => XYZ => D60_2_D65 => sRGB
+  const OUTPUT: [[f64; 3]; 3] = [
+    [1.60475, -0.53108, -0.07367],
+    [-0.10208, 1.10813, -0.00605],
+    [-0.00327, -0.07276, 1.07602],
+  ];
+
+  // Clamped at 0, as the fit dips slightly below it near black
+  fn rrt_and_odt_fit(v: f64) -> f64 {
+    let a = v * (v + 0.0245786) - 0.000090537;
+    let b = v * (0.983729 * v + 0.4329510) + 0.238081;
+    (a / b).max(0.0)
+  }
+}
+
+impl ToneMapper for Aces {
+  fn map(&self, color: Color) -> Color {
+    let c = multiply(&Aces::INPUT, color);
+    let c = Color::new(Aces::rrt_and_odt_fit(c.x), Aces::rrt_and_odt_fit(c.y), Aces::rrt_and_odt_fit(c.z));
+    multiply(&Aces::OUTPUT, c)
+  }
+}
+
+// Turns linear radiance into display values: exposure, then tone mapping, then the sRGB OETF
+pub struct DisplayTransform {
+  // Exposure adjustment in stops, every step doubles the brightness
+  pub exposure: f64,
+  pub tone_mapper: Box<dyn ToneMapper>,
+}
+
+impl DisplayTransform {
+  pub fn new(exposure: f64, tone_mapper: Box<dyn ToneMapper>) -> DisplayTransform {
+    DisplayTransform {
+      exposure,
+      tone_mapper,
+    }
+  }
+
+  pub fn apply(&self, color: Color) -> Color {
+    let exposed = color * 2f64.powf(self.exposure);
+    let mapped = self.tone_mapper.map(exposed);
+
+    let unit = Interval::new_from_range(0.0, 1.0);
+    Color::new(unit.clamp(mapped.x), unit.clamp(mapped.y), unit.clamp(mapped.z)).linear_to_srgb()
+  }
+}
+
+impl Default for DisplayTransform {
+  fn default() -> Self {
+    DisplayTransform::new(0.0, Box::new(Linear))
+  }
+}
+
+fn scale_luminance(color: Color, new_luminance: f64) -> Color {
+  let l = color.luminance();
+  if l <= 0.0 {
+    return Color::new(0.0, 0.0, 0.0);
+  }
+  color * (new_luminance / l)
+}
+
+fn multiply(m: &[[f64; 3]; 3], c: Color) -> Color {
+  Color::new(
+    m[0][0] * c.x + m[0][1] * c.y + m[0][2] * c.z,
+    m[1][0] * c.x + m[1][1] * c.y + m[1][2] * c.z,
+    m[2][0] * c.x + m[2][1] * c.y + m[2][2] * c.z,
+  )
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn grey(value: f64) -> Color {
+    Color::new(value, value, value)
+  }
+
+  #[test]
+  fn tone_mappers_keep_black_and_the_order_of_values() {
+    let mappers: [(&str, Box<dyn ToneMapper>); 5] = [
+      ("linear", Box::new(Linear)),
+      ("reinhard", Box::new(Reinhard)),
+      ("reinhard extended", Box::new(ReinhardExtended::new(4.0))),
+      ("hable", Box::new(Hable)),
+      ("aces", Box::new(Aces)),
+    ];
+    for (name, mapper) in mappers {
+      let black = mapper.map(grey(0.0));
+      assert!(black.x.abs() < 1e-12 && black.y.abs() < 1e-12 && black.z.abs() < 1e-12,
+        "{} maps black to {} {} {}", name, black.x, black.y, black.z);
+
+      let mut previous = black;
+      for i in 1..=2000 {
+        let mapped = mapper.map(grey(i as f64 * 0.01));
+        assert!(mapped.x >= previous.x && mapped.y >= previous.y && mapped.z >= previous.z,
+          "{} decreases at {}", name, i as f64 * 0.01);
+        previous = mapped;
+      }
+    }
+  }
+
+  #[test]
+  fn white_points_map_to_white() {
+    let white = ReinhardExtended::new(4.0).map(grey(4.0));
+    assert!((white.luminance() - 1.0).abs() < 1e-12, "luminance {}", white.luminance());
+
+    let white = Hable.map(grey(Hable::WHITE_POINT / Hable::EXPOSURE_BIAS));
+    for value in [white.x, white.y, white.z] {
+      assert!((value - 1.0).abs() < 1e-12, "value {}", value);
+    }
+  }
+
+  #[test]
+  fn srgb_encoding_round_trips() {
+    for i in 0..=1000 {
+      let value = i as f64 / 1000.0;
+      let decoded = grey(value).linear_to_srgb().srgb_to_linear();
+      assert!((decoded.x - value).abs() < 1e-12, "{} decodes to {}", value, decoded.x);
+      let encoded = grey(value).srgb_to_linear().linear_to_srgb();
+      assert!((encoded.x - value).abs() < 1e-12, "{} encodes to {}", value, encoded.x);
+    }
+  }
+
+  #[test]
+  fn srgb_encoding_is_continuous_at_the_knee() {
+    let knee = 0.0031308;
+    let (below, above) = (grey(knee).linear_to_srgb().x, grey(knee + 1e-12).linear_to_srgb().x);
+    assert!((above - below).abs() < 1e-7, "encoding jumps from {} to {}", below, above);
+
+    let (below, above) = (grey(below).srgb_to_linear().x, grey(below + 1e-12).srgb_to_linear().x);
+    assert!((above - below).abs() < 1e-8, "decoding jumps from {} to {}", below, above);
+  }
+}
\ No newline at end of file
@@ -0,0 +1,273 @@
+use std::ops::Mul;
+
+use crate::{aabb::Aabb, common::Common, vec3::{Point3, Vec3}};
+
+// Affine transform as a 4x4 matrix acting on column vectors, stored together with its inverse
+#[derive(Copy, Clone)]
+pub struct Transform {
+  matrix: [[f64; 4]; 4],
+  inverse: [[f64; 4]; 4],
+}
+
+const IDENTITY: [[f64; 4]; 4] = [
+  [1.0, 0.0, 0.0, 0.0],
+  [0.0, 1.0, 0.0, 0.0],
+  [0.0, 0.0, 1.0, 0.0],
+  [0.0, 0.0, 0.0, 1.0],
+];
+
+impl Transform {
+  pub const IDENTITY: Transform = Transform {
+    matrix: IDENTITY,
+    inverse: IDENTITY,
+  };
+
+  // Wrap a matrix, returning None if it is singular
+  pub fn new(matrix: [[f64; 4]; 4]) -> Option<Transform> {
+    let inverse = Transform::invert(&matrix)?;
+    Some(Transform { matrix, inverse })
+  }
+
+  pub fn translation(offset: &Vec3) -> Transform {
+    let mut matrix = IDENTITY;
+    let mut inverse = IDENTITY;
+    for (axis, value) in [offset.x, offset.y, offset.z].into_iter().enumerate() {
+      matrix[axis][3] = value;
+      inverse[axis][3] = -value;
+    }
+    Transform { matrix, inverse }
+  }
+
+  // Scale by a factor along each axis, none of which may be zero
+  pub fn scaling(factors: &Vec3) -> Transform {
+    let mut matrix = IDENTITY;
+    let mut inverse = IDENTITY;
+    for (axis, value) in [factors.x, factors.y, factors.z].into_iter().enumerate() {
+      matrix[axis][axis] = value;
+      inverse[axis][axis] = 1.0 / value;
+    }
+    Transform { matrix, inverse }
+  }
+
+  // Rotate counterclockwise by an angle in degrees around an axis through the origin
+  pub fn rotation(axis: &Vec3, degrees: f64) -> Transform {
+    let a = axis.unit_vector();
+    let radians = Common::degrees_to_radians(degrees);
+    let (sin, cos) = radians.sin_cos();
+    let t = 1.0 - cos;
+
+    let matrix = [
+      [t * a.x * a.x + cos, t * a.x * a.y - sin * a.z, t * a.x * a.z + sin * a.y, 0.0],
+      [t * a.x * a.y + sin * a.z, t * a.y * a.y + cos, t * a.y * a.z - sin * a.x, 0.0],
+      [t * a.x * a.z - sin * a.y, t * a.y * a.z + sin * a.x, t * a.z * a.z + cos, 0.0],
+      [0.0, 0.0, 0.0, 1.0],
+    ];
+    // The inverse of a rotation is its transpose
+    Transform { matrix, inverse: Transform::transpose(&matrix) }
+  }
+
+  pub fn matrix(&self) -> &[[f64; 4]; 4] {
+    &self.matrix
+  }
+
+  pub fn inverse(&self) -> Transform {
+    Transform { matrix: self.inverse, inverse: self.matrix }
+  }
+
+  // Inverse transpose of the upper 3x3 part, which keeps normals perpendicular to transformed surfaces
+  pub fn normal_matrix(&self) -> [[f64; 3]; 3] {
+    let m = &self.inverse;
+    [
+      [m[0][0], m[1][0], m[2][0]],
+      [m[0][1], m[1][1], m[2][1]],
+      [m[0][2], m[1][2], m[2][2]],
+    ]
+  }
+
+  pub fn point(&self, p: &Point3) -> Point3 {
+    let m = &self.matrix;
+    let x = m[0][0] * p.x + m[0][1] * p.y + m[0][2] * p.z + m[0][3];
+    let y = m[1][0] * p.x + m[1][1] * p.y + m[1][2] * p.z + m[1][3];
+    let z = m[2][0] * p.x + m[2][1] * p.y + m[2][2] * p.z + m[2][3];
+    let w = m[3][0] * p.x + m[3][1] * p.y + m[3][2] * p.z + m[3][3];
+    if w == 1.0 { Point3::new(x, y, z) } else { Point3::new(x / w, y / w, z / w) }
+  }
+
+  pub fn vector(&self, v: &Vec3) -> Vec3 {
+    let m = &self.matrix;
+    Vec3::new(
+      m[0][0] * v.x + m[0][1] * v.y + m[0][2] * v.z,
+      m[1][0] * v.x + m[1][1] * v.y + m[1][2] * v.z,
+      m[2][0] * v.x + m[2][1] * v.y + m[2][2] * v.z,
+    )
+  }
+
+  // Transform a surface normal, the result is not normalized
+  pub fn normal(&self, n: &Vec3) -> Vec3 {
+    let m = self.normal_matrix();
+    Vec3::new(
+      m[0][0] * n.x + m[0][1] * n.y + m[0][2] * n.z,
+      m[1][0] * n.x + m[1][1] * n.y + m[1][2] * n.z,
+      m[2][0] * n.x + m[2][1] * n.y + m[2][2] * n.z,
+    )
+  }
+
+  // Box enclosing the eight transformed corners of bbox
+  pub fn bounding_box(&self, bbox: &Aabb) -> Aabb {
+    let mut result = Aabb::EMPTY;
+    for i in 0..8 {
+      let corner = Point3::new(
+        if i & 1 == 0 { bbox.x.min } else { bbox.x.max },
+        if i & 2 == 0 { bbox.y.min } else { bbox.y.max },
+        if i & 4 == 0 { bbox.z.min } else { bbox.z.max },
+      );
+      let p = self.point(&corner);
+      result = Aabb::new_from_boxes(&result, &Aabb::new_from_points(&p, &p));
+    }
+    result
+  }
+
+  // Whether the transform only rotates, translates and scales uniformly, which preserves angles
+  // and therefore the solid angles that light sampling densities are measured in
+  pub fn is_similarity(&self) -> bool {
+    let columns = [0, 1, 2].map(|j| Vec3::new(self.matrix[0][j], self.matrix[1][j], self.matrix[2][j]));
+    let scale = columns[0].length_squared();
+    let tolerance = 1e-9 * scale;
+    self.matrix[3] == IDENTITY[3]
+      && (columns[1].length_squared() - scale).abs() <= tolerance
+      && (columns[2].length_squared() - scale).abs() <= tolerance
+      && columns[0].dot(&columns[1]).abs() <= tolerance
+      && columns[1].dot(&columns[2]).abs() <= tolerance
+      && columns[2].dot(&columns[0]).abs() <= tolerance
+  }
+
+  fn transpose(m: &[[f64; 4]; 4]) -> [[f64; 4]; 4] {
+    let mut t = [[0.0; 4]; 4];
+    for (i, row) in m.iter().enumerate() {
+      for (j, value) in row.iter().enumerate() {
+        t[j][i] = *value;
+      }
+    }
+    t
+  }
+
+  fn multiply(a: &[[f64; 4]; 4], b: &[[f64; 4]; 4]) -> [[f64; 4]; 4] {
+    let mut m = [[0.0; 4]; 4];
+    for (i, row) in m.iter_mut().enumerate() {
+      for (j, value) in row.iter_mut().enumerate() {
+        *value = (0..4).map(|k| a[i][k] * b[k][j]).sum();
+      }
+    }
+    m
+  }
+
+  // Gauss-Jordan elimination with partial pivoting
+  fn invert(m: &[[f64; 4]; 4]) -> Option<[[f64; 4]; 4]> {
+    let mut a = *m;
+    let mut inverse = IDENTITY;
+
+    for column in 0..4 {
+      let pivot = (column..4).max_by(|&i, &j| a[i][column].abs().total_cmp(&a[j][column].abs())).unwrap();
+      if a[pivot][column].abs() < 1e-12 {
+        return None;
+      }
+      a.swap(column, pivot);
+      inverse.swap(column, pivot);
+
+      let scale = 1.0 / a[column][column];
+      for j in 0..4 {
+        a[column][j] *= scale;
+        inverse[column][j] *= scale;
+      }
+
+      for row in 0..4 {
+        let factor = a[row][column];
+        if row == column || factor == 0.0 {
+          continue;
+        }
+        for j in 0..4 {
+          a[row][j] -= factor * a[column][j];
+          inverse[row][j] -= factor * inverse[column][j];
+        }
+      }
+    }
+    Some(inverse)
+  }
+}
+
+impl Default for Transform {
+  fn default() -> Self {
+    Transform::IDENTITY
+  }
+}
+
+// Composition, where (a * b) applies b first and then a
+impl Mul for Transform {
+  type Output = Transform;
+
+  fn mul(self, other: Transform) -> Self::Output {
+    Transform {
+      matrix: Transform::multiply(&self.matrix, &other.matrix),
+      inverse: Transform::multiply(&other.inverse, &self.inverse),
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn assert_identity(m: &[[f64; 4]; 4]) {
+    for (i, row) in m.iter().enumerate() {
+      for (j, value) in row.iter().enumerate() {
+        assert!((value - IDENTITY[i][j]).abs() < 1e-12, "entry ({}, {}) is {}", i, j, value);
+      }
+    }
+  }
+
+  #[test]
+  fn inverse_times_matrix_is_identity() {
+    let composed = Transform::translation(&Vec3::new(1.0, -2.0, 3.0))
+      * Transform::rotation(&Vec3::new(1.0, 2.0, -0.5), 37.0)
+      * Transform::scaling(&Vec3::new(2.0, 0.5, -3.0));
+    let general = Transform::new([
+      [2.0, 1.0, 0.0, 4.0],
+      [0.5, 3.0, -1.0, 0.0],
+      [0.0, 2.0, 1.0, -2.0],
+      [0.1, 0.0, 0.2, 1.0],
+    ]).unwrap();
+
+    for transform in [composed, general] {
+      assert_identity(&Transform::multiply(&transform.inverse().matrix, &transform.matrix));
+      assert_identity(&Transform::multiply(&transform.matrix, &transform.inverse().matrix));
+      // The inverse computed by elimination matches the one built up by composition
+      let inverse = Transform::invert(&transform.matrix).unwrap();
+      assert_identity(&Transform::multiply(&inverse, &transform.matrix));
+    }
+  }
+
+  #[test]
+  fn inverse_undoes_points_vectors_and_normals() {
+    let transform = Transform::rotation(&Vec3::new(0.0, 1.0, 1.0), 120.0) * Transform::scaling(&Vec3::new(1.0, 4.0, 0.25));
+    let p = Point3::new(0.3, -1.2, 2.5);
+    let back = transform.inverse().point(&transform.point(&p));
+    assert!((back - p).length() < 1e-12);
+    let v = Vec3::new(-2.0, 0.5, 1.0);
+    let back = transform.inverse().vector(&transform.vector(&v));
+    assert!((back - v).length() < 1e-12);
+
+    // Normals stay perpendicular to the transformed tangents
+    let (n, tangent) = (Vec3::new(0.0, 0.0, 1.0), Vec3::new(1.0, 1.0, 0.0));
+    assert!(transform.normal(&n).dot(&transform.vector(&tangent)).abs() < 1e-12);
+  }
+
+  #[test]
+  fn classifies_singular_and_similarity_transforms() {
+    let mut matrix = IDENTITY;
+    matrix[2] = [1.0, 2.0, 0.0, 0.0];
+    matrix[1] = [2.0, 4.0, 0.0, 0.0];
+    assert!(Transform::new(matrix).is_none());
+    assert!(Transform::rotation(&Vec3::new(0.0, 0.0, 1.0), 45.0).is_similarity());
+    assert!(!Transform::scaling(&Vec3::new(1.0, 2.0, 1.0)).is_similarity());
+  }
+}
\ No newline at end of file
@@ -0,0 +1,179 @@
+use std::sync::Arc;
+
+use crate::{aabb::Aabb, common::Common, hittable::{HitRecord, Hittable}, interval::Interval, material::Material, mesh::MeshData, onb::Onb, ray::Ray, sampler::PixelSampler, vec3::{Point3, Vec3}};
+
+#[derive(Clone)]
+pub struct Triangle {
+  corners: Corners,
+}
+
+// A standalone triangle keeps its vertices inline, while those of a mesh share its vertex buffers
+#[derive(Clone)]
+enum Corners {
+  Inline { positions: [Point3; 3], material: Arc<dyn Material> },
+  Mesh { mesh: Arc<MeshData>, face: usize },
+}
+
+impl Triangle {
+  pub fn new(a: Point3, b: Point3, c: Point3, material: Arc<dyn Material>) -> Triangle {
+    Triangle {
+      corners: Corners::Inline { positions: [a, b, c], material },
+    }
+  }
+
+  pub fn new_from_mesh(mesh: Arc<MeshData>, face: usize) -> Triangle {
+    Triangle {
+      corners: Corners::Mesh { mesh, face },
+    }
+  }
+
+  // Solve for the derivatives of position with respect to the texture coordinates, which are constant
+  // across the triangle. Degenerate texture coordinates get an arbitrary frame around the normal
+  fn tangents(uvs: [(f64, f64); 3], [p0, p1, p2]: [Point3; 3], normal: &Vec3) -> (Vec3, Vec3) {
+    let (du02, dv02) = (uvs[0].0 - uvs[2].0, uvs[0].1 - uvs[2].1);
+    let (du12, dv12) = (uvs[1].0 - uvs[2].0, uvs[1].1 - uvs[2].1);
+    let (dp02, dp12) = (p0 - p2, p1 - p2);
+
+    let determinant = du02 * dv12 - dv02 * du12;
+    if determinant.abs() < 1e-12 {
+      let uvw = Onb::new(normal);
+      return (uvw.u, uvw.v);
+    }
+    let inv_det = 1.0 / determinant;
+    ((dp02 * dv12 - dp12 * dv02) * inv_det, (dp12 * du02 - dp02 * du12) * inv_det)
+  }
+
+  fn vertices(&self) -> [Point3; 3] {
+    match &self.corners {
+      Corners::Inline { positions, .. } => *positions,
+      Corners::Mesh { mesh, face } => mesh.faces[*face].positions.map(|i| mesh.positions[i]),
+    }
+  }
+
+  // Normals and texture coordinates at the corners, which only faces of a mesh can have
+  fn normals(&self) -> Option<[Vec3; 3]> {
+    match &self.corners {
+      Corners::Inline { .. } => None,
+      Corners::Mesh { mesh, face } => mesh.faces[*face].normals.map(|n| n.map(|i| mesh.normals[i])),
+    }
+  }
+
+  fn uvs(&self) -> Option<[(f64, f64); 3]> {
+    match &self.corners {
+      Corners::Inline { .. } => None,
+      Corners::Mesh { mesh, face } => mesh.faces[*face].uvs.map(|t| t.map(|i| mesh.uvs[i])),
+    }
+  }
+
+  fn material(&self) -> &dyn Material {
+    match &self.corners {
+      Corners::Inline { material, .. } => material.as_ref(),
+      Corners::Mesh { mesh, .. } => mesh.material.as_ref(),
+    }
+  }
+}
+
+impl Hittable for Triangle {
+  // Möller–Trumbore ray-triangle intersection
+  fn hit(&self, ray: &Ray, ray_t: &Interval) -> (bool, Option<HitRecord<'_>>) {
+    let [p0, p1, p2] = self.vertices();
+    let edge1 = p1 - p0;
+    let edge2 = p2 - p0;
+
+    let pvec = ray.direction.cross(&edge2);
+    let det = edge1.dot(&pvec);
+    if det.abs() < 1e-12 {
+      return (false, None);
+    }
+    let inv_det = 1.0 / det;
+
+    let tvec = ray.origin - p0;
+    let b1 = tvec.dot(&pvec) * inv_det;
+    if !(0.0..=1.0).contains(&b1) {
+      return (false, None);
+    }
+
+    let qvec = tvec.cross(&edge1);
+    let b2 = ray.direction.dot(&qvec) * inv_det;
+    if b2 < 0.0 || b1 + b2 > 1.0 {
+      return (false, None);
+    }
+
+    let t = edge2.dot(&qvec) * inv_det;
+    if !ray_t.surrounds(t) {
+      return (false, None);
+    }
+    let b0 = 1.0 - b1 - b2;
+
+    // Vertex normals only shade the surface, with the geometric normal turned to agree with them
+    let geometric_normal = edge1.cross(&edge2).unit_vector();
+    let (outward_normal, shading_normal) = match self.normals() {
+      Some([n0, n1, n2]) => {
+        let shading_normal = (n0 * b0 + n1 * b1 + n2 * b2).unit_vector();
+        let outward_normal = if geometric_normal.dot(&shading_normal) < 0.0 { -geometric_normal } else { geometric_normal };
+        (outward_normal, shading_normal)
+      }
+      None => (geometric_normal, geometric_normal),
+    };
+
+    // Without texture coordinates, u and v are the barycentric coordinates of p1 and p2
+    let (u, v, dpdu, dpdv) = match self.uvs() {
+      Some([t0, t1, t2]) => {
+        let (dpdu, dpdv) = Triangle::tangents([t0, t1, t2], [p0, p1, p2], &geometric_normal);
+        (t0.0 * b0 + t1.0 * b1 + t2.0 * b2, t0.1 * b0 + t1.1 * b1 + t2.1 * b2, dpdu, dpdv)
+      }
+      None => (b1, b2, edge1, edge2),
+    };
+
+    let rec = HitRecord {
+      p: ray.at(t),
+      t,
+      u,
+      v,
+      material: self.material(),
+      normal: outward_normal,
+      geometric_normal: outward_normal,
+      dpdu,
+      dpdv,
+      front_face: false,
+    };
+    let rec = rec.set_face_normal(ray, outward_normal).set_shading_normal(shading_normal);
+
+    (true, Some(rec))
+  }
+
+  fn bounding_box(&self) -> Aabb {
+    let [p0, p1, p2] = self.vertices();
+    let bbox = Aabb::new_from_points(&p0, &p1);
+    Aabb::new_from_boxes(&bbox, &Aabb::new_from_points(&p2, &p2))
+  }
+
+  // Points are sampled uniformly over the area, converted to a density over solid angle
+  fn pdf_value(&self, origin: &Point3, direction: &Vec3, _time: f64) -> f64 {
+    let (hit, rec) = self.hit(&Ray::new(*origin, *direction), &Interval::new_from_range(0.001, Common::INFINITY));
+    if !hit {
+      return 0.0;
+    }
+
+    let [p0, p1, p2] = self.vertices();
+    let cross = (p1 - p0).cross(&(p2 - p0));
+    let area = 0.5 * cross.length();
+    let t = rec.unwrap().t;
+    let distance_squared = t * t * direction.length_squared();
+    let cosine = (direction.dot(&cross) / (direction.length() * cross.length())).abs();
+    if cosine < 1e-8 {
+      return 0.0;
+    }
+    distance_squared / (cosine * area)
+  }
+
+  fn random(&self, origin: &Point3, _time: f64, sampler: &mut PixelSampler) -> Vec3 {
+    let [p0, p1, p2] = self.vertices();
+    let (mut b1, mut b2) = sampler.get_2d();
+    if b1 + b2 > 1.0 {
+      b1 = 1.0 - b1;
+      b2 = 1.0 - b2;
+    }
+    p0 + (p1 - p0) * b1 + (p2 - p0) * b2 - origin
+  }
+}
\ No newline at end of file
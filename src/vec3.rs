@@ -40,7 +40,7 @@ impl Vec3 {
 
   pub fn near_zero(&self) -> bool {
     let s = 1e-8;
-    return self.x.abs() < s && self.y.abs() < s && self.z.abs() < s;
+    self.x.abs() < s && self.y.abs() < s && self.z.abs() < s
   }
 
   pub fn dot(&self, vec: &Vec3) -> f64 {
@@ -69,7 +69,7 @@ impl Vec3 {
   }
 
   pub fn random_unit_vector() -> Vec3 {
-    return Vec3::random_in_unit_sphere().unit_vector();
+    Vec3::random_in_unit_sphere().unit_vector()
   }
 
   pub fn random_on_hemisphere(normal: &Vec3) -> Vec3 {
@@ -77,20 +77,47 @@ impl Vec3 {
     if on_unit_sphere.dot(normal) > 0.0 {
       return on_unit_sphere;
     }
-    return -on_unit_sphere;
+    -on_unit_sphere
   }
 
-  pub fn random_in_unit_disk() -> Vec3 {
-    loop {
-      let p = Vec3::new(Common::random_float_in_range(-1.0, 1.0), Common::random_float_in_range(-1.0, 1.0), 0.0);
-      if p.length_squared() < 1.0 {
-        return p;
-      }
+  // Uniform direction for a pair of numbers in [0, 1), mapped to the height and the angle around Z
+  pub fn sample_unit_vector(u: (f64, f64)) -> Vec3 {
+    let z = 1.0 - 2.0 * u.0;
+    let r = (1.0 - z * z).max(0.0).sqrt();
+    let phi = 2.0 * Common::PI * u.1;
+    Vec3::new(r * phi.cos(), r * phi.sin(), z)
+  }
+
+  // Uniform point in the unit disk by Shirley and Chiu's concentric mapping of the square, which keeps
+  // stratified samples apart
+  pub fn sample_in_unit_disk(u: (f64, f64)) -> Vec3 {
+    let a = 2.0 * u.0 - 1.0;
+    let b = 2.0 * u.1 - 1.0;
+    if a == 0.0 && b == 0.0 {
+      return Vec3::new(0.0, 0.0, 0.0);
     }
+
+    let (r, theta) = if a.abs() > b.abs() {
+      (a, Common::PI / 4.0 * (b / a))
+    } else {
+      (b, Common::PI / 2.0 - Common::PI / 4.0 * (a / b))
+    };
+    Vec3::new(r * theta.cos(), r * theta.sin(), 0.0)
+  }
+
+  // Direction on the hemisphere around +Z, distributed by the cosine to the Z axis
+  pub fn sample_cosine_direction(u: (f64, f64)) -> Vec3 {
+    let (r1, r2) = u;
+
+    let phi = 2.0 * Common::PI * r1;
+    let x = phi.cos() * r2.sqrt();
+    let y = phi.sin() * r2.sqrt();
+    let z = (1.0 - r2).sqrt();
+    Vec3::new(x, y, z)
   }
 
   pub fn reflect(&self, n: &Vec3) -> Vec3 {
-    return self - &(n * self.dot(n) * 2.0);
+    self - &(n * self.dot(n) * 2.0)
   }
 
   pub fn refract(&self, n: &Vec3, etai_over_etat: f64) -> Vec3 {
@@ -98,15 +125,15 @@ impl Vec3 {
     let r_out_perp = (self + &(n * cos_theta)) * etai_over_etat;
     let inner = (1.0 - r_out_perp.length_squared()).abs();
     let r_out_parallel = n * (-(inner.sqrt()));
-    return r_out_perp + r_out_parallel;
+    r_out_perp + r_out_parallel
   }
 }
 
 
-impl Add for &Vec3 {
-  type Output = Vec3;
+impl Add for Vec3 {
+  type Output = Self;
 
-  fn add(self, other: Self) -> Self::Output {
+  fn add(self, other: Self) -> Self {
     Vec3 {
       x: self.x + other.x,
       y: self.y + other.y,
@@ -115,11 +142,11 @@ impl Add for &Vec3 {
   }
 }
 
-impl Add for Vec3 {
-  type Output = Self;
+impl Add for &Vec3 {
+  type Output = Vec3;
 
-  fn add(self, other: Self) -> Self {
-    &self + &other
+  fn add(self, other: Self) -> Self::Output {
+    *self + *other
   }
 }
 
@@ -127,11 +154,11 @@ impl Add<&Vec3> for Vec3 {
   type Output = Vec3;
 
   fn add(self, other: &Vec3) -> Self::Output {
-    &self + other
+    self + *other
   }
 }
 
-impl Sub for &Vec3 {
+impl Sub for Vec3 {
   type Output = Vec3;
 
   fn sub(self, other: Self) -> Self::Output {
@@ -143,11 +170,11 @@ impl Sub for &Vec3 {
   }
 }
 
-impl Sub for Vec3 {
+impl Sub for &Vec3 {
   type Output = Vec3;
 
   fn sub(self, other: Self) -> Self::Output {
-    &self - &other
+    *self - *other
   }
 }
 
@@ -155,7 +182,7 @@ impl Sub<&Vec3> for Vec3 {
   type Output = Vec3;
 
   fn sub(self, other: &Vec3) -> Self::Output {
-    &self - other
+    self - *other
   }
 }
 